@@ -0,0 +1,69 @@
+//! Generates the Unicode character tables the scalar scan classifies
+//! with, so the hot path reads a bitmask and a small range slice instead
+//! of calling into std, and so the Unicode data version the tables were
+//! built from is pinned here rather than implied by the toolchain.
+
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+/// The Unicode version the property data below was taken from.
+const UNICODE_VERSION: (u8, u8, u8) = (17, 0, 0);
+
+/// The `White_Space=Yes` code points, as inclusive ranges in code-point
+/// order — `PropList.txt` from the UCD, transcribed. The property has
+/// been stable for many Unicode versions; bump [`UNICODE_VERSION`] when
+/// re-checking it against a new release.
+const WHITE_SPACE: &[(u32, u32)] = &[
+    (0x0009, 0x000d),
+    (0x0020, 0x0020),
+    (0x0085, 0x0085),
+    (0x00a0, 0x00a0),
+    (0x1680, 0x1680),
+    (0x2000, 0x200a),
+    (0x2028, 0x2029),
+    (0x202f, 0x202f),
+    (0x205f, 0x205f),
+    (0x3000, 0x3000),
+];
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    let mut out = String::new();
+    let (major, minor, micro) = UNICODE_VERSION;
+    writeln!(
+        out,
+        "/// The Unicode version the generated tables were built from."
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "pub const UNICODE_VERSION: (u8, u8, u8) = ({major}, {minor}, {micro});"
+    )
+    .unwrap();
+
+    // One bit per ASCII code point, so the common case is a shift and a
+    // mask with no branches.
+    let mut ascii_mask = 0u128;
+    for &(lo, hi) in WHITE_SPACE {
+        for c in lo..=hi.min(0x7f) {
+            ascii_mask |= 1 << c;
+        }
+    }
+    writeln!(out, "const WHITE_SPACE_ASCII: u128 = {ascii_mask:#034x};").unwrap();
+
+    // The non-ASCII remainder, binary-searched. It is a handful of
+    // ranges, so a slice beats a multi-level table.
+    writeln!(out, "const WHITE_SPACE_RANGES: &[(u32, u32)] = &[").unwrap();
+    for &(lo, hi) in WHITE_SPACE {
+        if hi >= 0x80 {
+            writeln!(out, "    ({:#06x}, {:#06x}),", lo.max(0x80), hi).unwrap();
+        }
+    }
+    writeln!(out, "];").unwrap();
+
+    let dest = Path::new(&env::var("OUT_DIR").unwrap()).join("unicode_tables.rs");
+    fs::write(dest, out).unwrap();
+}
@@ -0,0 +1,66 @@
+//! Fuzz the chunk-merge model: counting chosen chunks independently and
+//! merging must match a whole-buffer count for every mergeable counter,
+//! on every backend and in both locale modes.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+
+use wc_rs::count::{count_chunk, count_slice, split_point, ChunkCounts, CountMode, Selection};
+use wc_rs::simd::CountingBackend;
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    backend: u8,
+    utf8: bool,
+    splits: Vec<u16>,
+    data: Vec<u8>,
+}
+
+const ALL: Selection = Selection {
+    lines: true,
+    words: true,
+    chars: true,
+    bytes: true,
+    max_line_length: true,
+    max_words_per_line: true,
+    min_words_per_line: true,
+    unique_words: true,
+    avg_word_length: true,
+    fold_case: false,
+    count_cr_lines: false,
+};
+
+fuzz_target!(|input: Input| {
+    let backends = CountingBackend::available();
+    let backend = backends[input.backend as usize % backends.len()];
+    let mode = if input.utf8 {
+        CountMode::Utf8
+    } else {
+        CountMode::Bytes
+    };
+    let mut splits: Vec<usize> = input.splits.iter().map(|&s| s as usize).collect();
+    splits.sort_unstable();
+
+    let mut acc = ChunkCounts::default();
+    let mut prev = 0;
+    for s in splits {
+        let at = split_point(&input.data, s.max(prev));
+        acc = acc.merge(count_chunk(&input.data[prev..at], ALL, mode, backend));
+        prev = at;
+    }
+    acc = acc.merge(count_chunk(&input.data[prev..], ALL, mode, backend));
+
+    let whole = count_slice(&input.data, ALL, mode, backend);
+    let mut merged = acc.finish();
+    // Max line length, the words-per-line extremes, and distinct words are
+    // not chunk-mergeable (tab stops depend on absolute columns, and a line
+    // or word can span a split); the parallel path never splits when they
+    // are selected.
+    merged.max_line_length = whole.max_line_length;
+    merged.max_words_per_line = whole.max_words_per_line;
+    merged.min_words_per_line = whole.min_words_per_line;
+    merged.unique_words = whole.unique_words;
+    assert_eq!(merged, whole);
+});
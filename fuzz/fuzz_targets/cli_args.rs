@@ -0,0 +1,29 @@
+//! Fuzz the clap parser and option-combination logic with arbitrary argv
+//! vectors: parsing must never panic, and accepted invocations must satisfy
+//! the invariants the rest of the binary relies on.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use wc_rs::cli::Cli;
+
+fuzz_target!(|argv: Vec<String>| {
+    let cli = match <Cli as clap::Parser>::try_parse_from(
+        std::iter::once("wc-rs".to_string()).chain(argv),
+    ) {
+        Ok(cli) => cli,
+        Err(_) => return,
+    };
+    // An accepted command line always selects at least one counter...
+    let sel = cli.selection();
+    assert!(!sel.is_empty());
+    // ...and never mixes --files0-from with file operands.
+    if cli.validate().is_ok() {
+        assert!(cli.files0_from.is_none() || cli.files.is_empty());
+    }
+    if let Some(threads) = cli.threads {
+        // Thread counts are plain values; the pool clamps them later.
+        let _ = threads;
+    }
+});
@@ -0,0 +1,49 @@
+//! Fuzz the counting kernels with structured input: the fuzzer picks the
+//! SIMD backend and locale mode explicitly, so AVX2-vs-scalar and
+//! single-byte-vs-UTF-8 combinations are explored intentionally.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+
+use wc_rs::count::{count_slice, CountMode, Selection};
+use wc_rs::simd::CountingBackend;
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    backend: u8,
+    utf8: bool,
+    data: Vec<u8>,
+}
+
+const ALL: Selection = Selection {
+    lines: true,
+    words: true,
+    chars: true,
+    bytes: true,
+    max_line_length: true,
+    max_words_per_line: true,
+    min_words_per_line: true,
+    unique_words: true,
+    avg_word_length: true,
+    fold_case: false,
+    count_cr_lines: false,
+};
+
+fuzz_target!(|input: Input| {
+    let backends = CountingBackend::available();
+    let backend = backends[input.backend as usize % backends.len()];
+    let mode = if input.utf8 {
+        CountMode::Utf8
+    } else {
+        CountMode::Bytes
+    };
+    let got = count_slice(&input.data, ALL, mode, backend);
+    let reference = count_slice(&input.data, ALL, mode, CountingBackend::Scalar);
+    assert_eq!(got, reference, "{backend:?} disagrees with scalar");
+    assert_eq!(got.bytes, input.data.len() as u64);
+    if mode == CountMode::Bytes {
+        assert_eq!(got.chars, got.bytes);
+    }
+});
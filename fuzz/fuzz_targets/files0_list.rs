@@ -0,0 +1,33 @@
+//! Fuzz the `--files0-from` list parser: NUL splitting, empty names,
+//! non-UTF-8 names, and oversized entries must never panic, and accepted
+//! lists must round-trip.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use wc_rs::files0;
+
+fuzz_target!(|data: &[u8]| {
+    match files0::parse_list(data) {
+        Ok(names) => {
+            // No entry is empty or contains a separator.
+            for name in &names {
+                assert!(!name.is_empty());
+                assert!(!name.contains(&0));
+            }
+            // Entries joined by NUL reproduce the input (modulo the
+            // optional trailing NUL).
+            let joined = names.join(&0u8);
+            assert!(data == joined.as_slice() || {
+                let mut with_nul = joined;
+                with_nul.push(0);
+                data == with_nul.as_slice()
+            });
+        }
+        Err(files0::Files0Error::EmptyName { index }) => {
+            // The reported entry index is within the list.
+            assert!(index <= data.iter().filter(|&&b| b == 0).count());
+        }
+    }
+});
@@ -0,0 +1,51 @@
+//! Fuzz the streaming scanner: feeding a buffer in arbitrary-sized pieces
+//! must match the whole-buffer count exactly (including max line length),
+//! on every backend and in both locale modes.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+
+use wc_rs::count::{count_slice, CountMode, Selection, StreamCounter};
+use wc_rs::simd::CountingBackend;
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    backend: u8,
+    utf8: bool,
+    step: u8,
+    data: Vec<u8>,
+}
+
+const ALL: Selection = Selection {
+    lines: true,
+    words: true,
+    chars: true,
+    bytes: true,
+    max_line_length: true,
+    max_words_per_line: true,
+    min_words_per_line: true,
+    unique_words: true,
+    avg_word_length: true,
+    fold_case: false,
+    count_cr_lines: false,
+};
+
+fuzz_target!(|input: Input| {
+    let backends = CountingBackend::available();
+    let backend = backends[input.backend as usize % backends.len()];
+    let mode = if input.utf8 {
+        CountMode::Utf8
+    } else {
+        CountMode::Bytes
+    };
+    let mut counter = StreamCounter::new(ALL, mode, backend);
+    for piece in input.data.chunks(usize::from(input.step).max(1)) {
+        counter.update(piece);
+    }
+    assert_eq!(
+        counter.finish(),
+        count_slice(&input.data, ALL, mode, backend)
+    );
+});
@@ -0,0 +1,498 @@
+//! Fallible, embedder-facing counting entry points.
+//!
+//! The kernels in [`crate::count`] are infallible because they work on
+//! slices the caller already owns. Embedders usually start from a reader or
+//! a path instead; [`try_count_reader`] and [`try_count_path`] do the I/O
+//! and fold everything that can stop a count into one [`CountError`], so
+//! callers match on a single error type instead of wrapping each layer
+//! themselves.
+
+use std::io::Read;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::count::{CountMode, Counts, Selection, StreamCounter};
+use crate::simd::CountingBackend;
+
+/// Read buffer size, matching the binary's streaming reads.
+const BUF_SIZE: usize = 256 * 1024;
+
+/// Everything that can stop a count.
+#[derive(Debug)]
+pub enum CountError {
+    /// The underlying reader or file failed.
+    Io(std::io::Error),
+    /// The requested backend cannot run on this CPU.
+    UnsupportedBackend(CountingBackend),
+    /// The caller's cancellation flag was set.
+    Cancelled,
+    /// The input exceeded the caller's byte limit.
+    LimitExceeded {
+        /// The limit that was crossed.
+        limit: u64,
+    },
+}
+
+impl std::fmt::Display for CountError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CountError::Io(err) => err.fmt(f),
+            CountError::UnsupportedBackend(backend) => {
+                write!(f, "backend {backend:?} is not available on this CPU")
+            }
+            CountError::Cancelled => write!(f, "counting was cancelled"),
+            CountError::LimitExceeded { limit } => {
+                write!(f, "input exceeds the {limit}-byte limit")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CountError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CountError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for CountError {
+    fn from(err: std::io::Error) -> CountError {
+        CountError::Io(err)
+    }
+}
+
+/// Limits and hooks applied while counting. The default imposes nothing:
+/// the whole input is counted with the detected backend.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CountLimits<'a> {
+    /// Fail with [`CountError::LimitExceeded`] once the input grows past
+    /// this many bytes, for callers that refuse oversized input outright
+    /// (the binary's `--max-bytes` truncates instead).
+    pub max_bytes: Option<u64>,
+    /// Checked between read buffers; set it from another thread to stop
+    /// with [`CountError::Cancelled`].
+    pub cancel: Option<&'a AtomicBool>,
+    /// Count with this backend instead of the detected one.
+    pub backend: Option<CountingBackend>,
+}
+
+impl CountLimits<'_> {
+    /// The backend to count with, or the error an impossible request gets.
+    fn resolve_backend(&self) -> Result<CountingBackend, CountError> {
+        match self.backend {
+            None => Ok(CountingBackend::detect_cached()),
+            Some(backend) if CountingBackend::available().contains(&backend) => Ok(backend),
+            Some(backend) => Err(CountError::UnsupportedBackend(backend)),
+        }
+    }
+}
+
+/// Count a reader's contents, applying `limits`.
+pub fn try_count_reader<R: Read>(
+    mut reader: R,
+    sel: Selection,
+    mode: CountMode,
+    limits: CountLimits<'_>,
+) -> Result<Counts, CountError> {
+    let backend = limits.resolve_backend()?;
+    let mut counter = StreamCounter::new(sel, mode, backend);
+    let mut buf = vec![0u8; BUF_SIZE];
+    let mut seen = 0u64;
+    loop {
+        if limits
+            .cancel
+            .is_some_and(|flag| flag.load(Ordering::Relaxed))
+        {
+            return Err(CountError::Cancelled);
+        }
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            return Ok(counter.finish());
+        }
+        seen += n as u64;
+        if let Some(limit) = limits.max_bytes {
+            if seen > limit {
+                return Err(CountError::LimitExceeded { limit });
+            }
+        }
+        counter.update(&buf[..n]);
+    }
+}
+
+/// Options for the multi-file entry points [`count_path`] and
+/// [`count_files`].
+#[derive(Debug, Clone, Copy)]
+pub struct CountOptions<'a> {
+    pub sel: Selection,
+    pub mode: CountMode,
+    /// Count several files concurrently. Needs the `parallel` feature;
+    /// without it files are counted sequentially. The work runs on
+    /// [`CountOptions::pool`] when one is given and otherwise on a
+    /// private pool built for the call — never on the global rayon pool,
+    /// which belongs to the embedder.
+    pub parallel: bool,
+    /// Run parallel work inside this pool instead of building a private
+    /// one per call, for embedders that already size and pin their own.
+    #[cfg(feature = "parallel")]
+    pub pool: Option<&'a rayon::ThreadPool>,
+    /// Limits applied to each file individually.
+    pub limits: CountLimits<'a>,
+}
+
+impl CountOptions<'_> {
+    /// Count everything, one file at a time, with no limits.
+    pub fn new(sel: Selection, mode: CountMode) -> Self {
+        CountOptions {
+            sel,
+            mode,
+            parallel: false,
+            #[cfg(feature = "parallel")]
+            pool: None,
+            limits: CountLimits::default(),
+        }
+    }
+}
+
+/// The outcome of a multi-file run: each file's result in input order, and
+/// the total over the files that counted successfully — the same sum the
+/// binary prints on its totals row.
+#[derive(Debug, Default)]
+pub struct FileTotals {
+    pub results: Vec<Result<Counts, CountError>>,
+    pub total: Counts,
+}
+
+/// Count one file the way the binary does: regular files are mapped and
+/// counted in place, everything else streams through [`try_count_reader`].
+pub fn count_path(path: &Path, opts: &CountOptions<'_>) -> Result<Counts, CountError> {
+    let backend = opts.limits.resolve_backend()?;
+    if opts
+        .limits
+        .cancel
+        .is_some_and(|flag| flag.load(Ordering::Relaxed))
+    {
+        return Err(CountError::Cancelled);
+    }
+    let file = std::fs::File::open(path)?;
+    let meta = file.metadata()?;
+    if meta.is_file() && meta.len() > 0 {
+        if let Some(limit) = opts.limits.max_bytes {
+            if meta.len() > limit {
+                return Err(CountError::LimitExceeded { limit });
+            }
+        }
+        // SAFETY: the map is read-only and dropped before return;
+        // concurrent truncation is the usual mmap caveat. Filesystems that
+        // refuse to map fall back to buffered reads below.
+        if let Ok(map) = unsafe { memmap2::Mmap::map(&file) } {
+            advise_mapped_input(&map);
+            return Ok(crate::count::count_slice(
+                &map, opts.sel, opts.mode, backend,
+            ));
+        }
+    }
+    try_count_reader(file, opts.sel, opts.mode, opts.limits)
+}
+
+/// Maps below this stay on regular pages: a huge page spans 2 MiB, so
+/// advising smaller maps buys nothing.
+#[cfg(target_os = "linux")]
+const HUGEPAGE_ADVICE_BYTES: usize = 2 * 1024 * 1024;
+
+/// Best-effort kernel advice for a freshly mapped input: the scan reads
+/// sequentially, and large maps are worth backing with huge pages to cut
+/// TLB pressure. Failures are ignored — advice never affects correctness.
+pub fn advise_mapped_input(map: &memmap2::Mmap) {
+    #[cfg(unix)]
+    {
+        let _ = map.advise(memmap2::Advice::Sequential);
+        #[cfg(target_os = "linux")]
+        if map.len() >= HUGEPAGE_ADVICE_BYTES {
+            let _ = map.advise(memmap2::Advice::HugePage);
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = map;
+}
+
+/// Count each file and sum the successes. With `opts.parallel` the files
+/// are spread over `opts.pool`, or a pool private to this call; results
+/// stay in input order either way.
+pub fn count_files<I, P>(paths: I, opts: &CountOptions<'_>) -> FileTotals
+where
+    I: IntoIterator<Item = P>,
+    P: AsRef<Path> + Sync,
+{
+    let paths: Vec<P> = paths.into_iter().collect();
+    #[cfg(feature = "parallel")]
+    let results: Vec<Result<Counts, CountError>> = if opts.parallel && paths.len() > 1 {
+        use rayon::prelude::*;
+        let count_all = || {
+            paths
+                .par_iter()
+                .map(|path| count_path(path.as_ref(), opts))
+                .collect::<Vec<_>>()
+        };
+        match opts.pool {
+            Some(pool) => pool.install(count_all),
+            // A private pool, torn down when the call returns, so the
+            // embedder's global pool is never installed or contended
+            // with. If threads cannot be spawned, count sequentially.
+            None => match rayon::ThreadPoolBuilder::new().build() {
+                Ok(pool) => pool.install(count_all),
+                Err(_) => paths
+                    .iter()
+                    .map(|path| count_path(path.as_ref(), opts))
+                    .collect(),
+            },
+        }
+    } else {
+        paths
+            .iter()
+            .map(|path| count_path(path.as_ref(), opts))
+            .collect()
+    };
+    #[cfg(not(feature = "parallel"))]
+    let results: Vec<Result<Counts, CountError>> = paths
+        .iter()
+        .map(|path| count_path(path.as_ref(), opts))
+        .collect();
+    let mut total = Counts::default();
+    for counts in results.iter().flatten() {
+        total += *counts;
+    }
+    FileTotals { results, total }
+}
+
+/// Count each file on `threads` plain std threads, for embedders that
+/// cannot take the rayon dependency (or whose async runtime conflicts with
+/// its global pool). Workers claim files from a shared index, so large and
+/// small files balance; results still land in input order and the total is
+/// the same sum [`count_files`] produces. `threads` is clamped to at least
+/// one and at most the number of files; `opts.parallel` is ignored.
+pub fn count_files_threads<I, P>(paths: I, opts: &CountOptions<'_>, threads: usize) -> FileTotals
+where
+    I: IntoIterator<Item = P>,
+    P: AsRef<Path> + Sync,
+{
+    let paths: Vec<P> = paths.into_iter().collect();
+    let threads = threads.clamp(1, paths.len().max(1));
+    let next = AtomicUsize::new(0);
+    let mut slots: Vec<Option<Result<Counts, CountError>>> = paths.iter().map(|_| None).collect();
+    std::thread::scope(|scope| {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        for _ in 0..threads {
+            let sender = sender.clone();
+            let next = &next;
+            let paths = &paths;
+            scope.spawn(move || loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(path) = paths.get(index) else {
+                    break;
+                };
+                let _ = sender.send((index, count_path(path.as_ref(), opts)));
+            });
+        }
+        drop(sender);
+        for (index, result) in receiver {
+            slots[index] = Some(result);
+        }
+    });
+    // Every index below paths.len() was claimed by exactly one worker.
+    let results: Vec<Result<Counts, CountError>> = slots.into_iter().flatten().collect();
+    let mut total = Counts::default();
+    for counts in results.iter().flatten() {
+        total += *counts;
+    }
+    FileTotals { results, total }
+}
+
+/// Open `path` and count its contents, applying `limits`.
+pub fn try_count_path(
+    path: &Path,
+    sel: Selection,
+    mode: CountMode,
+    limits: CountLimits<'_>,
+) -> Result<Counts, CountError> {
+    let file = std::fs::File::open(path)?;
+    // A regular file's size condemns it before any byte is read.
+    if let Some(limit) = limits.max_bytes {
+        let meta = file.metadata()?;
+        if meta.is_file() && meta.len() > limit {
+            return Err(CountError::LimitExceeded { limit });
+        }
+    }
+    try_count_reader(file, sel, mode, limits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: Selection = Selection {
+        lines: true,
+        words: true,
+        chars: true,
+        bytes: true,
+        max_line_length: true,
+        max_words_per_line: false,
+        min_words_per_line: false,
+        unique_words: false,
+        avg_word_length: false,
+        fold_case: false,
+        count_cr_lines: false,
+    };
+
+    #[test]
+    fn reader_counts_like_the_slice_kernel() {
+        let data = "héllo wörld\nsecond line\n".as_bytes();
+        let counts = try_count_reader(data, ALL, CountMode::Utf8, CountLimits::default()).unwrap();
+        assert_eq!(
+            counts,
+            crate::count::count_slice(data, ALL, CountMode::Utf8, CountingBackend::Scalar)
+        );
+    }
+
+    #[test]
+    fn byte_limit_fails_instead_of_truncating() {
+        let limits = CountLimits {
+            max_bytes: Some(4),
+            ..CountLimits::default()
+        };
+        let err = try_count_reader(&b"too long"[..], ALL, CountMode::Utf8, limits).unwrap_err();
+        assert!(matches!(err, CountError::LimitExceeded { limit: 4 }));
+        let ok = CountLimits {
+            max_bytes: Some(8),
+            ..CountLimits::default()
+        };
+        assert!(try_count_reader(&b"adequate"[..], ALL, CountMode::Utf8, ok).is_ok());
+    }
+
+    #[test]
+    fn cancellation_is_observed_between_buffers() {
+        let cancel = AtomicBool::new(true);
+        let limits = CountLimits {
+            cancel: Some(&cancel),
+            ..CountLimits::default()
+        };
+        let err = try_count_reader(&b"data"[..], ALL, CountMode::Utf8, limits).unwrap_err();
+        assert!(matches!(err, CountError::Cancelled));
+    }
+
+    #[test]
+    fn explicit_scalar_backend_is_always_available() {
+        let limits = CountLimits {
+            backend: Some(CountingBackend::Scalar),
+            ..CountLimits::default()
+        };
+        assert!(try_count_reader(&b"x\n"[..], ALL, CountMode::Utf8, limits).is_ok());
+    }
+
+    #[test]
+    fn count_files_totals_and_keeps_input_order() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let a = dir.path().join("a.txt");
+        let b = dir.path().join("b.txt");
+        std::fs::write(&a, "one two\n").unwrap();
+        std::fs::write(&b, "three\n").unwrap();
+        let opts = CountOptions::new(ALL, CountMode::Utf8);
+        let run = count_files([&a, &b, &dir.path().join("missing")], &opts);
+        assert_eq!(run.results.len(), 3);
+        assert_eq!(run.results[0].as_ref().unwrap().words, 2);
+        assert_eq!(run.results[1].as_ref().unwrap().words, 1);
+        assert!(run.results[2].is_err());
+        // The total covers only the files that counted.
+        assert_eq!(run.total.lines, 2);
+        assert_eq!(run.total.bytes, 14);
+
+        let parallel = count_files(
+            [&a, &b],
+            &CountOptions {
+                parallel: true,
+                ..opts
+            },
+        );
+        assert_eq!(
+            parallel.total,
+            Counts {
+                lines: run.total.lines,
+                ..run.total
+            }
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "parallel")]
+    fn caller_pool_is_used_and_results_match_sequential() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let paths: Vec<_> = (0..4)
+            .map(|i| {
+                let path = dir.path().join(format!("{i}.txt"));
+                std::fs::write(&path, "line\n".repeat(i + 1)).unwrap();
+                path
+            })
+            .collect();
+        let opts = CountOptions::new(ALL, CountMode::Utf8);
+        let sequential = count_files(&paths, &opts);
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap();
+        let on_pool = count_files(
+            &paths,
+            &CountOptions {
+                parallel: true,
+                pool: Some(&pool),
+                ..opts
+            },
+        );
+        assert_eq!(on_pool.total, sequential.total);
+        for (got, want) in on_pool.results.iter().zip(&sequential.results) {
+            assert_eq!(got.as_ref().unwrap(), want.as_ref().unwrap());
+        }
+    }
+
+    #[test]
+    fn std_thread_counting_matches_count_files() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let paths: Vec<_> = (0..5)
+            .map(|i| {
+                let path = dir.path().join(format!("{i}.txt"));
+                std::fs::write(&path, "word ".repeat(i + 1)).unwrap();
+                path
+            })
+            .collect();
+        let opts = CountOptions::new(ALL, CountMode::Utf8);
+        let rayon_run = count_files(&paths, &opts);
+        // More threads than files clamps; zero threads still counts.
+        for threads in [0, 2, 16] {
+            let run = count_files_threads(&paths, &opts, threads);
+            assert_eq!(run.total, rayon_run.total);
+            for (got, want) in run.results.iter().zip(&rayon_run.results) {
+                assert_eq!(got.as_ref().unwrap(), want.as_ref().unwrap());
+            }
+        }
+        // Errors stay in their slot, as in count_files.
+        let with_missing = count_files_threads(
+            [&paths[0], &dir.path().join("missing"), &paths[1]],
+            &opts,
+            2,
+        );
+        assert!(with_missing.results[1].is_err());
+        assert_eq!(with_missing.total.words, 3);
+    }
+
+    #[test]
+    fn missing_path_surfaces_the_io_error() {
+        let err = try_count_path(
+            Path::new("definitely/not/here"),
+            ALL,
+            CountMode::Utf8,
+            CountLimits::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, CountError::Io(_)));
+    }
+}
@@ -0,0 +1,161 @@
+//! Character-class auditing, behind the binary's `--char-classes`.
+//!
+//! Each decoded character lands in exactly one bucket: whitespace,
+//! control, letters, digits, punctuation (including symbols), or other —
+//! the catch-all for marks, unassigned code points, and undecodable
+//! bytes. Whitespace wins over control so tabs and newlines are not
+//! counted twice. All-ASCII input skips UTF-8 decoding entirely;
+//! everything else goes through the Unicode character properties, with
+//! incomplete trailing UTF-8 sequences carried between `update` calls
+//! like the streaming counter does.
+
+use crate::count::{incomplete_suffix_len, utf8_seq_len};
+
+/// Per-class character tallies for one input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CharClasses {
+    pub letters: u64,
+    pub digits: u64,
+    /// Punctuation and symbols.
+    pub punctuation: u64,
+    pub whitespace: u64,
+    pub control: u64,
+    /// Marks, unassigned code points, and undecodable bytes.
+    pub other: u64,
+}
+
+impl CharClasses {
+    fn classify(&mut self, c: char) {
+        if crate::unicode_data::is_white_space(c) {
+            self.whitespace += 1;
+        } else if c.is_control() {
+            self.control += 1;
+        } else if c.is_alphabetic() {
+            self.letters += 1;
+        } else if c.is_numeric() {
+            self.digits += 1;
+        } else if c_is_printable(c) {
+            self.punctuation += 1;
+        } else {
+            self.other += 1;
+        }
+    }
+}
+
+/// Whether a non-alphanumeric character renders as punctuation or a
+/// symbol rather than an invisible mark: it has a display width.
+fn c_is_printable(c: char) -> bool {
+    use unicode_width::UnicodeWidthChar;
+    c.width().is_some_and(|w| w > 0)
+}
+
+/// Incremental character-class counter.
+#[derive(Debug, Default)]
+pub struct ClassCounter {
+    classes: CharClasses,
+    pending: [u8; 4],
+    pending_len: usize,
+}
+
+impl ClassCounter {
+    pub fn new() -> Self {
+        ClassCounter::default()
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        if self.pending_len == 0 && data.is_ascii() {
+            for &b in data {
+                self.classes.classify(b as char);
+            }
+            return;
+        }
+        // Align to character boundaries, as the streaming counter does:
+        // complete a pending sequence, then hold back an incomplete tail.
+        let mut rest = data;
+        if self.pending_len > 0 {
+            let want = utf8_seq_len(self.pending[0]).unwrap_or(1);
+            let take = rest
+                .iter()
+                .take(want - self.pending_len)
+                .take_while(|&&b| b & 0xc0 == 0x80)
+                .count();
+            self.pending[self.pending_len..self.pending_len + take].copy_from_slice(&rest[..take]);
+            self.pending_len += take;
+            rest = &rest[take..];
+            if self.pending_len < want && rest.is_empty() {
+                return;
+            }
+            let pending = self.pending;
+            let len = self.pending_len;
+            self.pending_len = 0;
+            self.consume(&pending[..len]);
+        }
+        let keep = incomplete_suffix_len(rest);
+        let (body, tail) = rest.split_at(rest.len() - keep);
+        self.consume(body);
+        self.pending[..keep].copy_from_slice(tail);
+        self.pending_len = keep;
+    }
+
+    /// Classify one character-aligned group of input.
+    fn consume(&mut self, data: &[u8]) {
+        for chunk in data.utf8_chunks() {
+            for c in chunk.valid().chars() {
+                self.classes.classify(c);
+            }
+            self.classes.other += chunk.invalid().len() as u64;
+        }
+    }
+
+    pub fn finish(mut self) -> CharClasses {
+        if self.pending_len > 0 {
+            let pending = self.pending;
+            let len = self.pending_len;
+            self.pending_len = 0;
+            self.consume(&pending[..len]);
+        }
+        self.classes
+    }
+}
+
+/// Audit a complete in-memory input.
+pub fn count_char_classes(data: &[u8]) -> CharClasses {
+    let mut counter = ClassCounter::new();
+    counter.update(data);
+    counter.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_class_is_tallied_once() {
+        let c = count_char_classes("ab1 ,\t\x01é٣—\n".as_bytes());
+        assert_eq!(c.letters, 3); // a, b, é
+        assert_eq!(c.digits, 2); // 1 and the Arabic-Indic three
+        assert_eq!(c.punctuation, 2); // comma and em dash
+        assert_eq!(c.whitespace, 3); // space, tab, newline
+        assert_eq!(c.control, 1);
+    }
+
+    #[test]
+    fn undecodable_bytes_land_in_other() {
+        let c = count_char_classes(b"a\xff\xfeb");
+        assert_eq!(c.letters, 2);
+        assert_eq!(c.other, 2);
+    }
+
+    #[test]
+    fn characters_split_across_updates_classify_once() {
+        let data = "wörter 你好, numéro 3\n".as_bytes();
+        let whole = count_char_classes(data);
+        for step in 1..data.len() {
+            let mut counter = ClassCounter::new();
+            for piece in data.chunks(step) {
+                counter.update(piece);
+            }
+            assert_eq!(counter.finish(), whole, "step {step}");
+        }
+    }
+}
@@ -0,0 +1,980 @@
+//! Command-line definition.
+//!
+//! The clap parser lives in the library so integration tests and fuzz
+//! targets can exercise argument handling without spawning the binary.
+
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand, ValueEnum};
+
+use crate::count::{self, Selection};
+use crate::parallel::ParallelMode;
+use crate::simd::BackendChoice;
+
+/// Print newline, word, and byte counts for each FILE.
+///
+/// With no FILE, or when FILE is -, read standard input.
+#[derive(Debug, Parser)]
+#[command(name = "wc-rs", version, about)]
+pub struct Cli {
+    /// Print the byte counts.
+    #[arg(short = 'c', long)]
+    pub bytes: bool,
+
+    /// Print the character counts.
+    #[arg(short = 'm', long)]
+    pub chars: bool,
+
+    /// Print the newline counts.
+    #[arg(short = 'l', long)]
+    pub lines: bool,
+
+    /// Print the maximum display width.
+    #[arg(short = 'L', long)]
+    pub max_line_length: bool,
+
+    /// Print the largest number of words found on any line.
+    #[arg(long)]
+    pub max_words_per_line: bool,
+
+    /// Print the smallest number of words found on any line.
+    #[arg(long)]
+    pub min_words_per_line: bool,
+
+    /// Print the number of distinct words in each input; words are split
+    /// exactly as for -w. The totals row sums the per-file values.
+    #[arg(long)]
+    pub unique_words: bool,
+
+    /// Fold words to lowercase before comparing them, so `The` and `the`
+    /// count as one distinct word. Only affects --unique-words.
+    #[arg(long, requires = "unique_words")]
+    pub fold_case: bool,
+
+    /// Print the average word length in characters, computed in the same
+    /// pass as the other counters.
+    #[arg(long)]
+    pub avg_word_length: bool,
+
+    /// Decimal places for the --avg-word-length column.
+    #[arg(long, value_name = "N", default_value_t = 2)]
+    pub precision: usize,
+
+    /// Expand tabs to stops every N columns when computing -L display
+    /// widths, matching the editor's tab settings (default 8, as GNU wc
+    /// assumes).
+    #[arg(long, value_name = "N", default_value_t = count::DEFAULT_TAB_WIDTH)]
+    pub tab_size: u64,
+
+    /// Print the word counts.
+    #[arg(short = 'w', long)]
+    pub words: bool,
+
+    /// Report DELIM-separated fields per line instead of the standard
+    /// counters: the total field count, the fields-per-line range, and
+    /// whether every line agrees — a fast consistency check for CSV/TSV
+    /// files. DELIM is a single byte and defaults to a tab
+    /// (write `--fields=DELIM`; a separate argument would be ambiguous).
+    #[arg(
+        long,
+        value_name = "DELIM",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "\t"
+    )]
+    pub fields: Option<String>,
+
+    /// Report line terminators instead of the standard counters: how many
+    /// LF, CRLF, and lone-CR endings each input contains, flagging files
+    /// that mix styles.
+    #[arg(long, conflicts_with = "fields")]
+    pub line_endings: bool,
+
+    /// Report a character-class breakdown instead of the standard
+    /// counters: how many letters, digits, punctuation and symbol
+    /// characters, whitespace, control characters, and other characters
+    /// (marks, undecodable bytes) each input contains.
+    #[arg(long, conflicts_with_all = ["fields", "line_endings"])]
+    pub char_classes: bool,
+
+    /// Report Shannon entropy instead of the standard counters: each
+    /// input's byte-level entropy in bits per byte, from a 256-bucket
+    /// histogram. Values near 8 flag compressed or encrypted blobs hiding
+    /// in text trees; plain text sits around 4 to 5. Rendered with
+    /// --precision digits.
+    #[arg(long, conflicts_with_all = ["fields", "line_endings", "char_classes"])]
+    pub entropy: bool,
+
+    /// Also count a lone carriage return as a line terminator, as
+    /// classic-Mac text files use; CRLF pairs still count one line. An
+    /// explicit opt-in — default line counting stays newline-only.
+    #[arg(long)]
+    pub count_cr_lines: bool,
+
+    /// Retry transient read errors (timeouts, would-block) up to N times
+    /// per read before giving up on an input; interrupted reads are always
+    /// retried. Useful on network filesystems and slow devices.
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub retries: u32,
+
+    /// When a read fails mid-file (flaky NFS, yanked media), report the
+    /// counts accumulated over the readable prefix instead of discarding
+    /// them. The row is marked partial and the error still sets the exit
+    /// status.
+    #[arg(long)]
+    pub partial: bool,
+
+    /// Warn on stderr when an input has content but no final newline, whose
+    /// last line wc silently leaves out of the line count. Structured
+    /// outputs always report the condition.
+    #[arg(long)]
+    pub warn_missing_newline: bool,
+
+    /// Print a resource report to stderr at exit: wall time, user and
+    /// system CPU time, peak resident set size, and bytes processed.
+    /// Useful when tuning thread counts and buffer sizes.
+    #[arg(long)]
+    pub rusage: bool,
+
+    /// Skip the madvise hints (sequential access, huge pages on large
+    /// maps) normally applied to memory-mapped inputs.
+    #[arg(long)]
+    pub no_madvise: bool,
+
+    /// Read input from the NUL-terminated names in file F;
+    /// if F is - then read names from standard input.
+    #[arg(long, value_name = "F")]
+    pub files0_from: Option<PathBuf>,
+
+    /// After the per-file rows, print aggregated counts for every
+    /// directory containing the file operands — like `du`, each directory
+    /// row is a subtree total. An optional DEPTH keeps only directories at
+    /// most that many components deep. Pairs well with operand lists from
+    /// `find` or --files0-from; combine with --total=only to drop the
+    /// per-file rows.
+    #[arg(long, value_name = "DEPTH", num_args = 0..=1, require_equals = true)]
+    pub group_by_dir: Option<Option<usize>>,
+
+    /// Print counts in human-readable form using powers of 1024 (1.2M).
+    #[arg(long)]
+    pub human_readable: bool,
+
+    /// Like --human-readable, but use powers of 1000 (1.2M is 1200000).
+    #[arg(long, conflicts_with = "human_readable")]
+    pub si: bool,
+
+    /// Group digits of counts with the locale's thousands separator
+    /// (1,234,567); locales without a separator print plain digits.
+    #[arg(long, conflicts_with_all = ["human_readable", "si"])]
+    pub group_digits: bool,
+
+    /// Transcode input from a legacy encoding (shift_jis, euc-jp, gb18030,
+    /// latin1, ...) before counting; any WHATWG encoding label is accepted.
+    /// The byte count still reports raw input bytes.
+    #[arg(long, value_name = "NAME", conflicts_with = "locale_encoding")]
+    pub encoding: Option<String>,
+
+    /// Decompress standard input before counting, by piping it through the
+    /// system decompressor (gzip, zstd, xz, or bzip2); auto sniffs the
+    /// magic bytes and passes unrecognized data through unchanged, so
+    /// `cat logs.gz | wc-rs --decompress auto` counts the text. The byte
+    /// count reports decompressed bytes. File operands are not affected:
+    /// opening them decompressed would silently change what -c measures.
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t)]
+    pub decompress: Decompress,
+
+    /// Count only this byte range of each input (end-exclusive; either
+    /// offset may be omitted). Regular files seek; streams skip-read.
+    #[arg(long, value_name = "START:END")]
+    pub range: Option<ByteRange>,
+
+    /// Print the selected counters once per fixed-size window through each
+    /// input instead of once per file: N windows every N lines, Nb every N
+    /// bytes. Rows are labeled FILE:W with W the 1-based window index, and
+    /// a shorter final window still prints — a density profile of large
+    /// documents without external chunking scripts.
+    #[arg(long, value_name = "N[b]")]
+    pub window: Option<WindowSpec>,
+
+    /// Stop counting each input after N bytes; rows cut short are flagged
+    /// as truncated in the output.
+    #[arg(long, value_name = "N")]
+    pub max_bytes: Option<u64>,
+
+    /// Stop counting each input after N newlines, like --max-bytes.
+    #[arg(long, value_name = "N")]
+    pub max_lines: Option<u64>,
+
+    /// Fail the run (exit non-zero) when any single input exceeds N lines
+    /// — a CI gate for generated-file size policies, with no wrapper
+    /// script. Counts still print; each offender is diagnosed on stderr.
+    #[arg(long, value_name = "N")]
+    pub assert_max_lines: Option<u64>,
+
+    /// Like --assert-max-lines, for an input's byte count.
+    #[arg(long, value_name = "N")]
+    pub assert_max_bytes: Option<u64>,
+
+    /// Fail the run when the inputs' combined line count exceeds N.
+    #[arg(long, value_name = "N")]
+    pub assert_total_max_lines: Option<u64>,
+
+    /// Fail the run when the inputs' combined byte count exceeds N.
+    #[arg(long, value_name = "N")]
+    pub assert_total_max_bytes: Option<u64>,
+
+    /// Recount every file listed in the manifest FILE and report each one
+    /// whose counts drifted, like sha256sum -c: the manifest's own
+    /// counters and decoding mode apply, one status line prints per file,
+    /// and the exit status is non-zero when anything differs or cannot be
+    /// read. For datasets where only line/byte counts are tracked.
+    #[arg(long, value_name = "FILE")]
+    pub check: Option<PathBuf>,
+
+    /// Record the operands' counts in FILE as a manifest --check can
+    /// verify later: the selected counters, the decoding mode, and one
+    /// row per file, sorted by name so the output is stable across runs
+    /// and shells — fit for committing next to a dataset.
+    #[arg(long, value_name = "FILE", conflicts_with = "check")]
+    pub write_manifest: Option<PathBuf>,
+
+    /// Periodically save progress to FILE while streaming a single large
+    /// file, and resume from FILE if it already exists; the file is removed
+    /// once the count completes.
+    #[arg(long, value_name = "FILE")]
+    pub checkpoint: Option<PathBuf>,
+
+    /// Normalize decoded text before counting characters, so `e` plus a
+    /// combining accent and the precomposed letter count the same.
+    #[arg(long, value_enum, value_name = "FORM", default_value_t)]
+    pub normalize: Normalization,
+
+    /// Restrict behavior to the POSIX wc spec: only -c/-l/-m/-w, fields
+    /// separated by single spaces, and no wc-rs extensions. Also implied by
+    /// the POSIXLY_CORRECT environment variable.
+    #[arg(long)]
+    pub posix: bool,
+
+    /// Report per-input decisions (such as the encoding `--encoding auto`
+    /// detected) on standard error.
+    #[arg(long)]
+    pub debug: bool,
+
+    /// Suppress advisory messages on standard error, such as the hint
+    /// printed when reading from an interactive terminal. Diagnostics for
+    /// real errors still print.
+    #[arg(long)]
+    pub quiet: bool,
+
+    /// Suppress only the interactive-terminal hint, for scripts that
+    /// deliberately read stdin from a TTY.
+    #[arg(long)]
+    pub no_tty_hint: bool,
+
+    /// Count each input with both the detected SIMD backend and the scalar
+    /// reference, and fail loudly if they disagree.
+    #[arg(long)]
+    pub verify: bool,
+
+    /// How to pick the SIMD backend: trust CPU feature detection, or time
+    /// the candidates at startup and keep the fastest (auto-bench).
+    #[arg(long, value_enum, value_name = "CHOICE", default_value_t)]
+    pub backend: BackendChoice,
+
+    /// How input characters are decoded; auto follows the platform locale
+    /// (POSIX env vars, or the console code page on Windows).
+    #[arg(long, value_enum, value_name = "ENC", default_value_t)]
+    pub locale_encoding: LocaleEncoding,
+
+    /// Output format; machine-readable formats always carry raw numbers.
+    /// Defaults to WC_RS_OUTPUT when that is set.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        env = "WC_RS_OUTPUT",
+        default_value_t
+    )]
+    pub output: OutputFormat,
+
+    /// Write the report to FILE instead of standard output. The report is
+    /// staged in a temporary file beside FILE and renamed into place once
+    /// complete, so readers of FILE and other jobs' terminal output never
+    /// see it half-written.
+    #[arg(short = 'o', long, value_name = "FILE")]
+    pub output_file: Option<PathBuf>,
+
+    /// With --output-file, append the report to FILE instead of replacing
+    /// it — for accumulating one log across several runs.
+    #[arg(long, requires = "output_file")]
+    pub append: bool,
+
+    /// How file names are written in text output; literal prints the raw
+    /// bytes like GNU wc, the others escape them for shells or C parsers.
+    #[arg(long, value_enum, value_name = "STYLE", default_value_t)]
+    pub quoting_style: QuotingStyle,
+
+    /// When to colorize output (file names, the totals row, diagnostics).
+    #[arg(long, value_enum, value_name = "WHEN", default_value_t)]
+    pub color: ColorMode,
+
+    /// When to print a line with total counts. Defaults to WC_RS_TOTAL
+    /// when that is set.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "WHEN",
+        env = "WC_RS_TOTAL",
+        default_value_t
+    )]
+    pub total: TotalMode,
+
+    /// Label for the summary row instead of "total" — a dataset name, say,
+    /// when several reports are concatenated.
+    #[arg(long, value_name = "STRING", default_value = "total")]
+    pub total_label: String,
+
+    /// Print each file's row as soon as it finishes counting instead of
+    /// holding every result until the batch is done. Rows come out in
+    /// completion order, so slow files no longer delay fast ones; the
+    /// totals row at the end is unaffected.
+    #[arg(long)]
+    pub unordered: bool,
+
+    /// Keep running after the first report and reprint the counts whenever
+    /// an operand file changes — handy in a spare terminal while editing a
+    /// document. Uses inotify on Linux and falls back to polling the
+    /// files' metadata elsewhere; stop it with Ctrl-C.
+    #[arg(long, conflicts_with_all = ["check", "write_manifest", "files0_from", "unordered", "output_file"])]
+    pub watch: bool,
+
+    /// How --watch presents each new report: clear the screen first so the
+    /// counts stay in place, or append below the previous report for a
+    /// scrollable history.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        requires = "watch",
+        default_value_t
+    )]
+    pub watch_format: WatchFormat,
+
+    /// Speak line-delimited JSON-RPC 2.0 on stdin and stdout instead of
+    /// counting operands: each request names a path or carries inline
+    /// data, and its reply reports the selected counters. For editor
+    /// plugins and long-lived parents that drive wc-rs programmatically;
+    /// the stdio twin of `serve`.
+    #[arg(long, conflicts_with_all = ["watch", "check", "write_manifest", "files0_from", "output_file", "unordered"])]
+    pub rpc: bool,
+
+    /// With --output ndjson, add each file's size, mtime (seconds since
+    /// the epoch), decoding encoding, and the counting backend to its
+    /// row, so downstream analytics can join counts with file attributes
+    /// without stat calls of their own.
+    #[arg(long)]
+    pub with_metadata: bool,
+
+    /// Peg totals at the counter maximum instead of wrapping when a
+    /// 64-bit counter overflows, for runs long enough to get there —
+    /// --watch on synthetic streams, or endless accumulation on 32-bit
+    /// hosts. A saturated total is marked in the output: the totals
+    /// label gains "(saturated)" and NDJSON totals rows carry
+    /// "saturated":true.
+    #[arg(long)]
+    pub saturate: bool,
+
+    /// How to parallelize counting across threads.
+    #[arg(long, value_enum, value_name = "MODE", default_value_t)]
+    pub parallel_mode: ParallelMode,
+
+    /// Number of worker threads, or `auto` to size the pool from the
+    /// number and sizes of the operands (defaults to WC_RS_THREADS when
+    /// set, otherwise the number of CPUs).
+    #[arg(short = 'j', long, value_name = "N", env = "WC_RS_THREADS")]
+    pub threads: Option<ThreadCount>,
+
+    /// On hybrid P/E-core CPUs, pin the counting workers to the
+    /// performance cores so an efficiency core never becomes the critical
+    /// path. Linux only; ignored on homogeneous CPUs.
+    #[arg(long)]
+    pub prefer_p_cores: bool,
+
+    /// Files to count; - means standard input.
+    #[arg(value_name = "FILE")]
+    pub files: Vec<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Maintenance subcommands that run instead of counting.
+#[derive(Debug, Clone, PartialEq, Eq, Subcommand)]
+pub enum Command {
+    /// Cross-check every SIMD backend available on this CPU against the
+    /// scalar reference, the same comparison `--verify` applies per input.
+    SelfTest,
+    /// Answer count requests over a Unix socket until killed, so editors
+    /// and build daemons can count repeatedly without paying process
+    /// startup per call. The counter flags given before the subcommand
+    /// select what each reply reports.
+    Serve {
+        /// The socket path to listen on; a leftover socket file from a
+        /// previous daemon is replaced.
+        #[arg(long, value_name = "PATH")]
+        socket: PathBuf,
+    },
+}
+
+/// Character decoding for -m, -w, and -L, normally inferred from the locale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum LocaleEncoding {
+    /// Detect from the platform locale.
+    #[default]
+    Auto,
+    /// Decode input as UTF-8.
+    Utf8,
+    /// Treat every byte as one character, as in the C locale.
+    SingleByte,
+}
+
+/// A byte range of each input to count, end-exclusive, parsed from
+/// `START:END` where either offset may be omitted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: Option<u64>,
+}
+
+impl std::str::FromStr for ByteRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once(':')
+            .ok_or_else(|| "expected START:END".to_string())?;
+        let start = if start.is_empty() {
+            0
+        } else {
+            start
+                .parse()
+                .map_err(|_| format!("invalid start offset '{start}'"))?
+        };
+        let end = if end.is_empty() {
+            None
+        } else {
+            Some(
+                end.parse()
+                    .map_err(|_| format!("invalid end offset '{end}'"))?,
+            )
+        };
+        if end.is_some_and(|end| end < start) {
+            return Err("end offset precedes start offset".to_string());
+        }
+        Ok(ByteRange { start, end })
+    }
+}
+
+/// A `--threads` argument: a fixed worker count, or `auto` to let the
+/// binary size the pool from the workload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadCount {
+    /// Choose from the number and sizes of the operands.
+    Auto,
+    Fixed(usize),
+}
+
+impl std::str::FromStr for ThreadCount {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "auto" {
+            return Ok(ThreadCount::Auto);
+        }
+        s.parse()
+            .map(ThreadCount::Fixed)
+            .map_err(|_| format!("expected a thread count or 'auto', not '{s}'"))
+    }
+}
+
+/// A `--window` size, parsed from `N` (lines) or `Nb` (bytes).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowSpec {
+    pub size: u64,
+    /// Windows are measured in bytes rather than lines.
+    pub bytes: bool,
+}
+
+impl std::str::FromStr for WindowSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (digits, bytes) = match s.strip_suffix('b') {
+            Some(digits) => (digits, true),
+            None => (s, false),
+        };
+        let size = digits
+            .parse()
+            .map_err(|_| format!("invalid window size '{s}'"))?;
+        if size == 0 {
+            return Err("window size must be at least 1".to_string());
+        }
+        Ok(WindowSpec { size, bytes })
+    }
+}
+
+/// Compression applied to standard input, undone before counting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Decompress {
+    /// Count the bytes as they arrive.
+    #[default]
+    Never,
+    /// Sniff the magic bytes; unrecognized data passes through unchanged.
+    Auto,
+    /// Decompress with gzip, without sniffing.
+    Gzip,
+    /// Decompress with zstd, without sniffing.
+    Zstd,
+    /// Decompress with xz, without sniffing.
+    Xz,
+    /// Decompress with bzip2, without sniffing.
+    Bzip2,
+}
+
+/// Unicode normalization applied to decoded text before counting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Normalization {
+    /// Count code points as they appear in the input.
+    #[default]
+    None,
+    /// Canonical composition (precomposed characters).
+    Nfc,
+    /// Canonical decomposition (base characters plus combining marks).
+    Nfd,
+}
+
+/// How file names are escaped in the text output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum QuotingStyle {
+    /// The name's bytes, verbatim, even when they are not valid UTF-8.
+    #[default]
+    Literal,
+    /// Quoted so the name survives a round trip through a POSIX shell.
+    #[value(name = "shell-escape")]
+    ShellEscape,
+    /// A C string literal with backslash escapes.
+    C,
+}
+
+/// How `--watch` presents each new report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum WatchFormat {
+    /// Clear the screen before every report, so the counts stay put.
+    #[default]
+    Clear,
+    /// Print each report below the previous one.
+    Append,
+}
+
+/// The shape of what gets printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OutputFormat {
+    /// GNU wc's aligned columns.
+    #[default]
+    Text,
+    /// One JSON object per file, emitted as soon as that file finishes.
+    Ndjson,
+    /// Prometheus/OpenMetrics gauges (wc_lines{file="..."} N) for the
+    /// node_exporter textfile collector.
+    #[value(name = "openmetrics")]
+    OpenMetrics,
+}
+
+/// When escape-coded colors are emitted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ColorMode {
+    /// Color when writing to a terminal, unless NO_COLOR is set.
+    #[default]
+    Auto,
+    /// Always color, even into pipes.
+    Always,
+    /// Never color.
+    Never,
+}
+
+/// When the totals row is printed, mirroring GNU `wc --total`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum TotalMode {
+    /// Print the total when there is more than one input.
+    #[default]
+    Auto,
+    /// Always print the total.
+    Always,
+    /// Print only the total, no per-file rows.
+    Only,
+    /// Never print the total.
+    Never,
+}
+
+impl Cli {
+    /// Check option combinations that clap cannot express.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.files0_from.is_some() && !self.files.is_empty() {
+            return Err("file operands cannot be combined with --files0-from".to_string());
+        }
+        if self.tab_size == 0 {
+            return Err("--tab-size must be at least 1".to_string());
+        }
+        if let Some(delim) = &self.fields {
+            if delim.len() != 1 {
+                return Err(format!("--fields delimiter {delim:?} is not a single byte"));
+            }
+        }
+        if let Some(report) = [
+            (self.fields.is_some(), "--fields"),
+            (self.line_endings, "--line-endings"),
+            (self.char_classes, "--char-classes"),
+            (self.entropy, "--entropy"),
+            (self.window.is_some(), "--window"),
+        ]
+        .iter()
+        .find_map(|&(used, name)| used.then_some(name))
+        {
+            // A report replaces the counter columns; options that shape or
+            // reinterpret those columns have nothing to apply to.
+            if self.output != OutputFormat::Text {
+                return Err(format!("{report} only produces text output"));
+            }
+            if self.encoding.is_some()
+                || self.normalize != Normalization::None
+                || self.range.is_some()
+                || self.max_bytes.is_some()
+                || self.max_lines.is_some()
+            {
+                return Err(format!(
+                    "{report} cannot be combined with --encoding, --normalize, --range, or input caps"
+                ));
+            }
+            if self.checkpoint.is_some() || self.verify {
+                return Err(format!(
+                    "{report} cannot be combined with --checkpoint or --verify"
+                ));
+            }
+            if self.has_assert_limits() {
+                return Err(format!(
+                    "{report} cannot be combined with --assert limits; there are no counts to check"
+                ));
+            }
+        }
+        if (self.assert_max_lines.is_some() || self.assert_total_max_lines.is_some())
+            && !self.selection().lines
+        {
+            return Err("--assert limits on lines require line counting (-l)".to_string());
+        }
+        if (self.assert_max_bytes.is_some() || self.assert_total_max_bytes.is_some())
+            && !self.selection().bytes
+        {
+            return Err("--assert limits on bytes require byte counting (-c)".to_string());
+        }
+        if self.unordered && self.output != OutputFormat::Text {
+            return Err(
+                "--unordered only applies to text output; NDJSON already streams".to_string(),
+            );
+        }
+        if self.check.is_some() && (!self.files.is_empty() || self.files0_from.is_some()) {
+            return Err(
+                "--check reads its file list from the manifest, not from operands".to_string(),
+            );
+        }
+        if self.write_manifest.is_some() {
+            // Rows must be re-checkable: every entry needs a path to
+            // reopen, and every recorded value an exact integer meaning.
+            if self.files.is_empty() || self.files0_from.is_some() {
+                return Err("--write-manifest records file operands".to_string());
+            }
+            if self.files.iter().any(|path| path.as_os_str() == "-") {
+                return Err("--write-manifest cannot record standard input".to_string());
+            }
+            if self.avg_word_length || self.fold_case {
+                return Err(
+                    "--write-manifest cannot record --avg-word-length or --fold-case".to_string(),
+                );
+            }
+        }
+        if self.with_metadata && self.output != OutputFormat::Ndjson {
+            return Err("--with-metadata applies to --output ndjson".to_string());
+        }
+        if self.rpc {
+            if !self.files.is_empty() {
+                return Err("--rpc takes its requests on stdin, not file operands".to_string());
+            }
+            if self.output != OutputFormat::Text {
+                return Err("--rpc replies are already JSON; --output does not apply".to_string());
+            }
+            if self.fields.is_some()
+                || self.line_endings
+                || self.char_classes
+                || self.entropy
+                || self.window.is_some()
+            {
+                return Err("--rpc cannot be combined with a report mode".to_string());
+            }
+            if self.checkpoint.is_some() || self.has_assert_limits() {
+                return Err(
+                    "--rpc cannot be combined with --checkpoint or --assert limits".to_string(),
+                );
+            }
+        }
+        if self.watch {
+            // Watching means reopening and recounting, which only a named
+            // file supports, and only the plain table redraws sensibly.
+            if self.files.is_empty() {
+                return Err("--watch requires file operands to monitor".to_string());
+            }
+            if self.files.iter().any(|path| path.as_os_str() == "-") {
+                return Err("--watch cannot monitor standard input".to_string());
+            }
+            if self.output != OutputFormat::Text {
+                return Err("--watch only reprints the text table".to_string());
+            }
+            if self.fields.is_some()
+                || self.line_endings
+                || self.char_classes
+                || self.entropy
+                || self.window.is_some()
+            {
+                return Err("--watch cannot be combined with a report mode".to_string());
+            }
+            if self.checkpoint.is_some() {
+                return Err("--watch cannot be combined with --checkpoint".to_string());
+            }
+            if self.has_assert_limits() {
+                // A watch never exits on its own, so a failing exit status
+                // would have nothing to gate.
+                return Err("--watch cannot be combined with --assert limits".to_string());
+            }
+        }
+        if self.checkpoint.is_some() {
+            // A checkpoint records a byte offset into one raw stream; every
+            // option that reshapes the stream would make the offset a lie.
+            if self.encoding.is_some()
+                || self.normalize != Normalization::None
+                || self.range.is_some()
+                || self.max_bytes.is_some()
+                || self.max_lines.is_some()
+            {
+                return Err(
+                    "--checkpoint cannot be combined with --encoding, --normalize, --range, or input caps"
+                        .to_string(),
+                );
+            }
+            if self.files0_from.is_some() {
+                return Err("--checkpoint cannot be combined with --files0-from".to_string());
+            }
+            // A resumed counter cannot rebuild the distinct-word set from
+            // a checkpoint's numeric state.
+            if self.unique_words {
+                return Err("--checkpoint cannot be combined with --unique-words".to_string());
+            }
+            if self.files.len() != 1 || self.files[0].as_os_str() == "-" {
+                return Err("--checkpoint requires exactly one file operand".to_string());
+            }
+            if self.verify {
+                // A resumed reference counter would have missed the part of
+                // the file counted before the checkpoint.
+                return Err("--checkpoint cannot be combined with --verify".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// True when any `--assert-*` limit is set.
+    pub fn has_assert_limits(&self) -> bool {
+        self.assert_max_lines.is_some()
+            || self.assert_max_bytes.is_some()
+            || self.assert_total_max_lines.is_some()
+            || self.assert_total_max_bytes.is_some()
+    }
+
+    /// Whether POSIX mode is in effect: the flag, or the conventional
+    /// POSIXLY_CORRECT environment variable.
+    pub fn posix_mode(&self) -> bool {
+        self.posix || std::env::var_os("POSIXLY_CORRECT").is_some()
+    }
+
+    /// The first way this invocation strays from POSIX wc, if any. POSIX
+    /// defines only -c, -l, -m, -w and file operands, and makes -c and -m
+    /// mutually exclusive.
+    pub fn posix_violation(&self) -> Option<String> {
+        if self.bytes && self.chars {
+            return Some("-c and -m are mutually exclusive in POSIX mode".to_string());
+        }
+        let extensions = [
+            (self.max_line_length, "-L"),
+            (self.max_words_per_line, "--max-words-per-line"),
+            (self.unique_words, "--unique-words"),
+            (self.avg_word_length, "--avg-word-length"),
+            (self.min_words_per_line, "--min-words-per-line"),
+            (self.fields.is_some(), "--fields"),
+            (self.line_endings, "--line-endings"),
+            (self.char_classes, "--char-classes"),
+            (self.entropy, "--entropy"),
+            (self.count_cr_lines, "--count-cr-lines"),
+            (self.window.is_some(), "--window"),
+            (self.partial, "--partial"),
+            (self.retries != 0, "--retries"),
+            (self.warn_missing_newline, "--warn-missing-newline"),
+            (self.rusage, "--rusage"),
+            (self.no_madvise, "--no-madvise"),
+            (self.tab_size != count::DEFAULT_TAB_WIDTH, "--tab-size"),
+            (self.files0_from.is_some(), "--files0-from"),
+            (self.group_by_dir.is_some(), "--group-by-dir"),
+            (self.unordered, "--unordered"),
+            (self.human_readable, "--human-readable"),
+            (self.si, "--si"),
+            (self.group_digits, "--group-digits"),
+            (self.encoding.is_some(), "--encoding"),
+            (self.decompress != Decompress::Never, "--decompress"),
+            (self.range.is_some(), "--range"),
+            (self.max_bytes.is_some(), "--max-bytes"),
+            (self.max_lines.is_some(), "--max-lines"),
+            (self.assert_max_lines.is_some(), "--assert-max-lines"),
+            (self.assert_max_bytes.is_some(), "--assert-max-bytes"),
+            (
+                self.assert_total_max_lines.is_some(),
+                "--assert-total-max-lines",
+            ),
+            (
+                self.assert_total_max_bytes.is_some(),
+                "--assert-total-max-bytes",
+            ),
+            (self.watch, "--watch"),
+            (self.rpc, "--rpc"),
+            (self.with_metadata, "--with-metadata"),
+            (self.saturate, "--saturate"),
+            (self.check.is_some(), "--check"),
+            (self.write_manifest.is_some(), "--write-manifest"),
+            (self.checkpoint.is_some(), "--checkpoint"),
+            (self.normalize != Normalization::None, "--normalize"),
+            (self.debug, "--debug"),
+            (self.verify, "--verify"),
+            (self.backend != BackendChoice::Auto, "--backend"),
+            (
+                self.locale_encoding != LocaleEncoding::Auto,
+                "--locale-encoding",
+            ),
+            (self.output != OutputFormat::Text, "--output"),
+            (self.output_file.is_some(), "--output-file"),
+            (self.append, "--append"),
+            (
+                self.quoting_style != QuotingStyle::Literal,
+                "--quoting-style",
+            ),
+            (self.color == ColorMode::Always, "--color=always"),
+            (self.total != TotalMode::Auto, "--total"),
+            (self.total_label != "total", "--total-label"),
+        ];
+        extensions
+            .iter()
+            .find(|(used, _)| *used)
+            .map(|(_, name)| format!("{name} is not part of POSIX wc"))
+    }
+
+    /// The counters this invocation should print, applying the GNU default
+    /// when no counter flag was given.
+    pub fn selection(&self) -> Selection {
+        let explicit = Selection {
+            lines: self.lines,
+            words: self.words,
+            chars: self.chars,
+            bytes: self.bytes,
+            max_line_length: self.max_line_length,
+            max_words_per_line: self.max_words_per_line,
+            min_words_per_line: self.min_words_per_line,
+            unique_words: self.unique_words,
+            avg_word_length: self.avg_word_length,
+            fold_case: self.fold_case,
+            count_cr_lines: self.count_cr_lines,
+        };
+        if explicit.is_empty() {
+            // Modifiers alone leave the counter set at the default.
+            Selection {
+                count_cr_lines: self.count_cr_lines,
+                ..Selection::DEFAULT
+            }
+        } else {
+            explicit
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Cli {
+        Cli::try_parse_from(std::iter::once("wc-rs").chain(args.iter().copied())).unwrap()
+    }
+
+    #[test]
+    fn default_selection_is_lines_words_bytes() {
+        let sel = parse(&[]).selection();
+        assert_eq!(sel, Selection::DEFAULT);
+    }
+
+    #[test]
+    fn explicit_flags_replace_the_default() {
+        let sel = parse(&["-L", "-m"]).selection();
+        assert!(sel.chars && sel.max_line_length);
+        assert!(!sel.lines && !sel.words && !sel.bytes);
+    }
+
+    #[test]
+    fn combined_short_flags() {
+        let cli = parse(&["-lwc", "a", "b"]);
+        assert!(cli.lines && cli.words && cli.bytes);
+        assert_eq!(cli.files.len(), 2);
+    }
+
+    #[test]
+    fn range_parses_optional_offsets() {
+        let range = |s: &str| s.parse::<ByteRange>();
+        assert_eq!(
+            range("5:10"),
+            Ok(ByteRange {
+                start: 5,
+                end: Some(10)
+            })
+        );
+        assert_eq!(
+            range(":10"),
+            Ok(ByteRange {
+                start: 0,
+                end: Some(10)
+            })
+        );
+        assert_eq!(
+            range("5:"),
+            Ok(ByteRange {
+                start: 5,
+                end: None
+            })
+        );
+        assert!(range("10:5").is_err());
+        assert!(range("5").is_err());
+    }
+
+    #[test]
+    fn total_and_parallel_mode_values() {
+        let cli = parse(&["--total=only", "--parallel-mode=chunks"]);
+        assert_eq!(cli.total, TotalMode::Only);
+        assert_eq!(cli.parallel_mode, ParallelMode::Chunks);
+    }
+
+    #[test]
+    fn thread_count_parses_numbers_and_auto() {
+        assert_eq!(parse(&["-j", "3"]).threads, Some(ThreadCount::Fixed(3)));
+        assert_eq!(parse(&["-j", "auto"]).threads, Some(ThreadCount::Auto));
+        assert!(Cli::try_parse_from(["wc-rs", "-j", "some"]).is_err());
+    }
+}
@@ -0,0 +1,1925 @@
+//! Counting kernels and the chunk-merge model.
+//!
+//! Counting is expressed in two forms:
+//!
+//! * [`ChunkCounts`] — counters for a byte slice plus just enough boundary
+//!   information ([`ChunkCounts::merge`]) to combine adjacent chunks. This is
+//!   what parallel intra-file counting is built on. Merging is exact for
+//!   lines, words, characters and bytes as long as chunks are split on UTF-8
+//!   character boundaries (see [`split_point`]); the max-line-length counter
+//!   is only exact within a single chunk because tab stops depend on the
+//!   absolute column, so callers that need `-L` should not split.
+//! * [`StreamCounter`] — an incremental scanner for sequential reads. It is
+//!   exact for every counter, including max line length, and carries
+//!   incomplete UTF-8 sequences across `update` calls.
+//!
+//! The word and line-length rules follow GNU `wc`: only printable characters
+//! affect word state and columns (non-printable and undecodable bytes are
+//! ignored), `\r` and `\f` reset the column without counting a line, `\v`
+//! separates words without advancing, tabs advance to the next tab stop, and
+//! columns use the terminal display width (East Asian wide characters count
+//! two). Characters are the successfully decoded scalar values in UTF-8 mode
+//! and plain bytes in single-byte mode.
+
+use unicode_width::UnicodeWidthChar;
+
+use crate::simd::{CountingBackend, Kernels};
+
+/// Tab stops every eight columns, as `wc -L` assumes; the binary's
+/// `--tab-size` overrides it to match other editor settings.
+pub const DEFAULT_TAB_WIDTH: u64 = 8;
+
+/// Which counters a caller asked for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Selection {
+    pub lines: bool,
+    pub words: bool,
+    pub chars: bool,
+    pub bytes: bool,
+    pub max_line_length: bool,
+    /// The largest number of words on any line.
+    pub max_words_per_line: bool,
+    /// The smallest number of words on any line.
+    pub min_words_per_line: bool,
+    /// The number of distinct words.
+    pub unique_words: bool,
+    /// The average word length in characters, derived from the word-char
+    /// tally [`Counts::word_chars`].
+    pub avg_word_length: bool,
+    /// Fold words to lowercase before the distinct-word comparison, so
+    /// `The` and `the` collapse. A modifier on `unique_words`, not a
+    /// counter of its own.
+    pub fold_case: bool,
+    /// Treat a lone `\r` as a line terminator too, as classic-Mac files
+    /// use; CRLF pairs still count once, through their `\n`. A modifier on
+    /// `lines`, not a counter of its own.
+    pub count_cr_lines: bool,
+}
+
+impl Selection {
+    /// The GNU `wc` default: lines, words and bytes.
+    pub const DEFAULT: Selection = Selection {
+        lines: true,
+        words: true,
+        chars: false,
+        bytes: true,
+        max_line_length: false,
+        max_words_per_line: false,
+        min_words_per_line: false,
+        unique_words: false,
+        avg_word_length: false,
+        fold_case: false,
+        count_cr_lines: false,
+    };
+
+    /// Number of counters selected.
+    pub fn len(&self) -> usize {
+        [
+            self.lines,
+            self.words,
+            self.chars,
+            self.bytes,
+            self.max_line_length,
+            self.max_words_per_line,
+            self.min_words_per_line,
+            self.unique_words,
+            self.avg_word_length,
+        ]
+        .iter()
+        .filter(|&&b| b)
+        .count()
+    }
+
+    /// True if no counter is selected.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// True if the selection only needs a byte count.
+    pub fn bytes_only(&self) -> bool {
+        self.bytes && self.len() == 1
+    }
+
+    /// True if the selected counters can be computed per chunk and merged
+    /// across arbitrary (character-aligned) chunk boundaries. Max line
+    /// length cannot, because tab stops depend on the absolute column; the
+    /// words-per-line extremes cannot, because a line spanning chunks
+    /// would be seen as two; distinct words cannot, because a word
+    /// seen in two chunks would count twice; and CR lines cannot, because
+    /// a CRLF pair split across chunks would count its `\r` as lone.
+    pub fn is_chunk_mergeable(&self) -> bool {
+        !(self.max_line_length || self.words_per_line() || self.unique_words || self.count_cr_lines)
+    }
+
+    /// True if counting requires the full scalar scan (word state or column
+    /// tracking) rather than a bulk byte-classification pass.
+    pub fn needs_scan(&self) -> bool {
+        self.words
+            || self.max_line_length
+            || self.words_per_line()
+            || self.unique_words
+            || self.avg_word_length
+    }
+
+    /// True if either words-per-line extreme is selected.
+    pub fn words_per_line(&self) -> bool {
+        self.max_words_per_line || self.min_words_per_line
+    }
+}
+
+impl Default for Selection {
+    fn default() -> Self {
+        Selection::DEFAULT
+    }
+}
+
+/// How multi-byte input is interpreted when splitting words and counting
+/// characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CountMode {
+    /// Single-byte "C" locale: every byte is a character and only printable
+    /// ASCII affects words and columns.
+    Bytes,
+    /// UTF-8: characters are decoded scalar values; invalid sequences count
+    /// nothing, like undecodable bytes in GNU `wc`.
+    #[default]
+    Utf8,
+}
+
+/// The unit the `chars` counter is measured in.
+///
+/// [`CountMode`] fixes how bytes decode for the word and column rules; the
+/// char unit independently picks what a "character" means for the `-m`
+/// counter, so embedders can get grapheme counts from the same kernels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CharUnit {
+    /// Raw bytes, as in single-byte locales.
+    Bytes,
+    /// Decoded Unicode scalar values: GNU `wc -m` in UTF-8 locales.
+    #[default]
+    ScalarValues,
+    /// Extended grapheme clusters: user-perceived characters. Exact for
+    /// whole slices and streams; merging chunks can double-count a cluster
+    /// spanning a chunk boundary, since [`split_point`] only aligns to
+    /// scalar values.
+    Graphemes,
+}
+
+impl CharUnit {
+    /// The unit `wc` itself uses under the given mode.
+    pub fn for_mode(mode: CountMode) -> CharUnit {
+        match mode {
+            CountMode::Bytes => CharUnit::Bytes,
+            CountMode::Utf8 => CharUnit::ScalarValues,
+        }
+    }
+}
+
+/// The `chars` counter for a slice under the given unit.
+fn count_chars(data: &[u8], unit: CharUnit, kernels: Kernels) -> u64 {
+    match unit {
+        CharUnit::Bytes => data.len() as u64,
+        CharUnit::ScalarValues => kernels.count_utf8_chars(data),
+        CharUnit::Graphemes => count_graphemes(data),
+    }
+}
+
+/// Grapheme clusters in a complete slice; undecodable bytes count nothing.
+/// ASCII without CR is one cluster per byte (only CRLF joins), which keeps
+/// the common case on a bulk scan instead of the segmentation tables.
+fn count_graphemes(data: &[u8]) -> u64 {
+    use unicode_segmentation::UnicodeSegmentation;
+    if data.is_ascii() && memchr::memchr(b'\r', data).is_none() {
+        return data.len() as u64;
+    }
+    let mut total = 0u64;
+    for chunk in data.utf8_chunks() {
+        total += chunk.valid().graphemes(true).count() as u64;
+    }
+    total
+}
+
+/// The final counters for an input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Counts {
+    pub lines: u64,
+    pub words: u64,
+    pub chars: u64,
+    pub bytes: u64,
+    pub max_line_length: u64,
+    /// Most words on one line; tracked only when selected.
+    pub max_words_per_line: u64,
+    /// Fewest words on one line, `None` until a line has been seen;
+    /// tracked only when selected.
+    pub min_words_per_line: Option<u64>,
+    /// Distinct words in the input; tracked only when selected. Summing
+    /// inputs adds their per-input values, so a word shared by two files
+    /// counts once per file in a total.
+    pub unique_words: u64,
+    /// Characters inside words, the numerator of
+    /// [`Counts::avg_word_length`]; tracked only when the average is
+    /// selected. Plainly additive, so chunking and totals stay exact.
+    pub word_chars: u64,
+}
+
+impl Counts {
+    /// The sum of two inputs' counters, or `None` if any additive counter
+    /// would overflow `u64`. The extremes merge as in `+=`: the maxima
+    /// take the larger value and the minimum the smaller, neither of
+    /// which can overflow.
+    pub fn checked_add(&self, other: Counts) -> Option<Counts> {
+        Some(Counts {
+            lines: self.lines.checked_add(other.lines)?,
+            words: self.words.checked_add(other.words)?,
+            chars: self.chars.checked_add(other.chars)?,
+            bytes: self.bytes.checked_add(other.bytes)?,
+            max_line_length: self.max_line_length.max(other.max_line_length),
+            max_words_per_line: self.max_words_per_line.max(other.max_words_per_line),
+            min_words_per_line: merge_min(self.min_words_per_line, other.min_words_per_line),
+            unique_words: self.unique_words.checked_add(other.unique_words)?,
+            word_chars: self.word_chars.checked_add(other.word_chars)?,
+        })
+    }
+
+    /// The sum of two inputs' counters with the additive counters pegged
+    /// at `u64::MAX` instead of wrapping, for accumulations expected to
+    /// run long enough to overflow. The extremes merge as in `+=`.
+    pub fn saturating_add(&self, other: Counts) -> Counts {
+        Counts {
+            lines: self.lines.saturating_add(other.lines),
+            words: self.words.saturating_add(other.words),
+            chars: self.chars.saturating_add(other.chars),
+            bytes: self.bytes.saturating_add(other.bytes),
+            max_line_length: self.max_line_length.max(other.max_line_length),
+            max_words_per_line: self.max_words_per_line.max(other.max_words_per_line),
+            min_words_per_line: merge_min(self.min_words_per_line, other.min_words_per_line),
+            unique_words: self.unique_words.saturating_add(other.unique_words),
+            word_chars: self.word_chars.saturating_add(other.word_chars),
+        }
+    }
+
+    /// The sum of two inputs' counters with the additive counters
+    /// wrapping on overflow in both build profiles — the last resort
+    /// once a total no longer fits, where `AddAssign` would panic under
+    /// debug assertions. The extremes merge as in `+=`.
+    pub fn wrapping_add(&self, other: Counts) -> Counts {
+        Counts {
+            lines: self.lines.wrapping_add(other.lines),
+            words: self.words.wrapping_add(other.words),
+            chars: self.chars.wrapping_add(other.chars),
+            bytes: self.bytes.wrapping_add(other.bytes),
+            max_line_length: self.max_line_length.max(other.max_line_length),
+            max_words_per_line: self.max_words_per_line.max(other.max_words_per_line),
+            min_words_per_line: merge_min(self.min_words_per_line, other.min_words_per_line),
+            unique_words: self.unique_words.wrapping_add(other.unique_words),
+            word_chars: self.word_chars.wrapping_add(other.word_chars),
+        }
+    }
+
+    /// The average word length in characters, or zero with no words.
+    pub fn avg_word_length(&self) -> f64 {
+        if self.words == 0 {
+            0.0
+        } else {
+            self.word_chars as f64 / self.words as f64
+        }
+    }
+}
+
+impl std::ops::AddAssign for Counts {
+    fn add_assign(&mut self, rhs: Counts) {
+        self.lines += rhs.lines;
+        self.words += rhs.words;
+        self.chars += rhs.chars;
+        self.bytes += rhs.bytes;
+        self.max_line_length = self.max_line_length.max(rhs.max_line_length);
+        self.max_words_per_line = self.max_words_per_line.max(rhs.max_words_per_line);
+        self.min_words_per_line = merge_min(self.min_words_per_line, rhs.min_words_per_line);
+        self.unique_words += rhs.unique_words;
+        self.word_chars += rhs.word_chars;
+    }
+}
+
+/// The smaller of two optional minimums; `None` means no line seen yet.
+fn merge_min(a: Option<u64>, b: Option<u64>) -> Option<u64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+/// Counters for one chunk of a larger input, with the boundary state needed
+/// to merge adjacent chunks.
+///
+/// `counts.words` is the number of word *starts* in the chunk assuming word
+/// state is clear at entry; `counts.max_line_length` covers only lines
+/// entirely inside the chunk. [`ChunkCounts::finish`] folds the boundary
+/// state back in to produce the real [`Counts`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ChunkCounts {
+    pub counts: Counts,
+    /// The chunk contains at least one character that affects word state.
+    /// When false the chunk is transparent: word state passes through it.
+    pub affects_word_state: bool,
+    /// First word-state-affecting character is a word character.
+    pub first_is_word: bool,
+    /// Word state at the end of the chunk (meaningful only when
+    /// `affects_word_state`).
+    pub last_is_word: bool,
+    /// Columns before the first line break (the whole chunk if none).
+    pub prefix_cols: u64,
+    /// Columns after the last line break (equal to `prefix_cols` if none).
+    pub suffix_cols: u64,
+    /// The chunk contains a column reset: newline, CR or FF.
+    pub has_line_break: bool,
+    /// Word starts after the last newline (the whole chunk if none),
+    /// tracked only when a words-per-line extreme is selected. Like the max
+    /// line length, words-per-line is only exact within a chunk.
+    pub suffix_words: u64,
+}
+
+impl ChunkCounts {
+    /// Combine two adjacent chunks. `self` precedes `other` in the input.
+    pub fn merge(self, other: ChunkCounts) -> ChunkCounts {
+        if self.counts.bytes == 0 {
+            return other;
+        }
+        if other.counts.bytes == 0 {
+            return self;
+        }
+        let mut counts = Counts {
+            lines: self.counts.lines + other.counts.lines,
+            words: self.counts.words + other.counts.words,
+            chars: self.counts.chars + other.counts.chars,
+            bytes: self.counts.bytes + other.counts.bytes,
+            max_line_length: self
+                .counts
+                .max_line_length
+                .max(other.counts.max_line_length),
+            max_words_per_line: self
+                .counts
+                .max_words_per_line
+                .max(other.counts.max_words_per_line),
+            min_words_per_line: merge_min(
+                self.counts.min_words_per_line,
+                other.counts.min_words_per_line,
+            ),
+            // Exact only because selecting unique words disables chunk
+            // splitting; summed here so merging an empty chunk is lossless.
+            unique_words: self.counts.unique_words + other.counts.unique_words,
+            word_chars: self.counts.word_chars + other.counts.word_chars,
+        };
+        // A word spanning the boundary was counted as a start on both sides.
+        if self.last_is_word && other.first_is_word {
+            counts.words -= 1;
+        }
+        // The line spanning the boundary is interior iff both sides contain
+        // a line break delimiting it.
+        let spanning = self.suffix_cols + other.prefix_cols;
+        let (prefix_cols, suffix_cols) = match (self.has_line_break, other.has_line_break) {
+            (true, true) => {
+                counts.max_line_length = counts.max_line_length.max(spanning);
+                (self.prefix_cols, other.suffix_cols)
+            }
+            (true, false) => (self.prefix_cols, spanning),
+            (false, true) => (spanning, other.suffix_cols),
+            (false, false) => (spanning, spanning),
+        };
+        ChunkCounts {
+            counts,
+            affects_word_state: self.affects_word_state || other.affects_word_state,
+            first_is_word: if self.affects_word_state {
+                self.first_is_word
+            } else {
+                other.first_is_word
+            },
+            last_is_word: if other.affects_word_state {
+                other.last_is_word
+            } else {
+                self.last_is_word
+            },
+            prefix_cols,
+            suffix_cols,
+            has_line_break: self.has_line_break || other.has_line_break,
+            suffix_words: if other.counts.lines > 0 {
+                other.suffix_words
+            } else {
+                self.suffix_words + other.suffix_words
+            },
+        }
+    }
+
+    /// Fold the boundary state into the counters, producing the final counts
+    /// for a complete input.
+    pub fn finish(self) -> Counts {
+        let mut counts = self.counts;
+        counts.max_line_length = counts
+            .max_line_length
+            .max(self.prefix_cols)
+            .max(self.suffix_cols);
+        // An unterminated final line counts toward the extremes only when
+        // it holds at least one word, so a trailing newline does not drag
+        // the minimum to zero.
+        if self.suffix_words > 0 {
+            counts.max_words_per_line = counts.max_words_per_line.max(self.suffix_words);
+            counts.min_words_per_line =
+                merge_min(counts.min_words_per_line, Some(self.suffix_words));
+        }
+        counts
+    }
+}
+
+/// The distinct-word tally behind [`Selection::unique_words`]. Tokens are
+/// delimited exactly as the `words` counter delimits them, so the two
+/// counters always agree on what a word is; non-printable characters pass
+/// through a token without joining it, as they pass through word state.
+#[derive(Debug, Default)]
+struct UniqueWords {
+    fold_case: bool,
+    token: String,
+    seen: std::collections::HashSet<String>,
+}
+
+impl UniqueWords {
+    fn new(fold_case: bool) -> Self {
+        UniqueWords {
+            fold_case,
+            ..UniqueWords::default()
+        }
+    }
+
+    /// Extend the token in progress with one word character.
+    fn push(&mut self, c: char) {
+        if self.fold_case {
+            self.token.extend(c.to_lowercase());
+        } else {
+            self.token.push(c);
+        }
+    }
+
+    /// A word boundary: file the token in progress, if any.
+    fn end_token(&mut self) {
+        if self.token.is_empty() {
+            return;
+        }
+        if self.seen.contains(self.token.as_str()) {
+            self.token.clear();
+        } else {
+            self.seen.insert(std::mem::take(&mut self.token));
+        }
+    }
+
+    /// The distinct-word count at end of input.
+    fn finish(mut self) -> u64 {
+        self.end_token();
+        self.seen.len() as u64
+    }
+}
+
+/// One scanned character, reduced to what the counters care about.
+enum Scanned {
+    /// `\n`: counts a line, resets columns, separates words.
+    Newline,
+    /// `\r` or `\f`: resets columns and separates words, no line counted.
+    LineReset,
+    /// `\t`: advances to the next tab stop, separates words.
+    Tab,
+    /// `\v`: separates words without advancing.
+    Separator,
+    /// Printable whitespace with the given display width.
+    Space(u8),
+    /// Printable word character with the given display width.
+    Word(char, u8),
+    /// Non-printable or undecodable: no effect on any counter but chars.
+    Ignored,
+}
+
+/// Length of the UTF-8 sequence introduced by `b`, or `None` if `b` cannot
+/// start a sequence.
+pub(crate) fn utf8_seq_len(b: u8) -> Option<usize> {
+    match b {
+        0x00..=0x7f => Some(1),
+        0xc2..=0xdf => Some(2),
+        0xe0..=0xef => Some(3),
+        0xf0..=0xf4 => Some(4),
+        _ => None,
+    }
+}
+
+/// How the UTF-8 scan divides work between bulk ASCII byte classification
+/// and full character decoding when input mixes scripts. For ASCII the
+/// two classifiers agree, so runs of it can skip decoding — but flipping
+/// between the loops has a cost, and the profitable granularity depends
+/// on how the scripts interleave: mostly-ASCII text wants eager
+/// switching, dense Unicode text wants to stay on the decoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FallbackPolicy {
+    /// Decode every character; no bulk ASCII handling. Best when ASCII
+    /// is rare.
+    AlwaysScalar,
+    /// Classify ASCII runs of at least this many bytes in bulk and decode
+    /// only the spans between them, so isolated ASCII bytes inside
+    /// Unicode text do not thrash the loop choice.
+    AsciiRuns { min_run: usize },
+    /// Sample the input in fixed windows and hold a sustained regime —
+    /// bulk ASCII handling or pure decoding — switching only after
+    /// several consecutive samples disagree with it. The hysteresis keeps
+    /// documents that alternate between scripts from flipping loops at
+    /// every boundary; a streaming counter carries the regime across
+    /// updates.
+    Adaptive,
+}
+
+impl Default for FallbackPolicy {
+    fn default() -> Self {
+        // One widest-vector width: runs shorter than a SIMD register are
+        // not worth leaving the decoder for.
+        FallbackPolicy::AsciiRuns { min_run: 64 }
+    }
+}
+
+/// Drive `f` over every character of `data` under the given mode. Every
+/// policy produces the same classifications; only the loop structure
+/// differs.
+fn scan_chars(data: &[u8], mode: CountMode, policy: FallbackPolicy, f: &mut impl FnMut(Scanned)) {
+    scan_chars_stateful(data, mode, policy, &mut AdaptiveState::default(), f);
+}
+
+/// [`scan_chars`] with the adaptive controller's state held by the
+/// caller, so a streaming counter keeps its regime across updates.
+fn scan_chars_stateful(
+    data: &[u8],
+    mode: CountMode,
+    policy: FallbackPolicy,
+    adaptive: &mut AdaptiveState,
+    f: &mut impl FnMut(Scanned),
+) {
+    match mode {
+        CountMode::Bytes => {
+            for &b in data {
+                f(classify_byte(b));
+            }
+        }
+        CountMode::Utf8 => match policy {
+            FallbackPolicy::AlwaysScalar => scan_utf8(data, f),
+            FallbackPolicy::AsciiRuns { min_run } => scan_ascii_runs(data, min_run, f),
+            FallbackPolicy::Adaptive => {
+                let mut rest = data;
+                while !rest.is_empty() {
+                    let sample = adaptive_sample_len(rest);
+                    let (head, tail) = rest.split_at(sample);
+                    if adaptive.bulk {
+                        scan_ascii_runs(head, ADAPTIVE_BULK_MIN_RUN, f);
+                    } else {
+                        scan_utf8(head, f);
+                    }
+                    adaptive.observe(head);
+                    rest = tail;
+                }
+            }
+        },
+    }
+}
+
+/// The size the adaptive controller samples the input in.
+const ADAPTIVE_SAMPLE: usize = 4096;
+/// Consecutive disagreeing samples before the controller switches regime.
+const ADAPTIVE_HYSTERESIS: u8 = 4;
+/// The run threshold the bulk regime uses within a sample. Small, so
+/// Unicode bytes inside an ASCII-dominant sample still decode without the
+/// controller having to notice them.
+const ADAPTIVE_BULK_MIN_RUN: usize = 16;
+
+/// Regime state for [`FallbackPolicy::Adaptive`]: which loop is in force
+/// and how many recent samples have voted against it.
+#[derive(Debug, Clone, Copy)]
+struct AdaptiveState {
+    /// True while the bulk-ASCII regime holds.
+    bulk: bool,
+    /// Consecutive samples that disagreed with the regime.
+    streak: u8,
+}
+
+impl Default for AdaptiveState {
+    fn default() -> Self {
+        // Most input is ASCII; start in the bulk regime.
+        AdaptiveState {
+            bulk: true,
+            streak: 0,
+        }
+    }
+}
+
+impl AdaptiveState {
+    /// Fold one sample's composition into the regime decision.
+    fn observe(&mut self, sample: &[u8]) {
+        let non_ascii = sample.iter().filter(|b| !b.is_ascii()).count();
+        // ASCII-dominant: non-ASCII under ~3% of the sample.
+        let ascii_heavy = non_ascii * 32 < sample.len();
+        if ascii_heavy == self.bulk {
+            self.streak = 0;
+        } else {
+            self.streak += 1;
+            if self.streak >= ADAPTIVE_HYSTERESIS {
+                self.bulk = ascii_heavy;
+                self.streak = 0;
+            }
+        }
+    }
+}
+
+/// Length of the next adaptive sample: [`ADAPTIVE_SAMPLE`] backed off to
+/// a non-continuation byte so no UTF-8 sequence spans two samples.
+fn adaptive_sample_len(data: &[u8]) -> usize {
+    if data.len() <= ADAPTIVE_SAMPLE {
+        return data.len();
+    }
+    let mut end = ADAPTIVE_SAMPLE;
+    while end > 0 && data[end] & 0xc0 == 0x80 {
+        end -= 1;
+    }
+    if end == 0 {
+        data.len()
+    } else {
+        end
+    }
+}
+
+/// The [`FallbackPolicy::AsciiRuns`] loop: bulk-classify ASCII runs of at
+/// least `min_run` bytes, decode the spans between them.
+fn scan_ascii_runs(data: &[u8], min_run: usize, f: &mut impl FnMut(Scanned)) {
+    let min_run = min_run.max(1);
+    let mut rest = data;
+    while !rest.is_empty() {
+        let run = ascii_run_len(rest);
+        if run >= min_run || run == rest.len() {
+            for &b in &rest[..run] {
+                f(classify_byte(b));
+            }
+            rest = &rest[run..];
+        } else {
+            let span = scalar_span_len(rest, run, min_run);
+            scan_utf8(&rest[..span], f);
+            rest = &rest[span..];
+        }
+    }
+}
+
+/// The scalar decode path: every character through [`classify_char`],
+/// undecodable bytes as [`Scanned::Ignored`].
+fn scan_utf8(data: &[u8], f: &mut impl FnMut(Scanned)) {
+    for chunk in data.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            f(classify_char(c));
+        }
+        for _ in chunk.invalid() {
+            f(Scanned::Ignored);
+        }
+    }
+}
+
+/// Length of the leading all-ASCII run.
+fn ascii_run_len(data: &[u8]) -> usize {
+    data.iter().take_while(|b| b.is_ascii()).count()
+}
+
+/// Where the scalar span opened by a too-short ASCII run at `from` ends:
+/// at the start of the next ASCII run of `min_run` bytes, or the end of
+/// `data`. The boundary is always an ASCII byte, and UTF-8 sequences
+/// never span one, so both sides stay independently decodable.
+fn scalar_span_len(data: &[u8], from: usize, min_run: usize) -> usize {
+    let mut run_start = 0;
+    let mut run = 0usize;
+    for (i, b) in data.iter().enumerate().skip(from) {
+        if b.is_ascii() {
+            if run == 0 {
+                run_start = i;
+            }
+            run += 1;
+            if run >= min_run {
+                return run_start;
+            }
+        } else {
+            run = 0;
+        }
+    }
+    data.len()
+}
+
+fn classify_byte(b: u8) -> Scanned {
+    match b {
+        b'\n' => Scanned::Newline,
+        b'\r' | b'\x0c' => Scanned::LineReset,
+        b'\t' => Scanned::Tab,
+        b'\x0b' => Scanned::Separator,
+        b' ' => Scanned::Space(1),
+        0x21..=0x7e => Scanned::Word(b as char, 1),
+        _ => Scanned::Ignored,
+    }
+}
+
+fn classify_char(c: char) -> Scanned {
+    match c {
+        '\n' => Scanned::Newline,
+        '\r' | '\x0c' => Scanned::LineReset,
+        '\t' => Scanned::Tab,
+        '\x0b' => Scanned::Separator,
+        _ => match c.width() {
+            None => Scanned::Ignored,
+            Some(w) if crate::unicode_data::is_white_space(c) => Scanned::Space(w as u8),
+            Some(w) => Scanned::Word(c, w as u8),
+        },
+    }
+}
+
+/// Carriage returns not followed by a newline — the terminators
+/// [`Selection::count_cr_lines`] adds to the line count. A `\r` ending
+/// `data` counts as lone; streaming callers correct it when the next
+/// buffer opens with `\n`.
+fn lone_cr_count(data: &[u8], kernels: Kernels) -> u64 {
+    memchr::memchr_iter(b'\r', data).count() as u64 - kernels.count_crlf(data)
+}
+
+/// Count one chunk of input, producing mergeable per-chunk counters.
+///
+/// When the selection does not need the word/column scan this takes a bulk
+/// byte-classification fast path on the given backend.
+pub fn count_chunk(
+    data: &[u8],
+    sel: Selection,
+    mode: CountMode,
+    backend: CountingBackend,
+) -> ChunkCounts {
+    count_chunk_with_unit(data, sel, mode, CharUnit::for_mode(mode), backend)
+}
+
+/// Like [`count_chunk`], with an explicit char unit.
+pub fn count_chunk_with_unit(
+    data: &[u8],
+    sel: Selection,
+    mode: CountMode,
+    unit: CharUnit,
+    backend: CountingBackend,
+) -> ChunkCounts {
+    count_chunk_impl(
+        data,
+        sel,
+        mode,
+        unit,
+        backend,
+        DEFAULT_TAB_WIDTH,
+        FallbackPolicy::default(),
+    )
+}
+
+fn count_chunk_impl(
+    data: &[u8],
+    sel: Selection,
+    mode: CountMode,
+    unit: CharUnit,
+    backend: CountingBackend,
+    tab_width: u64,
+    policy: FallbackPolicy,
+) -> ChunkCounts {
+    let mut out = ChunkCounts {
+        counts: Counts {
+            bytes: data.len() as u64,
+            ..Counts::default()
+        },
+        ..ChunkCounts::default()
+    };
+    let kernels = backend.kernels();
+    out.counts.chars = count_chars(data, unit, kernels);
+    if sel.count_cr_lines {
+        out.counts.lines = lone_cr_count(data, kernels);
+    }
+    if !sel.needs_scan() {
+        out.counts.lines += kernels.count_lines(data);
+        out.has_line_break = out.counts.lines > 0;
+        return out;
+    }
+    let track_line_words = sel.words_per_line();
+    let mut in_word = false;
+    let mut cols = 0u64;
+    let mut interior_max = 0u64;
+    let mut line_words = 0u64;
+    let mut unique = sel.unique_words.then(|| UniqueWords::new(sel.fold_case));
+    scan_chars(data, mode, policy, &mut |s| {
+        let affects_word = !matches!(s, Scanned::Ignored);
+        if affects_word && !out.affects_word_state {
+            out.affects_word_state = true;
+            out.first_is_word = matches!(s, Scanned::Word(..));
+        }
+        if let Some(unique) = unique.as_mut() {
+            match s {
+                Scanned::Word(c, _) => unique.push(c),
+                Scanned::Ignored => {}
+                _ => unique.end_token(),
+            }
+        }
+        match s {
+            Scanned::Newline | Scanned::LineReset => {
+                if matches!(s, Scanned::Newline) {
+                    out.counts.lines += 1;
+                    // Records end at newlines only; \r and \f reset
+                    // columns but not the words-per-line tally.
+                    if track_line_words {
+                        out.counts.max_words_per_line =
+                            out.counts.max_words_per_line.max(line_words);
+                        out.counts.min_words_per_line =
+                            merge_min(out.counts.min_words_per_line, Some(line_words));
+                    }
+                    line_words = 0;
+                }
+                if out.has_line_break {
+                    interior_max = interior_max.max(cols);
+                } else {
+                    out.prefix_cols = cols;
+                    out.has_line_break = true;
+                }
+                cols = 0;
+                in_word = false;
+            }
+            Scanned::Tab => {
+                cols = (cols / tab_width + 1) * tab_width;
+                in_word = false;
+            }
+            Scanned::Separator => {
+                in_word = false;
+            }
+            Scanned::Space(w) => {
+                cols += u64::from(w);
+                in_word = false;
+            }
+            Scanned::Word(_, w) => {
+                cols += u64::from(w);
+                if sel.avg_word_length {
+                    out.counts.word_chars += 1;
+                }
+                if !in_word {
+                    out.counts.words += 1;
+                    line_words += 1;
+                    in_word = true;
+                }
+            }
+            Scanned::Ignored => {}
+        }
+    });
+    out.last_is_word = in_word;
+    out.suffix_cols = cols;
+    if track_line_words {
+        out.suffix_words = line_words;
+    }
+    if !out.has_line_break {
+        out.prefix_cols = cols;
+    }
+    out.counts.max_line_length = interior_max;
+    if let Some(unique) = unique {
+        out.counts.unique_words = unique.finish();
+    }
+    out
+}
+
+/// Count a complete in-memory input. Exact for all counters.
+pub fn count_slice(
+    data: &[u8],
+    sel: Selection,
+    mode: CountMode,
+    backend: CountingBackend,
+) -> Counts {
+    count_chunk(data, sel, mode, backend).finish()
+}
+
+/// Count discontiguous buffers as if they were one concatenated input:
+/// word state and UTF-8 sequences split across slice boundaries carry
+/// over through the streaming scanner. Rope structures and `readv`-style
+/// buffer rings count without gathering into one allocation; `IoSlice`
+/// arrays work directly since they deref to byte slices.
+pub fn count_slices<'a, I>(
+    slices: I,
+    sel: Selection,
+    mode: CountMode,
+    backend: CountingBackend,
+) -> Counts
+where
+    I: IntoIterator<Item = &'a [u8]>,
+{
+    let mut counter = StreamCounter::new(sel, mode, backend);
+    for slice in slices {
+        counter.update(slice);
+    }
+    counter.finish()
+}
+
+/// Like [`count_slice`], with an explicit char unit.
+pub fn count_slice_with_unit(
+    data: &[u8],
+    sel: Selection,
+    mode: CountMode,
+    unit: CharUnit,
+    backend: CountingBackend,
+) -> Counts {
+    count_chunk_with_unit(data, sel, mode, unit, backend).finish()
+}
+
+/// Like [`count_slice`], with tab stops every `tab_width` columns instead
+/// of [`DEFAULT_TAB_WIDTH`]. Only the max-line-length counter is affected.
+pub fn count_slice_with_tab_width(
+    data: &[u8],
+    sel: Selection,
+    mode: CountMode,
+    backend: CountingBackend,
+    tab_width: u64,
+) -> Counts {
+    count_chunk_impl(
+        data,
+        sel,
+        mode,
+        CharUnit::for_mode(mode),
+        backend,
+        tab_width,
+        FallbackPolicy::default(),
+    )
+    .finish()
+}
+
+/// Like [`count_slice`], with an explicit ASCII fallback policy. The
+/// counts never depend on the policy; this exists so benchmarks can
+/// compare loop structures on their own corpora.
+pub fn count_slice_with_policy(
+    data: &[u8],
+    sel: Selection,
+    mode: CountMode,
+    backend: CountingBackend,
+    policy: FallbackPolicy,
+) -> Counts {
+    count_chunk_impl(
+        data,
+        sel,
+        mode,
+        CharUnit::for_mode(mode),
+        backend,
+        DEFAULT_TAB_WIDTH,
+        policy,
+    )
+    .finish()
+}
+
+/// A counting backend produced different counters than the scalar
+/// reference, which a healthy CPU should make impossible.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackendMismatch {
+    pub backend: CountingBackend,
+    pub got: Counts,
+    pub expected: Counts,
+}
+
+impl std::fmt::Display for BackendMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?} backend disagrees with the scalar reference: got {:?}, expected {:?}",
+            self.backend, self.got, self.expected
+        )
+    }
+}
+
+impl std::error::Error for BackendMismatch {}
+
+/// Count `data` with `backend` and cross-check the result against the
+/// scalar reference implementation. This is the safety net behind the
+/// binary's `--verify` flag and `self-test` subcommand, for CPUs whose SIMD
+/// paths misbehave.
+pub fn verify_slice(
+    data: &[u8],
+    sel: Selection,
+    mode: CountMode,
+    backend: CountingBackend,
+    tab_width: u64,
+) -> Result<Counts, Box<BackendMismatch>> {
+    let got = count_slice_with_tab_width(data, sel, mode, backend, tab_width);
+    if backend == CountingBackend::Scalar {
+        return Ok(got);
+    }
+    let expected = count_slice_with_tab_width(data, sel, mode, CountingBackend::Scalar, tab_width);
+    if got == expected {
+        Ok(got)
+    } else {
+        // Boxed so the happy path's `Result` stays a couple of words wide.
+        Err(Box::new(BackendMismatch {
+            backend,
+            got,
+            expected,
+        }))
+    }
+}
+
+/// Adjust a proposed split offset forward so the split falls on a UTF-8
+/// character boundary (a non-continuation byte). Splitting inside a valid
+/// multi-byte character would change how both halves classify its bytes.
+pub fn split_point(data: &[u8], target: usize) -> usize {
+    let mut at = target.min(data.len());
+    while at < data.len() && data[at] & 0xc0 == 0x80 {
+        at += 1;
+    }
+    at
+}
+
+/// Incremental counter for sequential reads.
+///
+/// Feed data with [`StreamCounter::update`] and call
+/// [`StreamCounter::finish`] at end of input. Incomplete trailing UTF-8
+/// sequences are buffered between updates so character classification never
+/// sees a split character.
+#[derive(Debug)]
+pub struct StreamCounter {
+    sel: Selection,
+    mode: CountMode,
+    unit: CharUnit,
+    /// The backend's kernel table, resolved once at construction.
+    kernels: Kernels,
+    tab_width: u64,
+    policy: FallbackPolicy,
+    /// Regime carried across updates for [`FallbackPolicy::Adaptive`];
+    /// idle under the other policies. A performance hint only, so it is
+    /// not part of [`StreamState`].
+    adaptive: AdaptiveState,
+    counts: Counts,
+    in_word: bool,
+    cols: u64,
+    /// Word starts on the current (unterminated) line.
+    line_words: u64,
+    pending: [u8; 4],
+    pending_len: usize,
+    /// Text of the cluster still open at the end of the consumed input,
+    /// used only for [`CharUnit::Graphemes`].
+    grapheme_carry: String,
+    /// Whether the last consumed byte was `\r`, used only for
+    /// [`Selection::count_cr_lines`]: the CR is provisionally counted as a
+    /// lone terminator and uncounted if the next update opens with `\n`.
+    last_was_cr: bool,
+    /// The distinct-word set, kept only when selected. Not part of
+    /// [`StreamState`]: a snapshot cannot carry the set, so resumable
+    /// counting and unique words exclude each other.
+    unique: Option<UniqueWords>,
+}
+
+impl StreamCounter {
+    pub fn new(sel: Selection, mode: CountMode, backend: CountingBackend) -> Self {
+        StreamCounter {
+            sel,
+            mode,
+            unit: CharUnit::for_mode(mode),
+            kernels: backend.kernels(),
+            tab_width: DEFAULT_TAB_WIDTH,
+            policy: FallbackPolicy::default(),
+            adaptive: AdaptiveState::default(),
+            counts: Counts::default(),
+            in_word: false,
+            cols: 0,
+            line_words: 0,
+            pending: [0; 4],
+            pending_len: 0,
+            grapheme_carry: String::new(),
+            last_was_cr: false,
+            unique: sel.unique_words.then(|| UniqueWords::new(sel.fold_case)),
+        }
+    }
+
+    /// Count `chars` in the given unit instead of the mode's default.
+    pub fn with_char_unit(mut self, unit: CharUnit) -> Self {
+        self.unit = unit;
+        self
+    }
+
+    /// Expand tabs to stops every `width` columns instead of
+    /// [`DEFAULT_TAB_WIDTH`].
+    pub fn with_tab_width(mut self, width: u64) -> Self {
+        self.tab_width = width;
+        self
+    }
+
+    /// Scan with the given ASCII fallback policy instead of
+    /// [`FallbackPolicy::default`]. Counts are identical either way.
+    pub fn with_fallback_policy(mut self, policy: FallbackPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Snapshot the counter's mutable state between updates, so a caller can
+    /// persist it and later resume with [`StreamCounter::with_state`].
+    pub fn state(&self) -> StreamState {
+        StreamState {
+            counts: self.counts,
+            in_word: self.in_word,
+            cols: self.cols,
+            line_words: self.line_words,
+            pending: self.pending[..self.pending_len].to_vec(),
+            grapheme_carry: self.grapheme_carry.clone(),
+            last_was_cr: self.last_was_cr,
+        }
+    }
+
+    /// Continue from a snapshot taken by [`StreamCounter::state`]. Pending
+    /// bytes past a UTF-8 sequence's four are dropped rather than trusted.
+    pub fn with_state(mut self, state: StreamState) -> Self {
+        self.counts = state.counts;
+        self.in_word = state.in_word;
+        self.cols = state.cols;
+        self.line_words = state.line_words;
+        self.pending_len = state.pending.len().min(self.pending.len());
+        self.pending[..self.pending_len].copy_from_slice(&state.pending[..self.pending_len]);
+        self.grapheme_carry = state.grapheme_carry;
+        self.last_was_cr = state.last_was_cr;
+        self
+    }
+
+    pub fn update(&mut self, buf: &[u8]) {
+        self.counts.bytes += buf.len() as u64;
+        if self.sel.count_cr_lines && !buf.is_empty() {
+            if self.last_was_cr && buf[0] == b'\n' {
+                // The carried \r was the first half of a CRLF after all.
+                self.counts.lines -= 1;
+            }
+            self.counts.lines += lone_cr_count(buf, self.kernels);
+            self.last_was_cr = buf[buf.len() - 1] == b'\r';
+        }
+        if self.mode == CountMode::Bytes && self.unit == CharUnit::Bytes {
+            self.counts.chars += buf.len() as u64;
+            if self.sel.needs_scan() {
+                self.scan(buf);
+            } else {
+                self.counts.lines += self.kernels.count_lines(buf);
+            }
+            return;
+        }
+        if !self.sel.needs_scan() {
+            self.counts.lines += self.kernels.count_lines(buf);
+            if !self.sel.chars {
+                return;
+            }
+        }
+        // Align to character boundaries: complete a pending sequence, then
+        // hold back any incomplete trailing sequence for the next update.
+        let mut rest = buf;
+        if self.pending_len > 0 {
+            let want = utf8_seq_len(self.pending[0]).unwrap_or(1);
+            // Absorb only continuation bytes: a non-continuation byte ends
+            // the (then invalid) sequence early and must be rescanned.
+            let take = rest
+                .iter()
+                .take(want - self.pending_len)
+                .take_while(|&&b| b & 0xc0 == 0x80)
+                .count();
+            self.pending[self.pending_len..self.pending_len + take].copy_from_slice(&rest[..take]);
+            self.pending_len += take;
+            rest = &rest[take..];
+            if self.pending_len < want && rest.is_empty() {
+                return;
+            }
+            let pending = self.pending;
+            let len = self.pending_len;
+            self.pending_len = 0;
+            self.consume(&pending[..len]);
+        }
+        let keep = incomplete_suffix_len(rest);
+        let (body, tail) = rest.split_at(rest.len() - keep);
+        self.consume(body);
+        self.pending[..keep].copy_from_slice(tail);
+        self.pending_len = keep;
+    }
+
+    /// Process one character-aligned group of UTF-8 input.
+    fn consume(&mut self, data: &[u8]) {
+        match self.unit {
+            CharUnit::Bytes => self.counts.chars += data.len() as u64,
+            CharUnit::ScalarValues => self.counts.chars += self.kernels.count_utf8_chars(data),
+            CharUnit::Graphemes => self.consume_graphemes(data),
+        }
+        if self.sel.needs_scan() {
+            self.scan(data);
+        }
+    }
+
+    /// Count clusters incrementally. The last cluster seen so far may still
+    /// be extended by later input (a combining mark, a ZWJ continuation, the
+    /// LF of a CRLF), so it stays buffered until more text or end of input
+    /// settles it.
+    fn consume_graphemes(&mut self, data: &[u8]) {
+        use unicode_segmentation::UnicodeSegmentation;
+        for chunk in data.utf8_chunks() {
+            self.grapheme_carry.push_str(chunk.valid());
+            if !chunk.invalid().is_empty() {
+                // Undecodable bytes close any cluster in progress.
+                self.counts.chars += self.grapheme_carry.graphemes(true).count() as u64;
+                self.grapheme_carry.clear();
+            } else if let Some((start, _)) = self.grapheme_carry.grapheme_indices(true).next_back()
+            {
+                self.counts.chars += self.grapheme_carry[..start].graphemes(true).count() as u64;
+                self.grapheme_carry.drain(..start);
+            }
+        }
+    }
+
+    fn scan(&mut self, data: &[u8]) {
+        let track_line_words = self.sel.words_per_line();
+        let track_word_chars = self.sel.avg_word_length;
+        let tab_width = self.tab_width;
+        let counts = &mut self.counts;
+        let in_word = &mut self.in_word;
+        let cols = &mut self.cols;
+        let line_words = &mut self.line_words;
+        let unique = &mut self.unique;
+        let adaptive = &mut self.adaptive;
+        scan_chars_stateful(data, self.mode, self.policy, adaptive, &mut |s| {
+            if let Some(unique) = unique.as_mut() {
+                match s {
+                    Scanned::Word(c, _) => unique.push(c),
+                    Scanned::Ignored => {}
+                    _ => unique.end_token(),
+                }
+            }
+            match s {
+                Scanned::Newline | Scanned::LineReset => {
+                    if matches!(s, Scanned::Newline) {
+                        counts.lines += 1;
+                        if track_line_words {
+                            counts.max_words_per_line = counts.max_words_per_line.max(*line_words);
+                            counts.min_words_per_line =
+                                merge_min(counts.min_words_per_line, Some(*line_words));
+                        }
+                        *line_words = 0;
+                    }
+                    counts.max_line_length = counts.max_line_length.max(*cols);
+                    *cols = 0;
+                    *in_word = false;
+                }
+                Scanned::Tab => {
+                    *cols = (*cols / tab_width + 1) * tab_width;
+                    *in_word = false;
+                }
+                Scanned::Separator => {
+                    *in_word = false;
+                }
+                Scanned::Space(w) => {
+                    *cols += u64::from(w);
+                    *in_word = false;
+                }
+                Scanned::Word(_, w) => {
+                    *cols += u64::from(w);
+                    if track_word_chars {
+                        counts.word_chars += 1;
+                    }
+                    if !*in_word {
+                        counts.words += 1;
+                        *line_words += 1;
+                        *in_word = true;
+                    }
+                }
+                Scanned::Ignored => {}
+            }
+        });
+    }
+
+    pub fn finish(mut self) -> Counts {
+        if self.pending_len > 0 {
+            let pending = self.pending;
+            let len = self.pending_len;
+            self.pending_len = 0;
+            self.consume(&pending[..len]);
+        }
+        if !self.grapheme_carry.is_empty() {
+            use unicode_segmentation::UnicodeSegmentation;
+            self.counts.chars += self.grapheme_carry.graphemes(true).count() as u64;
+        }
+        self.counts.max_line_length = self.counts.max_line_length.max(self.cols);
+        if self.sel.words_per_line() && self.line_words > 0 {
+            self.counts.max_words_per_line = self.counts.max_words_per_line.max(self.line_words);
+            self.counts.min_words_per_line =
+                merge_min(self.counts.min_words_per_line, Some(self.line_words));
+        }
+        if let Some(unique) = self.unique.take() {
+            self.counts.unique_words = unique.finish();
+        }
+        self.counts
+    }
+}
+
+/// A [`StreamCounter`]'s mutable state, detached from the counter so it can
+/// outlive the process: checkpointing tools persist it and rebuild a counter
+/// that picks up mid-word and mid-character.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct StreamState {
+    pub counts: Counts,
+    pub in_word: bool,
+    /// Columns since the last line break.
+    pub cols: u64,
+    /// Word starts on the current (unterminated) line.
+    pub line_words: u64,
+    /// Bytes of an incomplete trailing UTF-8 sequence (at most three).
+    pub pending: Vec<u8>,
+    /// Text of the still-open grapheme cluster, used only for
+    /// [`CharUnit::Graphemes`].
+    pub grapheme_carry: String,
+    /// Whether the last consumed byte was `\r`, used only for
+    /// [`Selection::count_cr_lines`].
+    pub last_was_cr: bool,
+}
+
+/// Number of trailing bytes of `data` that form an incomplete (but so far
+/// valid) UTF-8 sequence and should be carried to the next read.
+pub(crate) fn incomplete_suffix_len(data: &[u8]) -> usize {
+    for back in 1..=3.min(data.len()) {
+        let b = data[data.len() - back];
+        if b & 0xc0 != 0x80 {
+            // Found the lead byte `back - 1` continuations from the end.
+            return match utf8_seq_len(b) {
+                Some(want) if want > back => back,
+                _ => 0,
+            };
+        }
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const ALL: Selection = Selection {
+        lines: true,
+        words: true,
+        chars: true,
+        bytes: true,
+        max_line_length: true,
+        max_words_per_line: false,
+        min_words_per_line: false,
+        unique_words: false,
+        avg_word_length: false,
+        fold_case: false,
+        count_cr_lines: false,
+    };
+
+    const WITH_WPL: Selection = Selection {
+        max_words_per_line: true,
+        min_words_per_line: true,
+        ..ALL
+    };
+
+    fn count_all(data: &[u8]) -> Counts {
+        count_slice(data, ALL, CountMode::Utf8, CountingBackend::Scalar)
+    }
+
+    #[test]
+    fn empty_input() {
+        assert_eq!(count_all(b""), Counts::default());
+    }
+
+    #[test]
+    fn basic_ascii() {
+        let c = count_all(b"hello world\nsecond line\n");
+        assert_eq!(c.lines, 2);
+        assert_eq!(c.words, 4);
+        assert_eq!(c.chars, 24);
+        assert_eq!(c.bytes, 24);
+        assert_eq!(c.max_line_length, 11);
+    }
+
+    #[test]
+    fn scattered_slices_count_like_their_concatenation() {
+        // Every split lands somewhere awkward: inside a word, inside a
+        // multi-byte character, and between the CR and LF of a pair.
+        let data = "héllo wörld\r\nsecond line\n".as_bytes();
+        let whole = count_all(data);
+        for splits in [&[3, 7][..], &[1, 2, 13][..], &[12][..]] {
+            let mut slices = Vec::new();
+            let mut start = 0;
+            for &end in splits {
+                slices.push(&data[start..end]);
+                start = end;
+            }
+            slices.push(&data[start..]);
+            assert_eq!(
+                count_slices(slices, ALL, CountMode::Utf8, CountingBackend::Scalar),
+                whole
+            );
+        }
+        // IoSlice arrays deref straight to byte slices.
+        let bufs = [
+            std::io::IoSlice::new(b"one "),
+            std::io::IoSlice::new(b"two\n"),
+        ];
+        let c = count_slices(
+            bufs.iter().map(|buf| &**buf),
+            ALL,
+            CountMode::Utf8,
+            CountingBackend::Scalar,
+        );
+        assert_eq!(c.words, 2);
+        assert_eq!(c.lines, 1);
+    }
+
+    #[test]
+    fn missing_final_newline_counts_last_line_length() {
+        let c = count_all(b"ab\nabcd");
+        assert_eq!(c.lines, 1);
+        assert_eq!(c.max_line_length, 4);
+    }
+
+    #[test]
+    fn tabs_advance_to_tab_stops() {
+        let c = count_all(b"a\tb\n");
+        assert_eq!(c.max_line_length, 9);
+    }
+
+    #[test]
+    fn tab_size_overrides_the_default_stops() {
+        let data = b"a\tb\n";
+        let c = count_slice_with_tab_width(data, ALL, CountMode::Utf8, CountingBackend::Scalar, 4);
+        assert_eq!(c.max_line_length, 5);
+        let mut sc =
+            StreamCounter::new(ALL, CountMode::Utf8, CountingBackend::Scalar).with_tab_width(4);
+        sc.update(data);
+        assert_eq!(sc.finish().max_line_length, 5);
+        // The default matches GNU's eight-column stops.
+        let c = count_all(data);
+        assert_eq!(c.max_line_length, 9);
+    }
+
+    #[test]
+    fn carriage_return_resets_columns_without_a_line() {
+        let c = count_all(b"aaaa\rzz\n");
+        assert_eq!(c.lines, 1);
+        assert_eq!(c.max_line_length, 4);
+        assert_eq!(c.words, 2);
+    }
+
+    #[test]
+    fn vertical_tab_separates_without_advancing() {
+        let c = count_all(b"ab\x0bcd\n");
+        assert_eq!(c.words, 2);
+        assert_eq!(c.max_line_length, 4);
+    }
+
+    #[test]
+    fn non_printable_bytes_are_ignored() {
+        // Control characters neither start nor end words, as in GNU wc.
+        assert_eq!(count_all(b"a\x01b \x01\n").words, 1);
+        assert_eq!(count_all(b"\x01\x02\n").max_line_length, 0);
+    }
+
+    #[test]
+    fn utf8_chars_and_words() {
+        let data = "héllo wörld\u{2003}x".as_bytes();
+        let c = count_all(data);
+        assert_eq!(c.words, 3);
+        assert_eq!(c.chars, 13);
+        assert_eq!(c.bytes, data.len() as u64);
+    }
+
+    #[test]
+    fn wide_chars_take_two_columns() {
+        let c = count_all("你好\n".as_bytes());
+        assert_eq!(c.chars, 3);
+        assert_eq!(c.max_line_length, 4);
+        assert_eq!(c.words, 1);
+    }
+
+    #[test]
+    fn invalid_utf8_counts_no_chars_and_no_words() {
+        let c = count_all(b"a \xff\xfe b\n");
+        assert_eq!(c.words, 2);
+        assert_eq!(c.chars, 5);
+        assert_eq!(c.bytes, 7);
+    }
+
+    #[test]
+    fn bytes_mode_counts_every_byte_as_a_char() {
+        // U+2003 EM SPACE is whitespace in UTF-8 mode; its bytes are ignored
+        // (non-printable) in single-byte mode, so they do not split words.
+        let data = "a\u{2003}b".as_bytes();
+        let utf8 = count_slice(data, ALL, CountMode::Utf8, CountingBackend::Scalar);
+        let bytes = count_slice(data, ALL, CountMode::Bytes, CountingBackend::Scalar);
+        assert_eq!(utf8.words, 2);
+        assert_eq!(utf8.chars, 3);
+        assert_eq!(bytes.words, 1);
+        assert_eq!(bytes.chars, data.len() as u64);
+    }
+
+    #[test]
+    fn words_per_line_extremes_track_each_line() {
+        let c = count_slice(
+            b"one two three\nfour\n\nfive six\n",
+            WITH_WPL,
+            CountMode::Utf8,
+            CountingBackend::Scalar,
+        );
+        assert_eq!(c.max_words_per_line, 3);
+        // The empty line has zero words and sets the minimum.
+        assert_eq!(c.min_words_per_line, Some(0));
+    }
+
+    #[test]
+    fn words_per_line_counts_an_unterminated_last_line_with_words() {
+        let c = count_slice(
+            b"a b c\nd e",
+            WITH_WPL,
+            CountMode::Utf8,
+            CountingBackend::Scalar,
+        );
+        assert_eq!(c.max_words_per_line, 3);
+        assert_eq!(c.min_words_per_line, Some(2));
+        // Trailing whitespace without words is not a line.
+        let c = count_slice(
+            b"a b\n  ",
+            WITH_WPL,
+            CountMode::Utf8,
+            CountingBackend::Scalar,
+        );
+        assert_eq!(c.min_words_per_line, Some(2));
+        // No lines at all leaves the minimum unset.
+        let c = count_slice(b"", WITH_WPL, CountMode::Utf8, CountingBackend::Scalar);
+        assert_eq!(c.min_words_per_line, None);
+    }
+
+    #[test]
+    fn unique_words_count_distinct_tokens() {
+        const UNIQUE: Selection = Selection {
+            unique_words: true,
+            ..ALL
+        };
+        let data = "the cat the dog The\n".as_bytes();
+        let c = count_slice(data, UNIQUE, CountMode::Utf8, CountingBackend::Scalar);
+        assert_eq!(c.words, 5);
+        assert_eq!(c.unique_words, 4); // the, cat, dog, The
+        let folded = count_slice(
+            data,
+            Selection {
+                fold_case: true,
+                ..UNIQUE
+            },
+            CountMode::Utf8,
+            CountingBackend::Scalar,
+        );
+        assert_eq!(folded.unique_words, 3);
+        // A non-printable byte passes through a token without splitting it,
+        // exactly as it passes through word state.
+        let c = count_slice(
+            b"ab a\x01b\n",
+            UNIQUE,
+            CountMode::Utf8,
+            CountingBackend::Scalar,
+        );
+        assert_eq!(c.unique_words, 1);
+    }
+
+    #[test]
+    fn cr_lines_count_lone_carriage_returns() {
+        const WITH_CR: Selection = Selection {
+            count_cr_lines: true,
+            ..ALL
+        };
+        // One lone CR, one CRLF (counted once, via its \n), one LF.
+        let data = b"mac\rstyle\r\nmixed\n";
+        assert_eq!(count_all(data).lines, 2);
+        let c = count_slice(data, WITH_CR, CountMode::Utf8, CountingBackend::Scalar);
+        assert_eq!(c.lines, 3);
+        // The modifier also applies on the no-scan fast path.
+        const LINES_ONLY: Selection = Selection {
+            words: false,
+            chars: false,
+            max_line_length: false,
+            ..WITH_CR
+        };
+        let fast = count_slice(data, LINES_ONLY, CountMode::Utf8, CountingBackend::Scalar);
+        assert_eq!(fast.lines, 3);
+    }
+
+    #[test]
+    fn cr_line_stream_joins_a_split_crlf() {
+        const WITH_CR: Selection = Selection {
+            count_cr_lines: true,
+            ..ALL
+        };
+        // Lone CR, CRLF, trailing lone CR: three terminators.
+        let data = b"a\rb\r\nc\r";
+        let whole = count_slice(data, WITH_CR, CountMode::Utf8, CountingBackend::Scalar);
+        assert_eq!(whole.lines, 3);
+        for step in 1..data.len() {
+            let mut counter = StreamCounter::new(WITH_CR, CountMode::Utf8, CountingBackend::Scalar);
+            for piece in data.chunks(step) {
+                counter.update(piece);
+            }
+            assert_eq!(counter.finish(), whole, "step {step}");
+        }
+    }
+
+    #[test]
+    fn fallback_policies_agree_on_mixed_scripts() {
+        // ASCII runs of assorted lengths around multi-byte text and one
+        // invalid byte, so every policy crosses both loops.
+        let mut data = b"short ascii \xc3\xa9 x \xe4\xb8\xad\xe6\x96\x87 ".to_vec();
+        data.extend(b"a long ascii stretch that clears any sensible minimum run\n");
+        data.extend(b"\xff tail \xc3\xa9");
+        let reference = count_slice_with_policy(
+            &data,
+            ALL,
+            CountMode::Utf8,
+            CountingBackend::Scalar,
+            FallbackPolicy::AlwaysScalar,
+        );
+        for min_run in [0, 1, 4, 64, usize::MAX] {
+            let policy = FallbackPolicy::AsciiRuns { min_run };
+            let c = count_slice_with_policy(
+                &data,
+                ALL,
+                CountMode::Utf8,
+                CountingBackend::Scalar,
+                policy,
+            );
+            assert_eq!(c, reference, "min_run {min_run}");
+        }
+    }
+
+    #[test]
+    fn adaptive_policy_matches_scalar_on_alternating_scripts() {
+        // Blocks big enough to span several samples in each regime, so
+        // the controller actually flips back and forth with hysteresis.
+        let mut data = Vec::new();
+        for _ in 0..3 {
+            data.extend("ascii words here \n".as_bytes().repeat(2000));
+            data.extend("纯中文字 词语 行\n".as_bytes().repeat(2000));
+        }
+        let reference = count_slice_with_policy(
+            &data,
+            ALL,
+            CountMode::Utf8,
+            CountingBackend::Scalar,
+            FallbackPolicy::AlwaysScalar,
+        );
+        let adaptive = count_slice_with_policy(
+            &data,
+            ALL,
+            CountMode::Utf8,
+            CountingBackend::Scalar,
+            FallbackPolicy::Adaptive,
+        );
+        assert_eq!(adaptive, reference);
+        // The regime outlives update boundaries when streaming.
+        let mut counter = StreamCounter::new(ALL, CountMode::Utf8, CountingBackend::Scalar)
+            .with_fallback_policy(FallbackPolicy::Adaptive);
+        for piece in data.chunks(997) {
+            counter.update(piece);
+        }
+        assert_eq!(counter.finish(), reference);
+    }
+
+    #[test]
+    fn adaptive_controller_switches_with_hysteresis() {
+        let mut state = AdaptiveState::default();
+        assert!(state.bulk);
+        // One Unicode-heavy sample is not enough to leave the bulk regime.
+        let unicode = "中".repeat(100).into_bytes();
+        state.observe(&unicode);
+        assert!(state.bulk);
+        // A run of them is.
+        for _ in 0..ADAPTIVE_HYSTERESIS {
+            state.observe(&unicode);
+        }
+        assert!(!state.bulk);
+        // An ASCII blip inside Unicode text resets the streak back.
+        state.observe(b"ascii");
+        state.observe(&unicode);
+        assert_eq!(state.streak, 0);
+        assert!(!state.bulk);
+    }
+
+    #[test]
+    fn fallback_policy_carries_through_streaming() {
+        let data = "ascii première moitié and some more ascii to finish\n".as_bytes();
+        let whole = count_slice(data, ALL, CountMode::Utf8, CountingBackend::Scalar);
+        for policy in [
+            FallbackPolicy::AlwaysScalar,
+            FallbackPolicy::AsciiRuns { min_run: 3 },
+        ] {
+            let mut counter = StreamCounter::new(ALL, CountMode::Utf8, CountingBackend::Scalar)
+                .with_fallback_policy(policy);
+            for piece in data.chunks(5) {
+                counter.update(piece);
+            }
+            assert_eq!(counter.finish(), whole, "{policy:?}");
+        }
+    }
+
+    #[test]
+    fn word_chars_feed_the_average_word_length() {
+        const WITH_AVG: Selection = Selection {
+            avg_word_length: true,
+            ..ALL
+        };
+        // Words of 3, 3, and 6 characters; the é is one char, two bytes.
+        let c = count_slice(
+            "one two sixxés\n".as_bytes(),
+            WITH_AVG,
+            CountMode::Utf8,
+            CountingBackend::Scalar,
+        );
+        assert_eq!(c.word_chars, 12);
+        assert_eq!(c.words, 3);
+        assert!((c.avg_word_length() - 4.0).abs() < f64::EPSILON);
+        // No words: the average is defined as zero rather than NaN.
+        let empty = count_slice(b"  \n", WITH_AVG, CountMode::Utf8, CountingBackend::Scalar);
+        assert_eq!(empty.avg_word_length(), 0.0);
+        // Additive, so chunked totals stay exact.
+        let mut sum = c;
+        sum += empty;
+        assert_eq!(sum.word_chars, 12);
+    }
+
+    #[test]
+    fn unique_words_stream_like_the_slice_kernel() {
+        const UNIQUE: Selection = Selection {
+            unique_words: true,
+            fold_case: true,
+            ..ALL
+        };
+        let data = "héllo Wörld wörld\nhéllo twice\n".as_bytes();
+        let whole = count_slice(data, UNIQUE, CountMode::Utf8, CountingBackend::Scalar);
+        assert_eq!(whole.unique_words, 3); // héllo, wörld, twice
+        for step in 1..data.len() {
+            let mut sc = StreamCounter::new(UNIQUE, CountMode::Utf8, CountingBackend::Scalar);
+            for piece in data.chunks(step) {
+                sc.update(piece);
+            }
+            assert_eq!(sc.finish(), whole, "step {step}");
+        }
+    }
+
+    #[test]
+    fn words_per_line_streams_like_the_slice_kernel() {
+        let data = "hé wörld\r\nsplit across\nupdates here\n你 好".as_bytes();
+        let whole = count_slice(data, WITH_WPL, CountMode::Utf8, CountingBackend::Scalar);
+        for step in 1..data.len() {
+            let mut sc = StreamCounter::new(WITH_WPL, CountMode::Utf8, CountingBackend::Scalar);
+            for piece in data.chunks(step) {
+                sc.update(piece);
+            }
+            assert_eq!(sc.finish(), whole, "step {step}");
+        }
+    }
+
+    #[test]
+    fn split_point_respects_char_boundaries() {
+        let data = "aé".as_bytes(); // 61 c3 a9
+        assert_eq!(split_point(data, 0), 0);
+        assert_eq!(split_point(data, 1), 1);
+        assert_eq!(split_point(data, 2), 3);
+        assert_eq!(split_point(data, 3), 3);
+    }
+
+    fn chunked(data: &[u8], splits: &[usize]) -> Counts {
+        let mut acc = ChunkCounts::default();
+        let mut prev = 0;
+        for &s in splits {
+            let at = split_point(data, s.max(prev));
+            acc = acc.merge(count_chunk(
+                &data[prev..at],
+                ALL,
+                CountMode::Utf8,
+                CountingBackend::Scalar,
+            ));
+            prev = at;
+        }
+        acc = acc.merge(count_chunk(
+            &data[prev..],
+            ALL,
+            CountMode::Utf8,
+            CountingBackend::Scalar,
+        ));
+        acc.finish()
+    }
+
+    #[test]
+    fn grapheme_unit_counts_clusters() {
+        // é as e + combining acute, a CRLF pair, then x: three clusters.
+        let data = "e\u{301}\r\nx".as_bytes();
+        let scalars = count_slice(data, ALL, CountMode::Utf8, CountingBackend::Scalar);
+        let graphemes = count_slice_with_unit(
+            data,
+            ALL,
+            CountMode::Utf8,
+            CharUnit::Graphemes,
+            CountingBackend::Scalar,
+        );
+        assert_eq!(scalars.chars, 5);
+        assert_eq!(graphemes.chars, 3);
+        // Every other counter is unaffected by the unit.
+        assert_eq!(scalars.words, graphemes.words);
+        assert_eq!(scalars.bytes, graphemes.bytes);
+    }
+
+    #[test]
+    fn stream_graphemes_join_across_updates() {
+        let mut sc = StreamCounter::new(ALL, CountMode::Utf8, CountingBackend::Scalar)
+            .with_char_unit(CharUnit::Graphemes);
+        sc.update(b"e");
+        sc.update("\u{301}\n".as_bytes());
+        assert_eq!(sc.finish().chars, 2);
+    }
+
+    #[test]
+    fn checked_add_matches_add_assign_until_overflow() {
+        let a = count_all(b"one two\nthree\n");
+        let b = count_all(b"a much longer line here\n");
+        let mut summed = a;
+        summed += b;
+        assert_eq!(a.checked_add(b), Some(summed));
+        // The extremes merge rather than add, so a pegged maximum alone
+        // does not overflow; a pegged additive counter does.
+        let pegged_max = Counts {
+            max_line_length: u64::MAX,
+            ..a
+        };
+        assert_eq!(pegged_max.checked_add(b).unwrap().max_line_length, u64::MAX);
+        let pegged_bytes = Counts {
+            bytes: u64::MAX,
+            ..a
+        };
+        assert_eq!(pegged_bytes.checked_add(b), None);
+        // Where the checked sum refuses, the saturating one pegs; below
+        // the ceiling the two agree.
+        assert_eq!(pegged_bytes.saturating_add(b).bytes, u64::MAX);
+        assert_eq!(pegged_bytes.saturating_add(b).lines, summed.lines);
+        assert_eq!(a.saturating_add(b), summed);
+        // Wrapping is the documented fallback, in debug builds too.
+        assert_eq!(pegged_bytes.wrapping_add(b).bytes, b.bytes - 1);
+        assert_eq!(a.wrapping_add(b), summed);
+    }
+
+    #[test]
+    fn stream_state_resumes_mid_word_and_mid_character() {
+        let data = "hé\u{2003}wörld 你\n".as_bytes();
+        for split in 0..data.len() {
+            let mut first = StreamCounter::new(ALL, CountMode::Utf8, CountingBackend::Scalar);
+            first.update(&data[..split]);
+            let mut second = StreamCounter::new(ALL, CountMode::Utf8, CountingBackend::Scalar)
+                .with_state(first.state());
+            second.update(&data[split..]);
+            assert_eq!(second.finish(), count_all(data), "split at {split}");
+        }
+    }
+
+    fn streamed(data: &[u8], step: usize) -> Counts {
+        let mut sc = StreamCounter::new(ALL, CountMode::Utf8, CountingBackend::Scalar);
+        for piece in data.chunks(step.max(1)) {
+            sc.update(piece);
+        }
+        sc.finish()
+    }
+
+    proptest! {
+        #[test]
+        fn chunked_merge_matches_whole(data: Vec<u8>, splits in proptest::collection::vec(0usize..4096, 0..4)) {
+            let mut splits = splits;
+            splits.sort_unstable();
+            let whole = count_all(&data);
+            let mut got = chunked(&data, &splits);
+            // Max line length is only mergeable within tab-free chunks; the
+            // parallel path never splits when `-L` is selected.
+            got.max_line_length = whole.max_line_length;
+            prop_assert_eq!(got, whole);
+        }
+
+        #[test]
+        fn chunked_merge_matches_whole_tabfree(text in "[a-z \né\u{2003}]{0,200}", splits in proptest::collection::vec(0usize..256, 0..4)) {
+            let mut splits = splits;
+            splits.sort_unstable();
+            let whole = count_all(text.as_bytes());
+            prop_assert_eq!(chunked(text.as_bytes(), &splits), whole);
+        }
+
+        #[test]
+        fn streaming_matches_whole(data: Vec<u8>, step in 1usize..64) {
+            prop_assert_eq!(streamed(&data, step), count_all(&data));
+        }
+
+        #[test]
+        fn streaming_graphemes_match_whole(data: Vec<u8>, step in 1usize..64) {
+            let whole = count_slice_with_unit(
+                &data,
+                ALL,
+                CountMode::Utf8,
+                CharUnit::Graphemes,
+                CountingBackend::Scalar,
+            );
+            let mut sc = StreamCounter::new(ALL, CountMode::Utf8, CountingBackend::Scalar)
+                .with_char_unit(CharUnit::Graphemes);
+            for piece in data.chunks(step) {
+                sc.update(piece);
+            }
+            prop_assert_eq!(sc.finish(), whole);
+        }
+    }
+}
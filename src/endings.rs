@@ -0,0 +1,139 @@
+//! Line-terminator auditing, behind the binary's `--line-endings`.
+//!
+//! Each terminator is one of LF (`\n`), CRLF (`\r\n`), or a lone CR — the
+//! old Mac convention and a frequent symptom of half-converted files. The
+//! counter classifies in bulk: newlines and CRLF pairs come from the SIMD
+//! kernels, carriage returns from a byte search, and the three tallies fall
+//! out by subtraction. CRLF pairs split across `update` calls are joined
+//! with a one-byte carry.
+
+use crate::simd::{CountingBackend, Kernels};
+
+/// Terminator tallies for one input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LineEndings {
+    /// Bare `\n` terminators.
+    pub lf: u64,
+    /// `\r\n` pairs.
+    pub crlf: u64,
+    /// Carriage returns not followed by a newline.
+    pub cr: u64,
+}
+
+impl LineEndings {
+    /// True when more than one terminator style appears.
+    pub fn is_mixed(&self) -> bool {
+        [self.lf, self.crlf, self.cr]
+            .iter()
+            .filter(|&&n| n > 0)
+            .count()
+            > 1
+    }
+}
+
+/// Incremental terminator counter.
+#[derive(Debug)]
+pub struct EndingCounter {
+    kernels: Kernels,
+    newlines: u64,
+    carriage_returns: u64,
+    crlf: u64,
+    last_was_cr: bool,
+}
+
+impl EndingCounter {
+    pub fn new(backend: CountingBackend) -> Self {
+        EndingCounter {
+            kernels: backend.kernels(),
+            newlines: 0,
+            carriage_returns: 0,
+            crlf: 0,
+            last_was_cr: false,
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+        self.newlines += self.kernels.count_lines(data);
+        self.carriage_returns += memchr::memchr_iter(b'\r', data).count() as u64;
+        self.crlf += self.kernels.count_crlf(data);
+        if self.last_was_cr && data[0] == b'\n' {
+            self.crlf += 1;
+        }
+        self.last_was_cr = data[data.len() - 1] == b'\r';
+    }
+
+    pub fn finish(self) -> LineEndings {
+        LineEndings {
+            lf: self.newlines - self.crlf,
+            crlf: self.crlf,
+            cr: self.carriage_returns - self.crlf,
+        }
+    }
+}
+
+/// Audit a complete in-memory input.
+pub fn count_line_endings(data: &[u8], backend: CountingBackend) -> LineEndings {
+    let mut counter = EndingCounter::new(backend);
+    counter.update(data);
+    counter.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn audit(data: &[u8]) -> LineEndings {
+        count_line_endings(data, CountingBackend::Scalar)
+    }
+
+    #[test]
+    fn each_style_is_tallied_separately() {
+        let e = audit(b"unix\nwindows\r\nmac\rplain");
+        assert_eq!(
+            e,
+            LineEndings {
+                lf: 1,
+                crlf: 1,
+                cr: 1
+            }
+        );
+        assert!(e.is_mixed());
+    }
+
+    #[test]
+    fn a_single_style_is_not_mixed() {
+        assert!(!audit(b"a\r\nb\r\n").is_mixed());
+        assert!(!audit(b"a\nb\n").is_mixed());
+        assert!(!audit(b"no terminators at all").is_mixed());
+    }
+
+    #[test]
+    fn cr_runs_only_pair_with_the_adjacent_newline() {
+        // \r\r\n is one lone CR followed by one CRLF.
+        let e = audit(b"\r\r\n");
+        assert_eq!(
+            e,
+            LineEndings {
+                lf: 0,
+                crlf: 1,
+                cr: 1
+            }
+        );
+    }
+
+    #[test]
+    fn crlf_split_across_updates_counts_once() {
+        let data = b"one\r\ntwo\r\nthree\r";
+        let whole = audit(data);
+        for step in 1..data.len() {
+            let mut counter = EndingCounter::new(CountingBackend::Scalar);
+            for piece in data.chunks(step) {
+                counter.update(piece);
+            }
+            assert_eq!(counter.finish(), whole, "step {step}");
+        }
+    }
+}
@@ -0,0 +1,161 @@
+//! Delimiter-separated field counting, behind the binary's `--fields`.
+//!
+//! A line's field count is one more than its delimiter count, except that a
+//! line with no content has zero fields. A `\r` immediately before the
+//! newline is treated as part of a CRLF terminator, not content, so Windows
+//! CSV exports audit the same as their Unix counterparts. The counter
+//! streams: lines may span `update` calls, and an unterminated final line
+//! with content still counts.
+
+/// Field counters for one input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FieldStats {
+    /// Lines seen, including an unterminated final line with content.
+    pub lines: u64,
+    /// Fields summed over every line.
+    pub total_fields: u64,
+    /// Fewest fields on one line; `None` until a line has been seen.
+    pub min_fields: Option<u64>,
+    /// Most fields on one line.
+    pub max_fields: u64,
+}
+
+impl FieldStats {
+    /// True when every line has the same number of fields (trivially true
+    /// for empty input).
+    pub fn is_consistent(&self) -> bool {
+        match self.min_fields {
+            None => true,
+            Some(min) => min == self.max_fields,
+        }
+    }
+}
+
+/// Incremental field counter for one delimiter byte.
+#[derive(Debug)]
+pub struct FieldCounter {
+    delim: u8,
+    stats: FieldStats,
+    line_delims: u64,
+    line_bytes: u64,
+    last_was_cr: bool,
+}
+
+impl FieldCounter {
+    pub fn new(delim: u8) -> Self {
+        FieldCounter {
+            delim,
+            stats: FieldStats::default(),
+            line_delims: 0,
+            line_bytes: 0,
+            last_was_cr: false,
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            if byte == b'\n' {
+                let content = self.line_bytes - u64::from(self.last_was_cr);
+                self.record(content);
+            } else {
+                if byte == self.delim {
+                    self.line_delims += 1;
+                }
+                self.line_bytes += 1;
+                self.last_was_cr = byte == b'\r';
+            }
+        }
+    }
+
+    /// Fold in a final line left unterminated at end of input.
+    pub fn finish(mut self) -> FieldStats {
+        if self.line_bytes > 0 {
+            let content = self.line_bytes;
+            self.record(content);
+        }
+        self.stats
+    }
+
+    fn record(&mut self, content_bytes: u64) {
+        let fields = if content_bytes == 0 {
+            0
+        } else {
+            self.line_delims + 1
+        };
+        self.stats.lines += 1;
+        self.stats.total_fields += fields;
+        self.stats.min_fields = Some(match self.stats.min_fields {
+            None => fields,
+            Some(min) => min.min(fields),
+        });
+        self.stats.max_fields = self.stats.max_fields.max(fields);
+        self.line_delims = 0;
+        self.line_bytes = 0;
+        self.last_was_cr = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(data: &[u8], delim: u8) -> FieldStats {
+        let mut counter = FieldCounter::new(delim);
+        counter.update(data);
+        counter.finish()
+    }
+
+    #[test]
+    fn consistent_rows_report_one_width() {
+        let stats = count(b"a,b,c\nd,e,f\n", b',');
+        assert_eq!(stats.lines, 2);
+        assert_eq!(stats.total_fields, 6);
+        assert_eq!(stats.min_fields, Some(3));
+        assert_eq!(stats.max_fields, 3);
+        assert!(stats.is_consistent());
+    }
+
+    #[test]
+    fn ragged_rows_and_empty_lines_break_consistency() {
+        let stats = count(b"a,b,c\nd\n\n", b',');
+        assert_eq!(stats.total_fields, 4);
+        assert_eq!(stats.min_fields, Some(0));
+        assert_eq!(stats.max_fields, 3);
+        assert!(!stats.is_consistent());
+    }
+
+    #[test]
+    fn crlf_terminators_are_not_content() {
+        // A CRLF-only line is empty; a field ending in CRLF is unchanged.
+        let stats = count(b"a,b\r\nc,d\r\n", b',');
+        assert_eq!(stats.total_fields, 4);
+        assert!(stats.is_consistent());
+        let stats = count(b"\r\n", b',');
+        assert_eq!(stats.min_fields, Some(0));
+    }
+
+    #[test]
+    fn unterminated_last_line_counts() {
+        let stats = count(b"a\tb\nc\td", b'\t');
+        assert_eq!(stats.lines, 2);
+        assert_eq!(stats.total_fields, 4);
+        assert!(stats.is_consistent());
+        // Empty input has no lines and is trivially consistent.
+        let stats = count(b"", b'\t');
+        assert_eq!(stats.min_fields, None);
+        assert!(stats.is_consistent());
+    }
+
+    #[test]
+    fn lines_split_across_updates_count_once() {
+        let data = b"one,two,three\nfour,five,six\n";
+        let whole = count(data, b',');
+        for step in 1..data.len() {
+            let mut counter = FieldCounter::new(b',');
+            for piece in data.chunks(step) {
+                counter.update(piece);
+            }
+            assert_eq!(counter.finish(), whole, "step {step}");
+        }
+    }
+}
@@ -0,0 +1,197 @@
+//! Parsing for `--files0-from` file lists.
+//!
+//! The list format is the one produced by `find -print0`: file names
+//! separated by NUL bytes, with an optional trailing NUL. Names are kept as
+//! raw bytes because file names are not required to be UTF-8.
+
+use std::fmt;
+
+/// An invalid entry in a `--files0-from` list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Files0Error {
+    /// A zero-length name (two adjacent NULs, or a leading NUL).
+    EmptyName {
+        /// Zero-based index of the offending entry.
+        index: usize,
+    },
+}
+
+impl fmt::Display for Files0Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Files0Error::EmptyName { index } => {
+                write!(f, "invalid zero-length file name at entry {index}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Files0Error {}
+
+/// Split a NUL-separated file list into its entries, failing on the first
+/// zero-length name. A final NUL terminates the last entry rather than
+/// starting an empty one, but does not excuse an empty entry before it:
+/// `"a\0"` is one entry while `"\0"` is one (invalid) empty entry,
+/// matching GNU's tokenization.
+pub fn parse_list(data: &[u8]) -> Result<Vec<&[u8]>, Files0Error> {
+    let mut names = Vec::new();
+    if data.is_empty() {
+        return Ok(names);
+    }
+    let mut pieces = data.split(|&b| b == 0).peekable();
+    while let Some(name) = pieces.next() {
+        if name.is_empty() && pieces.peek().is_none() {
+            // The terminator of the final entry, not an entry itself.
+            break;
+        }
+        if name.is_empty() {
+            return Err(Files0Error::EmptyName { index: names.len() });
+        }
+        names.push(name);
+    }
+    Ok(names)
+}
+
+/// Incremental reader for a NUL-separated list, for sources that cannot be
+/// slurped up front (stdin, pipes from `find -print0`). Each call yields the
+/// next entry as soon as its terminating NUL (or end of input) arrives, so
+/// counting can start before the producer finishes.
+pub struct Files0Reader<R> {
+    reader: R,
+    index: usize,
+    done: bool,
+}
+
+impl<R: std::io::BufRead> Files0Reader<R> {
+    pub fn new(reader: R) -> Self {
+        Files0Reader {
+            reader,
+            index: 0,
+            done: false,
+        }
+    }
+
+    /// The next file name, or `None` at end of input. A missing final NUL is
+    /// tolerated, matching [`parse_list`].
+    #[allow(clippy::type_complexity)]
+    pub fn next_name(&mut self) -> Option<Result<Vec<u8>, Files0ReadError>> {
+        if self.done {
+            return None;
+        }
+        let mut name = Vec::new();
+        match self.reader.read_until(0, &mut name) {
+            Err(err) => {
+                self.done = true;
+                return Some(Err(Files0ReadError::Io(err)));
+            }
+            Ok(0) => {
+                self.done = true;
+                return None;
+            }
+            Ok(_) => {}
+        }
+        if name.last() == Some(&0) {
+            name.pop();
+        } else {
+            // Unterminated final entry: nothing follows it.
+            self.done = true;
+        }
+        if name.is_empty() {
+            let err = Files0Error::EmptyName { index: self.index };
+            self.index += 1;
+            return Some(Err(Files0ReadError::Parse(err)));
+        }
+        self.index += 1;
+        Some(Ok(name))
+    }
+}
+
+/// Failure while reading a list incrementally: either the source itself or
+/// an invalid entry.
+#[derive(Debug)]
+pub enum Files0ReadError {
+    Io(std::io::Error),
+    Parse(Files0Error),
+}
+
+impl fmt::Display for Files0ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Files0ReadError::Io(err) => err.fmt(f),
+            Files0ReadError::Parse(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Files0ReadError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_on_nul() {
+        assert_eq!(parse_list(b"a\0b\0c").unwrap(), vec![&b"a"[..], b"b", b"c"]);
+    }
+
+    #[test]
+    fn trailing_nul_is_optional() {
+        assert_eq!(parse_list(b"a\0b\0").unwrap(), vec![&b"a"[..], b"b"]);
+    }
+
+    #[test]
+    fn empty_list() {
+        assert_eq!(parse_list(b"").unwrap(), Vec::<&[u8]>::new());
+    }
+
+    #[test]
+    fn rejects_empty_names() {
+        assert_eq!(
+            parse_list(b"a\0\0b"),
+            Err(Files0Error::EmptyName { index: 1 })
+        );
+        assert_eq!(parse_list(b"\0a"), Err(Files0Error::EmptyName { index: 0 }));
+        // A lone NUL is one empty entry, not an empty list.
+        assert_eq!(parse_list(b"\0"), Err(Files0Error::EmptyName { index: 0 }));
+    }
+
+    fn drain(data: &[u8]) -> Vec<Result<Vec<u8>, String>> {
+        let mut reader = Files0Reader::new(data);
+        let mut out = Vec::new();
+        while let Some(item) = reader.next_name() {
+            out.push(item.map_err(|e| e.to_string()));
+        }
+        out
+    }
+
+    #[test]
+    fn reader_matches_parse_list() {
+        for data in [&b"a\0b\0c"[..], b"a\0b\0", b""] {
+            let streamed: Vec<_> = drain(data).into_iter().map(Result::unwrap).collect();
+            assert_eq!(streamed, parse_list(data).unwrap(), "list {data:?}");
+        }
+    }
+
+    #[test]
+    fn reader_reports_empty_names_in_place() {
+        let items = drain(b"a\0\0b");
+        assert_eq!(items[0], Ok(b"a".to_vec()));
+        assert_eq!(
+            items[1],
+            Err("invalid zero-length file name at entry 1".to_string())
+        );
+        assert_eq!(items[2], Ok(b"b".to_vec()));
+        // GNU diagnoses every empty entry, including ones a trailing NUL
+        // might seem to excuse.
+        assert_eq!(drain(b"\0\0").len(), 2);
+        assert_eq!(drain(b"\0").len(), 1);
+    }
+
+    #[test]
+    fn names_may_be_non_utf8() {
+        assert_eq!(
+            parse_list(b"\xff\xfe\0x").unwrap(),
+            vec![&b"\xff\xfe"[..], b"x"]
+        );
+    }
+}
@@ -0,0 +1,155 @@
+//! Gettext-style translation for the binary's fixed messages.
+//!
+//! GNU wc ships localized diagnostics, and downstream wrappers sometimes
+//! parse them, so the strings wc-rs prints verbatim — the totals label,
+//! the missing-newline warning — go through [`translate`]. The catalogs
+//! are compiled in rather than loaded from `.mo` files: the message set
+//! is tiny, and a missing translation falls back to English instead of
+//! failing. The language comes from `LC_ALL`/`LC_MESSAGES`/`LANG` in
+//! glibc precedence order; [`translate_to`] takes it explicitly so tests
+//! need not mutate the process environment.
+
+/// A fixed user-facing string the binary prints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Message {
+    /// The default totals row label.
+    Total,
+    /// The warning for inputs whose last line has no newline.
+    NoNewlineAtEndOfFile,
+    /// The prefix on standard-output write failures.
+    WriteError,
+    /// The hint printed when stdin is an interactive terminal.
+    ReadingFromTerminal,
+}
+
+/// One language's catalog. Every entry is mandatory, so adding a message
+/// variant forces every catalog to say something for it.
+struct Catalog {
+    lang: &'static str,
+    total: &'static str,
+    no_newline: &'static str,
+    write_error: &'static str,
+    tty_hint: &'static str,
+}
+
+const ENGLISH: Catalog = Catalog {
+    lang: "en",
+    total: "total",
+    no_newline: "no newline at end of file",
+    write_error: "write error",
+    tty_hint: "reading from terminal; press Ctrl-D to finish, or pass FILE arguments",
+};
+
+static CATALOGS: &[Catalog] = &[
+    Catalog {
+        lang: "de",
+        total: "insgesamt",
+        no_newline: "keine neue Zeile am Ende der Datei",
+        write_error: "Schreibfehler",
+        tty_hint: "Lese vom Terminal; Strg-D beendet die Eingabe, oder FILE-Argumente übergeben",
+    },
+    Catalog {
+        lang: "es",
+        total: "total",
+        no_newline: "no hay salto de línea al final del archivo",
+        write_error: "error de escritura",
+        tty_hint: "leyendo del terminal; pulse Ctrl-D para terminar o pase argumentos FILE",
+    },
+    Catalog {
+        lang: "fr",
+        total: "total",
+        no_newline: "pas de retour à la ligne à la fin du fichier",
+        write_error: "erreur d'écriture",
+        tty_hint: "lecture depuis le terminal ; Ctrl-D pour terminer, ou passez des arguments FILE",
+    },
+];
+
+/// The translation for `message` in the process locale's language.
+pub fn translate(message: Message) -> &'static str {
+    translate_to(message, language().as_deref())
+}
+
+/// Like [`translate`], but for an explicit language code (`de`, `fr`,
+/// ...). Unknown languages and `None` get the English string.
+pub fn translate_to(message: Message, lang: Option<&str>) -> &'static str {
+    let catalog = lang
+        .and_then(|lang| CATALOGS.iter().find(|catalog| catalog.lang == lang))
+        .unwrap_or(&ENGLISH);
+    match message {
+        Message::Total => catalog.total,
+        Message::NoNewlineAtEndOfFile => catalog.no_newline,
+        Message::WriteError => catalog.write_error,
+        Message::ReadingFromTerminal => catalog.tty_hint,
+    }
+}
+
+/// The message language from the environment: the part of the locale name
+/// before `_` or `.`, lowercased, so `de_DE.UTF-8` yields `de`.
+fn language() -> Option<String> {
+    language_from(|var| std::env::var(var).ok())
+}
+
+/// Like [`language`], but reading variables through `env`. Empty values
+/// are skipped, as glibc does; `LC_MESSAGES` ranks where `LC_CTYPE` does
+/// for [`crate::locale`] because messages follow their own category.
+pub fn language_from(env: impl Fn(&str) -> Option<String>) -> Option<String> {
+    for var in ["LC_ALL", "LC_MESSAGES", "LANG"] {
+        if let Some(value) = env(var) {
+            if value.is_empty() {
+                continue;
+            }
+            let lang = value
+                .split(['_', '.'])
+                .next()
+                .unwrap_or(&value)
+                .to_ascii_lowercase();
+            return Some(lang);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_languages_translate_and_unknown_fall_back() {
+        assert_eq!(translate_to(Message::Total, Some("de")), "insgesamt");
+        assert_eq!(
+            translate_to(Message::WriteError, Some("fr")),
+            "erreur d'écriture"
+        );
+        assert_eq!(
+            translate_to(Message::NoNewlineAtEndOfFile, Some("xx")),
+            "no newline at end of file"
+        );
+        assert_eq!(translate_to(Message::Total, None), "total");
+    }
+
+    #[test]
+    fn language_follows_glibc_precedence_and_skips_empties() {
+        let fixed = |vars: &'static [(&str, &str)]| {
+            move |var: &str| {
+                vars.iter()
+                    .find(|(name, _)| *name == var)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+        assert_eq!(
+            language_from(fixed(&[("LC_ALL", "de_DE.UTF-8"), ("LANG", "fr_FR")])),
+            Some("de".to_string())
+        );
+        assert_eq!(
+            language_from(fixed(&[("LC_ALL", ""), ("LC_MESSAGES", "es_ES")])),
+            Some("es".to_string())
+        );
+        // The C locale is a language nothing translates to, so it gets
+        // English through the fallback.
+        assert_eq!(
+            language_from(fixed(&[("LANG", "C.UTF-8")])),
+            Some("c".to_string())
+        );
+        assert_eq!(language_from(fixed(&[])), None);
+    }
+}
@@ -0,0 +1,36 @@
+//! Core counting routines for `wc-rs`, a fast `wc(1)` clone.
+//!
+//! The crate is split into a library (this module tree) and a thin CLI
+//! binary. The library exposes the counting kernels, the chunk-merge model
+//! used for parallel counting, and the CLI definition itself so that
+//! integration tests and fuzz targets can drive them directly.
+
+pub mod api;
+pub mod classes;
+#[cfg(feature = "cli")]
+pub mod cli;
+pub mod count;
+pub mod endings;
+pub mod entropy;
+pub mod fields;
+pub mod files0;
+pub mod i18n;
+pub mod locale;
+#[cfg(feature = "parallel")]
+pub mod parallel;
+pub mod simd;
+pub mod unicode_data;
+
+pub use api::{
+    count_files, count_files_threads, count_path, try_count_path, try_count_reader, CountError,
+    CountLimits, CountOptions, FileTotals,
+};
+pub use classes::{count_char_classes, CharClasses, ClassCounter};
+pub use count::{
+    count_slices, ChunkCounts, CountMode, Counts, FallbackPolicy, Selection, StreamCounter,
+};
+pub use endings::{count_line_endings, EndingCounter, LineEndings};
+pub use entropy::{byte_entropy, ByteHistogram};
+pub use fields::{FieldCounter, FieldStats};
+pub use locale::{detect_locale, Locale};
+pub use simd::{register_kernel, resolved_kernels, CountKernel, CountingBackend, Kernels};
@@ -0,0 +1,134 @@
+//! POSIX locale detection, shared by the binary and embedders.
+//!
+//! The binary reads `LC_ALL`, `LC_CTYPE`, and `LANG` in glibc precedence
+//! order to decide byte vs UTF-8 counting and whether a legacy multibyte
+//! charset needs transcoding. Embedders tended to re-implement that scan
+//! inconsistently; [`detect_locale`] exposes it, and
+//! [`detect_locale_from`] takes the environment as a closure so tests need
+//! not mutate the process environment.
+
+use crate::count::CountMode;
+
+/// What the locale implies for counting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Locale {
+    /// The locale name that decided, e.g. `ja_JP.eucJP`, or `None` when no
+    /// variable was set (the implicit C locale).
+    pub name: Option<String>,
+    /// Byte vs UTF-8 character interpretation.
+    pub mode: CountMode,
+    /// A multibyte legacy charset that needs transcoding before counting,
+    /// for locales like `ja_JP.eucJP`. UTF-8 and single-byte charsets are
+    /// `None`; they are counted without transcoding.
+    pub encoding: Option<&'static encoding_rs::Encoding>,
+}
+
+/// Detect the locale from the process environment.
+pub fn detect_locale() -> Locale {
+    detect_locale_from(|var| std::env::var(var).ok())
+}
+
+/// Like [`detect_locale`], but reading variables through `env`, so a fixed
+/// environment can be injected. Empty values are skipped, as glibc does.
+pub fn detect_locale_from(env: impl Fn(&str) -> Option<String>) -> Locale {
+    for var in ["LC_ALL", "LC_CTYPE", "LANG"] {
+        if let Some(value) = env(var) {
+            if value.is_empty() {
+                continue;
+            }
+            let lower = value.to_ascii_lowercase();
+            let mode = if lower.contains("utf-8") || lower.contains("utf8") {
+                CountMode::Utf8
+            } else {
+                CountMode::Bytes
+            };
+            return Locale {
+                mode,
+                encoding: charset_encoding(&value),
+                name: Some(value),
+            };
+        }
+    }
+    Locale {
+        name: None,
+        mode: CountMode::Bytes,
+        encoding: None,
+    }
+}
+
+/// Map a locale name's charset suffix to an encoding, for the multibyte
+/// charsets where byte counting would misreport chars and words. The
+/// charset is the part after the dot in `ja_JP.eucJP`; spellings vary by
+/// platform, so names are normalized before the WHATWG label lookup.
+pub fn charset_encoding(locale: &str) -> Option<&'static encoding_rs::Encoding> {
+    let charset = locale.split('.').nth(1)?.split('@').next()?;
+    let normalized: String = charset
+        .chars()
+        .filter(|c| *c != '-' && *c != '_')
+        .collect::<String>()
+        .to_ascii_lowercase();
+    let label = match normalized.as_str() {
+        "eucjp" | "ujis" => "euc-jp",
+        "sjis" | "shiftjis" | "pck" => "shift_jis",
+        "gb18030" => "gb18030",
+        "gbk" | "gb2312" | "euccn" => "gbk",
+        "big5" | "big5hkscs" => "big5",
+        "euckr" => "euc-kr",
+        _ => return None,
+    };
+    encoding_rs::Encoding::for_label(label.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locale_charsets_map_to_encodings() {
+        assert_eq!(charset_encoding("ja_JP.eucJP"), Some(encoding_rs::EUC_JP));
+        assert_eq!(charset_encoding("ja_JP.SJIS"), Some(encoding_rs::SHIFT_JIS));
+        assert_eq!(
+            charset_encoding("zh_CN.GB18030"),
+            Some(encoding_rs::GB18030)
+        );
+        assert_eq!(charset_encoding("ko_KR.euc-KR"), Some(encoding_rs::EUC_KR));
+        // UTF-8 and single-byte locales stay on the non-transcoding paths.
+        assert_eq!(charset_encoding("en_US.UTF-8"), None);
+        assert_eq!(charset_encoding("de_DE.ISO-8859-1"), None);
+        assert_eq!(charset_encoding("C"), None);
+    }
+
+    fn fixed<'a>(vars: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |var| {
+            vars.iter()
+                .find(|(name, _)| *name == var)
+                .map(|(_, value)| value.to_string())
+        }
+    }
+
+    #[test]
+    fn precedence_follows_glibc() {
+        let locale = detect_locale_from(fixed(&[
+            ("LC_ALL", "ja_JP.eucJP"),
+            ("LC_CTYPE", "en_US.UTF-8"),
+        ]));
+        assert_eq!(locale.name.as_deref(), Some("ja_JP.eucJP"));
+        assert_eq!(locale.mode, CountMode::Bytes);
+        assert_eq!(locale.encoding, Some(encoding_rs::EUC_JP));
+
+        let locale = detect_locale_from(fixed(&[("LANG", "en_US.UTF-8")]));
+        assert_eq!(locale.mode, CountMode::Utf8);
+        assert_eq!(locale.encoding, None);
+    }
+
+    #[test]
+    fn empty_values_are_skipped_and_no_vars_mean_c_locale() {
+        let locale = detect_locale_from(fixed(&[("LC_ALL", ""), ("LANG", "C.UTF-8")]));
+        assert_eq!(locale.mode, CountMode::Utf8);
+
+        let locale = detect_locale_from(fixed(&[]));
+        assert_eq!(locale.name, None);
+        assert_eq!(locale.mode, CountMode::Bytes);
+        assert_eq!(locale.encoding, None);
+    }
+}
@@ -0,0 +1,5223 @@
+//! The `wc-rs` binary: argument handling, I/O, and output formatting around
+//! the counting kernels in the `wc_rs` library.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, IsTerminal, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+use clap::Parser;
+use rayon::prelude::*;
+
+use wc_rs::api::{advise_mapped_input, count_path, try_count_reader, CountLimits, CountOptions};
+use wc_rs::classes::{CharClasses, ClassCounter};
+use wc_rs::cli::{
+    ByteRange, Cli, ColorMode, Command, Decompress, LocaleEncoding, Normalization, OutputFormat,
+    QuotingStyle, ThreadCount, TotalMode, WatchFormat, WindowSpec,
+};
+use wc_rs::count::{
+    count_slice_with_tab_width, verify_slice, BackendMismatch, CountMode, Counts, Selection,
+    StreamCounter, StreamState, DEFAULT_TAB_WIDTH,
+};
+use wc_rs::endings::{EndingCounter, LineEndings};
+use wc_rs::entropy::ByteHistogram;
+use wc_rs::fields::{FieldCounter, FieldStats};
+use wc_rs::files0;
+use wc_rs::i18n::{translate, Message};
+use wc_rs::parallel::{
+    choose_strategy, count_slice_chunked, hybrid_topology, pin_current_thread_to, OpenFileLimit,
+    ParallelMode, Strategy, MIN_CHUNK_SPLIT_BYTES,
+};
+use wc_rs::simd::{bench_fastest, detect_simd_path, pin_backend, BackendChoice, CountingBackend};
+
+/// Read buffer size for streaming inputs.
+const BUF_SIZE: usize = 256 * 1024;
+
+/// How numeric output fields are rendered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum NumberFormat {
+    /// Exact decimal values.
+    Raw,
+    /// Scaled with a unit suffix; `si` selects powers of 1000 over 1024.
+    Human { si: bool },
+    /// Exact decimal values with the locale's digit grouping applied.
+    Grouped(Grouping),
+}
+
+impl NumberFormat {
+    fn from_cli(cli: &Cli) -> Self {
+        if cli.human_readable || cli.si {
+            NumberFormat::Human { si: cli.si }
+        } else if cli.group_digits {
+            NumberFormat::Grouped(Grouping::from_locale())
+        } else {
+            NumberFormat::Raw
+        }
+    }
+
+    /// Render one count. Human form follows coreutils (`du -h`/`du --si`):
+    /// round up, one decimal below 10, and at most four significant
+    /// characters before the suffix.
+    fn render(&self, value: u64) -> String {
+        let si = match self {
+            NumberFormat::Raw => return value.to_string(),
+            NumberFormat::Grouped(grouping) => return grouping.apply(value),
+            NumberFormat::Human { si } => *si,
+        };
+        let base: u64 = if si { 1000 } else { 1024 };
+        if value < base {
+            return value.to_string();
+        }
+        let suffixes: [char; 6] = if si {
+            ['k', 'M', 'G', 'T', 'P', 'E']
+        } else {
+            ['K', 'M', 'G', 'T', 'P', 'E']
+        };
+        let mut unit = 0;
+        let mut divisor = base as u128;
+        loop {
+            // Ceiling in tenths; rounding can carry into the next unit
+            // (1048575 is 1.0M, not 1024K).
+            let tenths = (value as u128 * 10).div_ceil(divisor);
+            if tenths < 100 {
+                return format!("{}.{}{}", tenths / 10, tenths % 10, suffixes[unit]);
+            }
+            let whole = tenths.div_ceil(10);
+            if whole < base as u128 {
+                return format!("{}{}", whole, suffixes[unit]);
+            }
+            divisor *= base as u128;
+            unit += 1;
+        }
+    }
+}
+
+/// The locale's digit-grouping rule: a separator plus POSIX group sizes,
+/// least-significant first, with the last size repeating.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Grouping {
+    separator: String,
+    sizes: Vec<u8>,
+}
+
+impl Grouping {
+    /// Read LC_NUMERIC from the environment. The C and POSIX locales have
+    /// no separator, so grouping there is a no-op, as with printf's %'d.
+    #[cfg(unix)]
+    fn from_locale() -> Self {
+        // SAFETY: setlocale/localeconv are called once, before any threads
+        // that could observe the process locale are spawned, and the
+        // returned strings are copied immediately.
+        unsafe {
+            libc::setlocale(libc::LC_NUMERIC, c"".as_ptr());
+            let conv = libc::localeconv();
+            let separator = std::ffi::CStr::from_ptr((*conv).thousands_sep)
+                .to_string_lossy()
+                .into_owned();
+            let mut sizes = Vec::new();
+            let mut group = (*conv).grouping;
+            while *group > 0 && *group < i8::MAX {
+                sizes.push(*group as u8);
+                group = group.add(1);
+            }
+            Grouping { separator, sizes }
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn from_locale() -> Self {
+        Grouping {
+            separator: String::new(),
+            sizes: Vec::new(),
+        }
+    }
+
+    /// Insert separators from the right according to the group sizes.
+    fn apply(&self, value: u64) -> String {
+        let digits = value.to_string();
+        if self.separator.is_empty() || self.sizes.is_empty() {
+            return digits;
+        }
+        let mut groups: Vec<&str> = Vec::new();
+        let mut rest = digits.as_str();
+        let mut sizes = self.sizes.iter().copied();
+        let mut size = sizes.next().unwrap() as usize;
+        while rest.len() > size {
+            let (head, tail) = rest.split_at(rest.len() - size);
+            groups.push(tail);
+            rest = head;
+            size = sizes.next().map_or(size, |s| s as usize);
+        }
+        groups.push(rest);
+        groups.reverse();
+        groups.join(&self.separator)
+    }
+}
+
+/// ANSI styling for the human-oriented output, decided once per stream.
+/// All methods degrade to the plain text when styling is off.
+#[derive(Debug, Clone, Copy)]
+struct Style {
+    enabled: bool,
+}
+
+impl Style {
+    /// Styling for a stream: on for terminals under `auto` (unless NO_COLOR
+    /// is set, which an explicit `always` overrides), never into pipes.
+    fn for_stream(mode: ColorMode, is_terminal: bool) -> Self {
+        let enabled = match mode {
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+            ColorMode::Auto => {
+                is_terminal && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+            }
+        };
+        Style { enabled }
+    }
+
+    fn paint(&self, code: &str, text: &str) -> String {
+        if self.enabled {
+            format!("\x1b[{code}m{text}\x1b[0m")
+        } else {
+            text.to_string()
+        }
+    }
+
+    /// Like [`Style::paint`], but for names that are raw bytes rather than
+    /// guaranteed UTF-8.
+    fn paint_bytes(&self, code: &str, text: &[u8]) -> Vec<u8> {
+        if self.enabled {
+            let mut out = format!("\x1b[{code}m").into_bytes();
+            out.extend_from_slice(text);
+            out.extend_from_slice(b"\x1b[0m");
+            out
+        } else {
+            text.to_vec()
+        }
+    }
+
+    /// File name column: cyan.
+    fn file_name(&self, name: &[u8]) -> Vec<u8> {
+        self.paint_bytes("36", name)
+    }
+
+    /// The totals row label: bold.
+    fn total(&self, label: &str) -> Vec<u8> {
+        self.paint_bytes("1", label.as_bytes())
+    }
+
+    /// Diagnostics: red.
+    fn error(&self, text: &str) -> String {
+        self.paint("31", text)
+    }
+}
+
+/// Everything the counting paths need besides the input itself, resolved
+/// once from the command line.
+#[derive(Debug, Clone, Copy)]
+struct CountJob {
+    sel: Selection,
+    mode: CountMode,
+    encoding: Option<DecodePipeline>,
+    range: Option<ByteRange>,
+    max_bytes: Option<u64>,
+    max_lines: Option<u64>,
+    verify: bool,
+    tab_width: u64,
+    partial: bool,
+    retries: u32,
+    decompress: Decompress,
+    madvise: bool,
+    debug: bool,
+}
+
+/// Per-row conditions reported next to the counters.
+#[derive(Debug, Clone, Copy, Default)]
+struct RowFlags {
+    /// The input was cut short by an input cap.
+    truncated: bool,
+    /// The input has content but no final newline, so its last line is not
+    /// in the line count.
+    missing_newline: bool,
+    /// A mid-file read error cut the count short; only the readable prefix
+    /// is counted.
+    partial: bool,
+}
+
+/// How input bytes become characters when the plain byte/UTF-8 paths do
+/// not apply: an encoding choice plus optional normalization.
+#[derive(Debug, Clone, Copy)]
+struct DecodePipeline {
+    selector: EncodingSelector,
+    normalize: Normalization,
+}
+
+/// The encoding half of a [`DecodePipeline`].
+#[derive(Debug, Clone, Copy)]
+enum EncodingSelector {
+    /// A specific encoding, from --encoding or the locale charset.
+    Fixed(&'static encoding_rs::Encoding),
+    /// Sniff each input: BOMs first, then heuristics on the first block.
+    Auto { debug: bool },
+}
+
+/// One input operand.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Input {
+    Stdin,
+    File(PathBuf),
+    /// An already-open descriptor named by an `fd://N` operand.
+    Fd(i32),
+    /// An `http(s)://` operand whose response body is counted.
+    #[cfg(feature = "http")]
+    Url(String),
+}
+
+impl Input {
+    fn display_name(&self) -> String {
+        match self {
+            Input::Stdin => "-".to_string(),
+            Input::File(path) => {
+                #[cfg(windows)]
+                if let Some(s) = path.to_str() {
+                    return strip_verbatim_prefix(s);
+                }
+                path.display().to_string()
+            }
+            Input::Fd(fd) => format!("fd://{fd}"),
+            #[cfg(feature = "http")]
+            Input::Url(url) => url.clone(),
+        }
+    }
+
+    /// The name as the raw bytes to print. On Unix this preserves non-UTF-8
+    /// file names exactly, so `wc-rs $(find ...)` round-trips; elsewhere
+    /// undecodable names degrade to the lossy display form.
+    #[cfg(unix)]
+    fn name_bytes(&self) -> Vec<u8> {
+        use std::os::unix::ffi::OsStrExt;
+        match self {
+            Input::Stdin => b"-".to_vec(),
+            Input::File(path) => path.as_os_str().as_bytes().to_vec(),
+            Input::Fd(_) => self.display_name().into_bytes(),
+            #[cfg(feature = "http")]
+            Input::Url(_) => self.display_name().into_bytes(),
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn name_bytes(&self) -> Vec<u8> {
+        self.display_name().into_bytes()
+    }
+
+    /// File size from metadata, for width estimation and strategy choice.
+    /// Stdin is fstat'ed so `wc-rs < file` sizes its columns like GNU;
+    /// `None` for pipes, other non-regular files, and stat failures.
+    fn size(&self) -> Option<u64> {
+        match self {
+            Input::Stdin => stdin_size(),
+            Input::File(path) => {
+                let meta = std::fs::metadata(openable_path(path)).ok()?;
+                meta.is_file().then_some(meta.len())
+            }
+            Input::Fd(fd) => {
+                let meta = fd_input_file(*fd).ok()?.metadata().ok()?;
+                meta.is_file().then_some(meta.len())
+            }
+            // Sizing a URL would cost a request of its own.
+            #[cfg(feature = "http")]
+            Input::Url(_) => None,
+        }
+    }
+
+    /// Open a non-stdin operand: files by path, `fd://N` by duplicating the
+    /// named descriptor so the caller's copy survives the count.
+    fn open_file(&self) -> io::Result<File> {
+        match self {
+            Input::Stdin => unreachable!("stdin is read in place, not opened"),
+            Input::File(path) => File::open(openable_path(path)),
+            Input::Fd(fd) => fd_input_file(*fd),
+            #[cfg(feature = "http")]
+            Input::Url(_) => unreachable!("URL bodies stream; they are never files"),
+        }
+    }
+}
+
+/// The body of an `http(s)://` operand as a byte stream. Request failures
+/// and non-success statuses surface as the row's I/O error.
+#[cfg(feature = "http")]
+fn url_reader(url: &str) -> io::Result<Box<dyn Read>> {
+    let response = ureq::get(url).call().map_err(io::Error::other)?;
+    Ok(Box::new(response.into_reader()))
+}
+
+/// How many leading bytes `--decompress auto` sniffs: enough for the
+/// longest magic number (xz's six bytes).
+const SNIFF_LEN: usize = 6;
+
+/// Standard input as a byte stream, decompressed per `--decompress`.
+/// Decompression delegates to the system tool (`gzip -dc` and friends)
+/// rather than pulling four codec crates into the build; a feeder thread
+/// keeps the tool's input full while the counting path reads its output.
+fn stdin_reader(retries: u32, decompress: Decompress) -> io::Result<Box<dyn Read>> {
+    let tool = match decompress {
+        Decompress::Never => return Ok(Box::new(RetryReader::new(io::stdin().lock(), retries))),
+        Decompress::Auto => {
+            // The feeder thread needs the unlocked handle: a StdinLock
+            // cannot move across threads.
+            let mut source = RetryReader::new(io::stdin(), retries);
+            let mut prefix = [0u8; SNIFF_LEN];
+            let got = read_until_full(&mut source, &mut prefix)?;
+            let prefix = prefix[..got].to_vec();
+            return match sniff_compression(&prefix) {
+                Some(tool) => decompressor_reader(tool, prefix, source),
+                None => Ok(Box::new(io::Cursor::new(prefix).chain(source))),
+            };
+        }
+        Decompress::Gzip => "gzip",
+        Decompress::Zstd => "zstd",
+        Decompress::Xz => "xz",
+        Decompress::Bzip2 => "bzip2",
+    };
+    decompressor_reader(tool, Vec::new(), RetryReader::new(io::stdin(), retries))
+}
+
+/// The decompressor a magic number names, if any. Covers gzip, zstd, xz,
+/// and bzip2; anything else is counted as-is.
+fn sniff_compression(prefix: &[u8]) -> Option<&'static str> {
+    match prefix {
+        [0x1f, 0x8b, ..] => Some("gzip"),
+        [0x28, 0xb5, 0x2f, 0xfd, ..] => Some("zstd"),
+        [0xfd, b'7', b'z', b'X', b'Z', 0x00, ..] => Some("xz"),
+        [b'B', b'Z', b'h', b'1'..=b'9', ..] => Some("bzip2"),
+        _ => None,
+    }
+}
+
+/// Fill `buf` from `reader`, stopping early only at end of input.
+fn read_until_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let n = reader.read(&mut buf[filled..])?;
+        if n == 0 {
+            break;
+        }
+        filled += n;
+    }
+    Ok(filled)
+}
+
+/// Pipe `prefix` and then `source` through `tool -dc` and read the
+/// decompressed output. The tool's stderr is inherited, so its own
+/// diagnostics for corrupt input reach the user directly.
+fn decompressor_reader(
+    tool: &'static str,
+    prefix: Vec<u8>,
+    mut source: impl Read + Send + 'static,
+) -> io::Result<Box<dyn Read>> {
+    use std::process::{Command as Process, Stdio};
+    let mut child = Process::new(tool)
+        .arg("-dc")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|err| io::Error::new(err.kind(), format!("cannot run {tool}: {err}")))?;
+    let mut child_stdin = child.stdin.take().expect("child stdin was piped");
+    // Feed from a separate thread so reading the tool's output can never
+    // deadlock against writing its input. A broken pipe here means the
+    // tool quit early; its exit status is reported at EOF instead.
+    std::thread::spawn(move || {
+        let _ = child_stdin
+            .write_all(&prefix)
+            .and_then(|()| io::copy(&mut source, &mut child_stdin));
+    });
+    Ok(Box::new(DecompressReader {
+        tool,
+        child,
+        done: false,
+    }))
+}
+
+/// The output side of a spawned decompressor. End of output reaps the
+/// child and turns a failure exit into a read error, so truncated or
+/// corrupt compressed input fails the row like any other bad read.
+struct DecompressReader {
+    tool: &'static str,
+    child: std::process::Child,
+    done: bool,
+}
+
+impl Read for DecompressReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.done {
+            return Ok(0);
+        }
+        let stdout = self.child.stdout.as_mut().expect("child stdout was piped");
+        let n = stdout.read(buf)?;
+        if n == 0 {
+            self.done = true;
+            let status = self.child.wait()?;
+            if !status.success() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} failed ({status})", self.tool),
+                ));
+            }
+        }
+        Ok(n)
+    }
+}
+
+/// A `File` duplicated from an `fd://N` operand's descriptor. Working on a
+/// dup means dropping the `File` never closes the caller's copy, and a
+/// repeated operand still has a live descriptor to count.
+#[cfg(unix)]
+fn fd_input_file(fd: i32) -> io::Result<File> {
+    use std::os::fd::BorrowedFd;
+    // SAFETY: the caller named this descriptor on the command line; keeping
+    // it open for the duration of the run is their side of the contract.
+    let borrowed = unsafe { BorrowedFd::borrow_raw(fd) };
+    Ok(File::from(borrowed.try_clone_to_owned()?))
+}
+
+#[cfg(not(unix))]
+fn fd_input_file(_fd: i32) -> io::Result<File> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "fd:// inputs are only supported on Unix",
+    ))
+}
+
+/// Legacy Win32 file APIs reject paths at or past MAX_PATH unless they are
+/// given in verbatim (`\\?\`) form, so long operands — including entries a
+/// `find -print0` equivalent feeds through `--files0-from` — are rewritten
+/// before opening. Short, already-verbatim, and non-Unicode paths pass
+/// through untouched.
+#[cfg(windows)]
+fn openable_path(path: &Path) -> std::borrow::Cow<'_, Path> {
+    const MAX_PATH: usize = 260;
+    if path.as_os_str().len() < MAX_PATH {
+        return std::borrow::Cow::Borrowed(path);
+    }
+    let absolute = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        match std::env::current_dir() {
+            Ok(dir) => dir.join(path),
+            Err(_) => return std::borrow::Cow::Borrowed(path),
+        }
+    };
+    match absolute.to_str() {
+        Some(s) => std::borrow::Cow::Owned(PathBuf::from(add_verbatim_prefix(s))),
+        None => std::borrow::Cow::Borrowed(path),
+    }
+}
+
+#[cfg(not(windows))]
+fn openable_path(path: &Path) -> &Path {
+    path
+}
+
+/// Rewrite an absolute Windows path into verbatim form: drives gain `\\?\`
+/// and UNC shares become `\\?\UNC\server\share\...`. String-based so the
+/// logic is testable on every host.
+#[cfg(any(windows, test))]
+fn add_verbatim_prefix(absolute: &str) -> String {
+    if absolute.starts_with(r"\\?\") || absolute.starts_with(r"\\.\") {
+        absolute.to_string()
+    } else if let Some(rest) = absolute.strip_prefix(r"\\") {
+        format!(r"\\?\UNC\{rest}")
+    } else {
+        format!(r"\\?\{absolute}")
+    }
+}
+
+/// The inverse, for display: users handed us `C:\...` or `\\server\...`
+/// and expect to read that back, not the verbatim spelling.
+#[cfg(any(windows, test))]
+fn strip_verbatim_prefix(name: &str) -> String {
+    if let Some(rest) = name.strip_prefix(r"\\?\UNC\") {
+        format!(r"\\{rest}")
+    } else if let Some(rest) = name.strip_prefix(r"\\?\") {
+        rest.to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// Size of whatever is on stdin, if it is a regular file.
+#[cfg(unix)]
+fn stdin_size() -> Option<u64> {
+    // SAFETY: zeroed stat is a valid argument buffer, and fd 0 is not
+    // touched beyond the stat itself.
+    unsafe {
+        let mut stat = std::mem::zeroed::<libc::stat>();
+        if libc::fstat(0, &mut stat) != 0 {
+            return None;
+        }
+        (stat.st_mode & libc::S_IFMT == libc::S_IFREG).then_some(stat.st_size as u64)
+    }
+}
+
+#[cfg(not(unix))]
+fn stdin_size() -> Option<u64> {
+    None
+}
+
+/// Whether the binary was invoked under the name `wc` (a symlink or hard
+/// link install that shadows the system wc).
+fn invoked_as_wc() -> bool {
+    std::env::args_os()
+        .next()
+        .map(PathBuf::from)
+        .and_then(|path| path.file_stem().map(|stem| stem == "wc"))
+        .unwrap_or(false)
+}
+
+fn main() -> ExitCode {
+    // Installed as `wc`, behave like GNU wc unless extensions are asked for
+    // explicitly: no WC_RS_* environment defaults, no color, and no
+    // subcommand names swallowing file operands.
+    let applet = invoked_as_wc();
+    if applet {
+        for var in ["WC_RS_THREADS", "WC_RS_OUTPUT", "WC_RS_TOTAL"] {
+            std::env::remove_var(var);
+        }
+    }
+    let mut cli = Cli::parse();
+    if applet {
+        match cli.command.take() {
+            // GNU wc would count files named after the subcommands.
+            Some(Command::SelfTest) => cli.files.push(PathBuf::from("self-test")),
+            Some(Command::Serve { .. }) => cli.files.push(PathBuf::from("serve")),
+            None => {}
+        }
+        let color_given =
+            std::env::args().any(|arg| arg == "--color" || arg.starts_with("--color="));
+        if !color_given {
+            cli.color = ColorMode::Never;
+        }
+    }
+    if let Some(command) = &cli.command {
+        return match command {
+            Command::SelfTest => run_self_test(),
+            Command::Serve { socket } => run_serve(&cli, socket),
+        };
+    }
+    let sel = cli.selection();
+    let mode = detect_count_mode(&cli);
+    let encoding = match resolve_encoding(&cli) {
+        Ok(encoding) => encoding,
+        Err(err) => {
+            eprintln!("wc-rs: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    // East Asian multibyte locales decode through the same transcoder an
+    // explicit --encoding uses, so ja_JP.eucJP and friends count like the
+    // system wc under those locales.
+    let encoding = encoding.or_else(|| {
+        (cli.locale_encoding == LocaleEncoding::Auto && cli.encoding.is_none())
+            .then(native_locale_encoding)
+            .flatten()
+            .map(EncodingSelector::Fixed)
+    });
+    // Asking for normalization implies Unicode semantics, so without an
+    // explicit encoding the text is decoded as UTF-8.
+    let encoding = match (encoding, cli.normalize) {
+        (None, Normalization::None) => None,
+        (selector, normalize) => Some(DecodePipeline {
+            selector: selector.unwrap_or(EncodingSelector::Fixed(encoding_rs::UTF_8)),
+            normalize,
+        }),
+    };
+    let job = CountJob {
+        sel,
+        mode,
+        encoding,
+        range: cli.range,
+        max_bytes: cli.max_bytes,
+        max_lines: cli.max_lines,
+        verify: cli.verify,
+        tab_width: cli.tab_size,
+        partial: cli.partial,
+        retries: cli.retries,
+        decompress: cli.decompress,
+        madvise: !cli.no_madvise,
+        debug: cli.debug,
+    };
+
+    if cli.backend == BackendChoice::AutoBench {
+        let fastest = bench_fastest();
+        if cli.debug {
+            eprintln!("wc-rs: auto-bench selected the {fastest:?} backend");
+        }
+        pin_backend(fastest);
+    }
+
+    let topology = hybrid_topology();
+    if cli.debug {
+        if let Some(topology) = &topology {
+            eprintln!(
+                "wc-rs: hybrid CPU: {} performance cores, {} efficiency cores",
+                topology.performance.len(),
+                topology.efficiency.len()
+            );
+        }
+    }
+    let threads = cli.threads.map(|threads| match threads {
+        ThreadCount::Fixed(n) => n,
+        ThreadCount::Auto => auto_thread_count(&cli),
+    });
+    // Errors only if a global pool already exists, which cannot happen
+    // this early in the binary.
+    if let (true, Some(topology)) = (cli.prefer_p_cores, topology) {
+        let cores = topology.performance;
+        let _ = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads.unwrap_or(cores.len()))
+            .start_handler(move |_| pin_current_thread_to(&cores))
+            .build_global();
+    } else if let Some(threads) = threads {
+        let _ = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global();
+    }
+
+    if let Err(err) = cli.validate() {
+        eprintln!("wc-rs: {err}");
+        return ExitCode::FAILURE;
+    }
+
+    let posix = cli.posix_mode();
+    if posix {
+        if let Some(violation) = cli.posix_violation() {
+            eprintln!("wc-rs: {violation}");
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let rusage = RusageReport::new(cli.rusage);
+
+    // An implicit stdin read from a terminal looks like a hang; say so,
+    // unless a script asked for silence.
+    if should_hint_tty(&cli, posix, io::stdin().is_terminal()) {
+        eprintln!("wc-rs: {}", translate(Message::ReadingFromTerminal));
+    }
+
+    if let Some(manifest) = &cli.check {
+        return run_check(manifest);
+    }
+    if let Some(dest) = &cli.write_manifest {
+        return run_write_manifest(&cli, dest, sel, job.mode);
+    }
+    if cli.rpc {
+        return run_rpc(sel, job.mode);
+    }
+
+    // A list arriving on stdin or through a pipe cannot be sized up front;
+    // parse it incrementally and count each file as its name arrives.
+    if let Some(list_path) = &cli.files0_from {
+        if !is_regular_file(list_path) {
+            return run_files0_streaming(list_path, &cli, job, &rusage);
+        }
+    }
+
+    let (inputs, mut failed) = match resolve_inputs(&cli) {
+        Ok(resolved) => resolved,
+        Err(err) => {
+            eprintln!("wc-rs: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if cli.watch {
+        return run_watch(&cli, &inputs, job);
+    }
+    if let Some(delim) = &cli.fields {
+        return run_fields(&cli, &inputs, delim.as_bytes()[0], failed, &rusage);
+    }
+    if cli.line_endings {
+        return run_line_endings(&cli, &inputs, failed, &rusage);
+    }
+    if cli.char_classes {
+        return run_char_classes(&cli, &inputs, failed, &rusage);
+    }
+    if cli.entropy {
+        return run_entropy(&cli, &inputs, failed, &rusage);
+    }
+
+    let sizes: Vec<Option<u64>> = inputs
+        .iter()
+        .map(|input| Some(range_overlap(input.size()?, cli.range)))
+        .collect();
+    let strategy = choose_strategy(cli.parallel_mode, &sizes, sel, rayon::current_num_threads());
+
+    // Stdin is one shared stream: the first `-` operand consumes it and any
+    // later `-` sees EOF, as in GNU. Making that explicit also keeps the
+    // parallel map from racing multiple workers on the same descriptor.
+    let first_stdin = inputs.iter().position(|input| *input == Input::Stdin);
+
+    if let Some(window) = cli.window {
+        return run_window(&cli, &inputs, job, window, &sizes, failed, &rusage);
+    }
+    if cli.output == OutputFormat::Ndjson {
+        return run_ndjson(&cli, &inputs, job, strategy, failed, &rusage);
+    }
+    if cli.unordered {
+        return run_unordered(&cli, &inputs, job, strategy, &sizes, failed, &rusage);
+    }
+
+    let results: Vec<io::Result<(Counts, RowFlags)>> = match strategy {
+        Strategy::Files if inputs.len() > 1 => {
+            let stdin_counts =
+                first_stdin.map(|_| count_input(&Input::Stdin, job, Strategy::Files));
+            let fd_limit = OpenFileLimit::from_rlimit();
+            let mut results: Vec<io::Result<(Counts, RowFlags)>> = inputs
+                .par_iter()
+                .map(|input| match input {
+                    Input::Stdin => Ok((Counts::default(), RowFlags::default())),
+                    _ => {
+                        let _permit = fd_limit.acquire();
+                        count_input(input, job, Strategy::Files)
+                    }
+                })
+                .collect();
+            if let (Some(index), Some(counts)) = (first_stdin, stdin_counts) {
+                results[index] = counts;
+            }
+            results
+        }
+        _ => {
+            let mut stdin_consumed = false;
+            inputs
+                .iter()
+                .map(|input| {
+                    if *input == Input::Stdin {
+                        if stdin_consumed {
+                            return Ok((Counts::default(), RowFlags::default()));
+                        }
+                        stdin_consumed = true;
+                    }
+                    count_one(input, &cli, job, strategy)
+                })
+                .collect()
+        }
+    };
+
+    let mut total = Counts::default();
+    let mut rows: Vec<(Counts, Vec<u8>, RowFlags)> = Vec::with_capacity(inputs.len());
+    let mut dir_groups: BTreeMap<PathBuf, Counts> = BTreeMap::new();
+    let mut errors: Vec<String> = Vec::new();
+    for (input, result) in inputs.iter().zip(results) {
+        match result {
+            Ok((counts, flags)) => {
+                warn_missing_newline(&cli, input, flags);
+                add_to_total(&mut total, counts, cli.saturate);
+                if let Some(depth) = cli.group_by_dir {
+                    add_dir_groups(&mut dir_groups, input, counts, depth);
+                }
+                rows.push((counts, input.name_bytes(), flags));
+            }
+            Err(err) => {
+                let (prefix, err) = split_partial(err);
+                if let Some((counts, flags)) = prefix {
+                    warn_missing_newline(&cli, input, flags);
+                    add_to_total(&mut total, counts, cli.saturate);
+                    if let Some(depth) = cli.group_by_dir {
+                        add_dir_groups(&mut dir_groups, input, counts, depth);
+                    }
+                    rows.push((counts, input.name_bytes(), flags));
+                }
+                errors.push(format!("wc-rs: {}: {}", diagnostic_name(&cli, input), err));
+                failed = true;
+            }
+        }
+    }
+    rusage.add_bytes(total.bytes);
+    if cli.has_assert_limits() {
+        for (counts, name, _) in &rows {
+            failed |= check_assert_limits(&cli, counts, &diagnostic_name_bytes(&cli, name), false);
+        }
+        failed |= check_assert_limits(&cli, &total, total_label(&cli), true);
+    }
+
+    let format = NumberFormat::from_cli(&cli);
+    // POSIX mode keeps the output bare: no colors, no column alignment.
+    let style = if posix {
+        Style::for_stream(ColorMode::Never, false)
+    } else {
+        Style::for_stream(cli.color, output_is_terminal(&cli))
+    };
+    let err_style = Style::for_stream(cli.color, io::stderr().is_terminal());
+    let print_rows = cli.total != TotalMode::Only;
+    let print_total = match cli.total {
+        TotalMode::Auto => inputs.len() > 1,
+        TotalMode::Always | TotalMode::Only => true,
+        TotalMode::Never => false,
+    };
+    for message in &errors {
+        eprintln!("{}", err_style.error(message));
+    }
+    let width = if posix {
+        // POSIX asks for fields separated by blanks, not aligned columns.
+        1
+    } else {
+        match &format {
+            NumberFormat::Raw => number_width(&sizes, sel, &rows),
+            // Scaled or grouped values no longer track byte sizes; align to
+            // the widest rendered field instead.
+            _ => rendered_width(
+                &format,
+                sel,
+                &rows,
+                print_total.then_some(&total),
+                cli.precision,
+            ),
+        }
+    };
+    let mut out = match open_output(&cli) {
+        Ok(out) => out,
+        Err(err) => return exit_for_output_error(&cli, err),
+    };
+    let mut write = || -> io::Result<()> {
+        if cli.output == OutputFormat::OpenMetrics {
+            write_openmetrics(&mut out, &rows, sel)?;
+            return Ok(());
+        }
+        if print_rows {
+            for (counts, name, flags) in &rows {
+                let name = styled_row_name(&cli, &style, name, *flags);
+                write_counts(
+                    &mut out,
+                    counts,
+                    sel,
+                    &format,
+                    width,
+                    cli.precision,
+                    name.as_deref(),
+                )?;
+            }
+        }
+        if cli.group_by_dir.is_some() {
+            for (dir, counts) in &dir_groups {
+                let name = style.file_name(&quote_name(&dir_name_bytes(dir), cli.quoting_style));
+                write_counts(
+                    &mut out,
+                    counts,
+                    sel,
+                    &format,
+                    width,
+                    cli.precision,
+                    Some(&name),
+                )?;
+            }
+        }
+        if print_total {
+            let label = style.total(&total_row_label(&cli));
+            write_counts(
+                &mut out,
+                &total,
+                sel,
+                &format,
+                width,
+                cli.precision,
+                Some(&label),
+            )?;
+        }
+        Ok(())
+    };
+    let written = write();
+    if let Err(err) = written.and_then(|()| out.commit()) {
+        return exit_for_write_error(err);
+    }
+
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Below this much sized input, `-j auto` stays single-threaded: pool
+/// startup and work splitting cost more than they save.
+const AUTO_SINGLE_THREAD_BYTES: u64 = 4 * 1024 * 1024;
+
+/// Pick a worker count for `-j auto` from the workload rather than the
+/// machine alone: one worker per operand when files are counted whole,
+/// the full CPU count when a file is big enough for intra-file chunk
+/// splitting, and one thread for batches too small to amortize a pool.
+fn auto_thread_count(cli: &Cli) -> usize {
+    let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let sizes: Vec<Option<u64>> = cli
+        .files
+        .iter()
+        .map(|path| {
+            let meta = std::fs::metadata(openable_path(path)).ok()?;
+            meta.is_file().then_some(meta.len())
+        })
+        .collect();
+    let sized: u64 = sizes.iter().flatten().sum();
+    let all_sized = !sizes.is_empty() && sizes.iter().all(Option::is_some);
+    // Mirrors choose_strategy: chunk splitting needs mergeable counters,
+    // a mode that allows it, and at least one file worth splitting.
+    let splittable = cli.parallel_mode != ParallelMode::Files
+        && cli.selection().is_chunk_mergeable()
+        && sizes
+            .iter()
+            .any(|size| matches!(size, Some(n) if *n >= MIN_CHUNK_SPLIT_BYTES));
+    let threads = if all_sized && sized < AUTO_SINGLE_THREAD_BYTES && !splittable {
+        1
+    } else if splittable {
+        cpus
+    } else {
+        sizes.len().max(1).min(cpus)
+    };
+    if cli.debug {
+        eprintln!(
+            "wc-rs: -j auto chose {threads} of {cpus} threads: {} operands, {sized} sized bytes, \
+             chunk splitting {}",
+            sizes.len(),
+            if splittable { "possible" } else { "off" }
+        );
+    }
+    threads
+}
+
+/// The styled, quoted name a table row prints, with truncation markers
+/// appended. Names are shown whenever operands were given explicitly;
+/// only the implicit stdin row is nameless.
+fn styled_row_name(cli: &Cli, style: &Style, name: &[u8], flags: RowFlags) -> Option<Vec<u8>> {
+    let show_names = !cli.files.is_empty() || cli.files0_from.is_some();
+    show_names.then(|| {
+        let mut styled = style.file_name(&quote_name(name, cli.quoting_style));
+        if flags.truncated {
+            styled.extend_from_slice(b" (truncated)");
+        }
+        if flags.partial {
+            styled.extend_from_slice(b" (partial)");
+        }
+        styled
+    })
+}
+
+/// Fold one file's counts into every ancestor directory the depth limit
+/// keeps, so each directory row is a subtree total, like `du`. Inputs
+/// without a path — stdin, descriptors, URLs — belong to no directory.
+fn add_dir_groups(
+    groups: &mut BTreeMap<PathBuf, Counts>,
+    input: &Input,
+    counts: Counts,
+    depth: Option<usize>,
+) {
+    let Input::File(path) = input else { return };
+    let mut dir = path.parent();
+    while let Some(d) = dir {
+        if d.as_os_str().is_empty() {
+            break;
+        }
+        if depth.is_none_or(|max| d.components().count() <= max) {
+            *groups.entry(d.to_path_buf()).or_default() += counts;
+        }
+        dir = d.parent();
+    }
+}
+
+/// A directory's name as raw bytes, matching how file rows print theirs.
+#[cfg(unix)]
+fn dir_name_bytes(dir: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    dir.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn dir_name_bytes(dir: &Path) -> Vec<u8> {
+    dir.display().to_string().into_bytes()
+}
+
+/// Decide byte vs UTF-8 interpretation: an explicit --locale-encoding wins,
+/// otherwise the platform locale is consulted.
+fn detect_count_mode(cli: &Cli) -> CountMode {
+    match cli.locale_encoding {
+        LocaleEncoding::Utf8 => CountMode::Utf8,
+        LocaleEncoding::SingleByte => CountMode::Bytes,
+        LocaleEncoding::Auto => native_count_mode(),
+    }
+}
+
+/// A multibyte (non-UTF-8) charset implied by the locale, if any.
+#[cfg(not(windows))]
+fn native_locale_encoding() -> Option<&'static encoding_rs::Encoding> {
+    wc_rs::locale::detect_locale().encoding
+}
+
+/// ANSI code pages for the CJK locales Windows installs use.
+#[cfg(windows)]
+fn native_locale_encoding() -> Option<&'static encoding_rs::Encoding> {
+    extern "system" {
+        fn GetACP() -> u32;
+    }
+    // SAFETY: GetACP takes no arguments and only reads process state.
+    let label = match unsafe { GetACP() } {
+        932 => "shift_jis",
+        936 => "gbk",
+        949 => "euc-kr",
+        950 => "big5",
+        _ => return None,
+    };
+    encoding_rs::Encoding::for_label(label.as_bytes())
+}
+
+/// The usual locale environment variables, in glibc precedence order; the
+/// scan itself lives in [`wc_rs::locale`] so embedders share it.
+#[cfg(not(windows))]
+fn native_count_mode() -> CountMode {
+    wc_rs::locale::detect_locale().mode
+}
+
+/// Windows consoles report their encoding as a code page, not through env
+/// vars: prefer the console output code page, falling back to the ANSI code
+/// page for non-console sessions. Only 65001 (UTF-8) decodes multibyte
+/// characters; every ANSI page is treated as single-byte.
+#[cfg(windows)]
+fn native_count_mode() -> CountMode {
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetConsoleOutputCP() -> u32;
+        fn GetACP() -> u32;
+    }
+    // SAFETY: both calls take no arguments and only read process state.
+    let code_page = unsafe {
+        match GetConsoleOutputCP() {
+            0 => GetACP(),
+            cp => cp,
+        }
+    };
+    const CP_UTF8: u32 = 65001;
+    if code_page == CP_UTF8 {
+        CountMode::Utf8
+    } else {
+        CountMode::Bytes
+    }
+}
+
+/// Whether a `--files0-from` source can be slurped and sized up front.
+/// `-` and non-regular files (pipes from `find -print0`) cannot.
+fn is_regular_file(path: &Path) -> bool {
+    path != Path::new("-")
+        && std::fs::metadata(path)
+            .map(|meta| meta.is_file())
+            .unwrap_or(false)
+}
+
+/// Count the inputs and emit one JSON object per file the moment it
+/// finishes, so consumers of very long runs can stream results. Under the
+/// per-file parallel strategy objects appear in completion order; each one
+/// carries its file name.
+fn run_ndjson(
+    cli: &Cli,
+    inputs: &[Input],
+    job: CountJob,
+    strategy: Strategy,
+    mut failed: bool,
+    rusage: &RusageReport,
+) -> ExitCode {
+    let CountJob { sel, .. } = job;
+    let first_stdin = inputs.iter().position(|input| *input == Input::Stdin);
+    let err_style = Style::for_stream(cli.color, io::stderr().is_terminal());
+    let mut out = match open_output(cli) {
+        Ok(out) => out,
+        Err(err) => return exit_for_output_error(cli, err),
+    };
+    let mut total = Counts::default();
+    let mut emit = |out: &mut dyn Write,
+                    input: &Input,
+                    result: io::Result<(Counts, RowFlags)>|
+     -> io::Result<()> {
+        match result {
+            Ok((counts, flags)) => {
+                warn_missing_newline(cli, input, flags);
+                add_to_total(&mut total, counts, cli.saturate);
+                failed |= check_assert_limits(cli, &counts, &diagnostic_name(cli, input), false);
+                if cli.total != TotalMode::Only {
+                    writeln!(
+                        out,
+                        "{}",
+                        ndjson_row(
+                            Some(&input.display_name()),
+                            &counts,
+                            sel,
+                            flags,
+                            cli.with_metadata
+                                .then(|| row_metadata(input, &job))
+                                .as_ref(),
+                        )
+                    )?;
+                    out.flush()?;
+                }
+            }
+            Err(err) => {
+                let (prefix, err) = split_partial(err);
+                if let Some((counts, flags)) = prefix {
+                    warn_missing_newline(cli, input, flags);
+                    add_to_total(&mut total, counts, cli.saturate);
+                    if cli.total != TotalMode::Only {
+                        writeln!(
+                            out,
+                            "{}",
+                            ndjson_row(
+                                Some(&input.display_name()),
+                                &counts,
+                                sel,
+                                flags,
+                                cli.with_metadata
+                                    .then(|| row_metadata(input, &job))
+                                    .as_ref(),
+                            )
+                        )?;
+                        out.flush()?;
+                    }
+                }
+                let message = format!("wc-rs: {}: {}", diagnostic_name(cli, input), err);
+                eprintln!("{}", err_style.error(&message));
+                failed = true;
+            }
+        }
+        Ok(())
+    };
+    let written = match strategy {
+        Strategy::Files if inputs.len() > 1 => {
+            let stdin_counts =
+                first_stdin.map(|_| count_input(&Input::Stdin, job, Strategy::Files));
+            let fd_limit = OpenFileLimit::from_rlimit();
+            let (sender, receiver) = std::sync::mpsc::channel();
+            let mut written = Ok(());
+            std::thread::scope(|scope| {
+                scope.spawn(|| {
+                    // The pre-read stdin result goes out first; the files
+                    // then stream in completion order.
+                    if let (Some(index), Some(result)) = (first_stdin, stdin_counts) {
+                        let _ = sender.send((index, result));
+                    }
+                    inputs.par_iter().enumerate().for_each_with(
+                        sender,
+                        |sender, (index, input)| {
+                            if Some(index) == first_stdin {
+                                return;
+                            }
+                            let result = match input {
+                                Input::Stdin => Ok((Counts::default(), RowFlags::default())),
+                                _ => {
+                                    let _permit = fd_limit.acquire();
+                                    count_input(input, job, Strategy::Files)
+                                }
+                            };
+                            let _ = sender.send((index, result));
+                        },
+                    );
+                });
+                for (index, result) in receiver {
+                    if written.is_ok() {
+                        written = emit(&mut out, &inputs[index], result);
+                    }
+                }
+            });
+            written
+        }
+        _ => {
+            let mut stdin_consumed = false;
+            let mut written = Ok(());
+            for input in inputs {
+                let result = if *input == Input::Stdin && stdin_consumed {
+                    Ok((Counts::default(), RowFlags::default()))
+                } else {
+                    stdin_consumed |= *input == Input::Stdin;
+                    count_one(input, cli, job, strategy)
+                };
+                written = emit(&mut out, input, result);
+                if written.is_err() {
+                    break;
+                }
+            }
+            written
+        }
+    };
+    if let Err(err) = written {
+        return exit_for_write_error(err);
+    }
+    rusage.add_bytes(total.bytes);
+    failed |= check_assert_limits(cli, &total, total_label(cli), true);
+    let print_total = match cli.total {
+        TotalMode::Auto => inputs.len() > 1,
+        TotalMode::Always | TotalMode::Only => true,
+        TotalMode::Never => false,
+    };
+    if print_total
+        && writeln!(
+            out,
+            "{}",
+            ndjson_row(None, &total, sel, RowFlags::default(), None)
+        )
+        .is_err()
+    {
+        return ExitCode::SUCCESS;
+    }
+    if let Err(err) = out.commit() {
+        return exit_for_write_error(err);
+    }
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// The `--unordered` run: print each table row the moment its file
+/// finishes instead of buffering until every result is in. Under the
+/// per-file parallel strategy rows appear in completion order — the point
+/// of the mode — and the totals row still sums every input. The column
+/// width comes from the operand sizes, since later rows are unknown when
+/// the first one prints.
+fn run_unordered(
+    cli: &Cli,
+    inputs: &[Input],
+    job: CountJob,
+    strategy: Strategy,
+    sizes: &[Option<u64>],
+    mut failed: bool,
+    rusage: &RusageReport,
+) -> ExitCode {
+    let CountJob { sel, .. } = job;
+    let first_stdin = inputs.iter().position(|input| *input == Input::Stdin);
+    let style = Style::for_stream(cli.color, output_is_terminal(cli));
+    let err_style = Style::for_stream(cli.color, io::stderr().is_terminal());
+    let format = NumberFormat::from_cli(cli);
+    let width = unordered_width(&format, sizes);
+    let mut out = match open_output(cli) {
+        Ok(out) => out,
+        Err(err) => return exit_for_output_error(cli, err),
+    };
+    let mut total = Counts::default();
+    let mut dir_groups: BTreeMap<PathBuf, Counts> = BTreeMap::new();
+    let mut emit = |out: &mut Output,
+                    input: &Input,
+                    result: io::Result<(Counts, RowFlags)>|
+     -> io::Result<()> {
+        match result {
+            Ok((counts, flags)) => {
+                warn_missing_newline(cli, input, flags);
+                add_to_total(&mut total, counts, cli.saturate);
+                failed |= check_assert_limits(cli, &counts, &diagnostic_name(cli, input), false);
+                if let Some(depth) = cli.group_by_dir {
+                    add_dir_groups(&mut dir_groups, input, counts, depth);
+                }
+                if cli.total != TotalMode::Only {
+                    let name = styled_row_name(cli, &style, &input.name_bytes(), flags);
+                    write_counts(
+                        out,
+                        &counts,
+                        sel,
+                        &format,
+                        width,
+                        cli.precision,
+                        name.as_deref(),
+                    )?;
+                    out.flush()?;
+                }
+            }
+            Err(err) => {
+                let (prefix, err) = split_partial(err);
+                if let Some((counts, flags)) = prefix {
+                    warn_missing_newline(cli, input, flags);
+                    add_to_total(&mut total, counts, cli.saturate);
+                    if let Some(depth) = cli.group_by_dir {
+                        add_dir_groups(&mut dir_groups, input, counts, depth);
+                    }
+                    if cli.total != TotalMode::Only {
+                        let name = styled_row_name(cli, &style, &input.name_bytes(), flags);
+                        write_counts(
+                            out,
+                            &counts,
+                            sel,
+                            &format,
+                            width,
+                            cli.precision,
+                            name.as_deref(),
+                        )?;
+                        out.flush()?;
+                    }
+                }
+                let message = format!("wc-rs: {}: {}", diagnostic_name(cli, input), err);
+                eprintln!("{}", err_style.error(&message));
+                failed = true;
+            }
+        }
+        Ok(())
+    };
+    let written = match strategy {
+        Strategy::Files if inputs.len() > 1 => {
+            let stdin_counts =
+                first_stdin.map(|_| count_input(&Input::Stdin, job, Strategy::Files));
+            let fd_limit = OpenFileLimit::from_rlimit();
+            let (sender, receiver) = std::sync::mpsc::channel();
+            let mut written = Ok(());
+            std::thread::scope(|scope| {
+                scope.spawn(|| {
+                    // The pre-read stdin result goes out first; the files
+                    // then stream in completion order.
+                    if let (Some(index), Some(result)) = (first_stdin, stdin_counts) {
+                        let _ = sender.send((index, result));
+                    }
+                    inputs.par_iter().enumerate().for_each_with(
+                        sender,
+                        |sender, (index, input)| {
+                            if Some(index) == first_stdin {
+                                return;
+                            }
+                            let result = match input {
+                                Input::Stdin => Ok((Counts::default(), RowFlags::default())),
+                                _ => {
+                                    let _permit = fd_limit.acquire();
+                                    count_input(input, job, Strategy::Files)
+                                }
+                            };
+                            let _ = sender.send((index, result));
+                        },
+                    );
+                });
+                for (index, result) in receiver {
+                    if written.is_ok() {
+                        written = emit(&mut out, &inputs[index], result);
+                    }
+                }
+            });
+            written
+        }
+        _ => {
+            let mut stdin_consumed = false;
+            let mut written = Ok(());
+            for input in inputs {
+                let result = if *input == Input::Stdin && stdin_consumed {
+                    Ok((Counts::default(), RowFlags::default()))
+                } else {
+                    stdin_consumed |= *input == Input::Stdin;
+                    count_one(input, cli, job, strategy)
+                };
+                written = emit(&mut out, input, result);
+                if written.is_err() {
+                    break;
+                }
+            }
+            written
+        }
+    };
+    if let Err(err) = written {
+        return exit_for_write_error(err);
+    }
+    rusage.add_bytes(total.bytes);
+    failed |= check_assert_limits(cli, &total, total_label(cli), true);
+    let print_total = match cli.total {
+        TotalMode::Auto => inputs.len() > 1,
+        TotalMode::Always | TotalMode::Only => true,
+        TotalMode::Never => false,
+    };
+    let mut finish = || -> io::Result<()> {
+        if cli.group_by_dir.is_some() {
+            for (dir, counts) in &dir_groups {
+                let name = style.file_name(&quote_name(&dir_name_bytes(dir), cli.quoting_style));
+                write_counts(
+                    &mut out,
+                    counts,
+                    sel,
+                    &format,
+                    width,
+                    cli.precision,
+                    Some(&name),
+                )?;
+            }
+        }
+        if print_total {
+            let label = style.total(&total_row_label(cli));
+            write_counts(
+                &mut out,
+                &total,
+                sel,
+                &format,
+                width,
+                cli.precision,
+                Some(&label),
+            )?;
+        }
+        Ok(())
+    };
+    if let Err(err) = finish() {
+        return exit_for_write_error(err);
+    }
+    if let Err(err) = out.commit() {
+        return exit_for_write_error(err);
+    }
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// The `--window` mode: the selected counters once per fixed-size block of
+/// lines or bytes through each input, restarting the stream scanner at
+/// every boundary. Rows are labeled `FILE:W` with `W` the 1-based window
+/// index; a shorter final window still prints, and the totals row sums
+/// every window.
+fn run_window(
+    cli: &Cli,
+    inputs: &[Input],
+    job: CountJob,
+    window: WindowSpec,
+    sizes: &[Option<u64>],
+    mut failed: bool,
+    rusage: &RusageReport,
+) -> ExitCode {
+    let style = Style::for_stream(cli.color, output_is_terminal(cli));
+    let err_style = Style::for_stream(cli.color, io::stderr().is_terminal());
+    let format = NumberFormat::from_cli(cli);
+    // Later windows are unknown when the first row prints, so the column
+    // width comes from the operand sizes, as in --unordered.
+    let width = unordered_width(&format, sizes);
+    let mut out = match open_output(cli) {
+        Ok(out) => out,
+        Err(err) => return exit_for_output_error(cli, err),
+    };
+    let mut total = Counts::default();
+    let mut stdin_consumed = false;
+    for input in inputs {
+        let consumed = *input == Input::Stdin && stdin_consumed;
+        stdin_consumed |= *input == Input::Stdin;
+        let new_counter = || {
+            StreamCounter::new(job.sel, job.mode, detect_simd_path()).with_tab_width(job.tab_width)
+        };
+        let mut counter = new_counter();
+        let mut index = 0u64;
+        // Lines or bytes in the open window, per the window unit.
+        let mut fill = 0u64;
+        // Raw bytes in the open window, deciding whether the final
+        // (possibly empty) window is worth a row.
+        let mut open_bytes = 0u64;
+        let mut written: io::Result<()> = Ok(());
+        let mut emit = |out: &mut Output, counts: &Counts, index: u64| -> io::Result<()> {
+            add_to_total(&mut total, *counts, cli.saturate);
+            if cli.total == TotalMode::Only {
+                return Ok(());
+            }
+            let mut name = quote_name(&input.name_bytes(), cli.quoting_style);
+            name.extend_from_slice(format!(":{}", index + 1).as_bytes());
+            let name = style.file_name(&name);
+            write_counts(
+                out,
+                counts,
+                job.sel,
+                &format,
+                width,
+                cli.precision,
+                Some(&name),
+            )
+        };
+        let streamed = if consumed {
+            Ok(0)
+        } else {
+            stream_input(input, job.retries, job.decompress, |mut buf| {
+                while !buf.is_empty() && written.is_ok() {
+                    let (take, complete) = if window.bytes {
+                        let room = usize::try_from(window.size - fill).unwrap_or(usize::MAX);
+                        (room.min(buf.len()), buf.len() >= room)
+                    } else {
+                        let need = (window.size - fill) as usize;
+                        match memchr::memchr_iter(b'\n', buf).nth(need - 1) {
+                            Some(pos) => (pos + 1, true),
+                            None => (buf.len(), false),
+                        }
+                    };
+                    counter.update(&buf[..take]);
+                    open_bytes += take as u64;
+                    if complete {
+                        written = emit(
+                            &mut out,
+                            &std::mem::replace(&mut counter, new_counter()).finish(),
+                            index,
+                        );
+                        index += 1;
+                        fill = 0;
+                        open_bytes = 0;
+                    } else {
+                        fill += if window.bytes {
+                            take as u64
+                        } else {
+                            memchr::memchr_iter(b'\n', &buf[..take]).count() as u64
+                        };
+                    }
+                    buf = &buf[take..];
+                }
+            })
+        };
+        match streamed {
+            Ok(bytes) => {
+                rusage.add_bytes(bytes);
+                // The trailing short window; an empty input still gets one
+                // all-zero row.
+                if written.is_ok() && (open_bytes > 0 || index == 0) {
+                    written = emit(&mut out, &counter.finish(), index);
+                }
+            }
+            Err(err) => {
+                let message = format!("wc-rs: {}: {}", diagnostic_name(cli, input), err);
+                eprintln!("{}", err_style.error(&message));
+                failed = true;
+            }
+        }
+        if let Err(err) = written {
+            return exit_for_write_error(err);
+        }
+    }
+    let print_total = match cli.total {
+        TotalMode::Auto => inputs.len() > 1,
+        TotalMode::Always | TotalMode::Only => true,
+        TotalMode::Never => false,
+    };
+    if print_total {
+        let label = style.total(&total_row_label(cli));
+        if let Err(err) = write_counts(
+            &mut out,
+            &total,
+            job.sel,
+            &format,
+            width,
+            cli.precision,
+            Some(&label),
+        ) {
+            return exit_for_write_error(err);
+        }
+    }
+    if let Err(err) = out.commit() {
+        return exit_for_write_error(err);
+    }
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// How long `--watch` sleeps between metadata polls when change events
+/// are unavailable.
+const WATCH_POLL: Duration = Duration::from_millis(500);
+
+/// One operand's identity in a watch snapshot: modification time and
+/// size, or `None` while the file is missing or unreadable.
+type WatchStamp = Option<(SystemTime, u64)>;
+
+fn watch_snapshot(paths: &[&Path]) -> Vec<WatchStamp> {
+    paths
+        .iter()
+        .map(|path| {
+            let meta = std::fs::metadata(openable_path(path)).ok()?;
+            Some((meta.modified().ok()?, meta.len()))
+        })
+        .collect()
+}
+
+/// The `--watch` loop: re-count and reprint the operands whenever one of
+/// them changes. It runs until interrupted, so the exit status only ever
+/// reports a setup problem.
+fn run_watch(cli: &Cli, inputs: &[Input], job: CountJob) -> ExitCode {
+    let mut paths = Vec::with_capacity(inputs.len());
+    for input in inputs {
+        match input {
+            Input::File(path) => paths.push(path.as_path()),
+            _ => {
+                eprintln!(
+                    "wc-rs: --watch monitors files, not {}",
+                    input.display_name()
+                );
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    let format = NumberFormat::from_cli(cli);
+    let style = Style::for_stream(cli.color, output_is_terminal(cli));
+    let err_style = Style::for_stream(cli.color, io::stderr().is_terminal());
+    let print_total = match cli.total {
+        TotalMode::Auto => inputs.len() > 1,
+        TotalMode::Always | TotalMode::Only => true,
+        TotalMode::Never => false,
+    };
+    let mut stamps = watch_snapshot(&paths);
+    loop {
+        let sizes: Vec<Option<u64>> = inputs
+            .iter()
+            .map(|input| Some(range_overlap(input.size()?, cli.range)))
+            .collect();
+        let strategy = choose_strategy(
+            cli.parallel_mode,
+            &sizes,
+            job.sel,
+            rayon::current_num_threads(),
+        );
+        let mut rows: Vec<(Counts, Vec<u8>, RowFlags)> = Vec::with_capacity(inputs.len());
+        let mut total = Counts::default();
+        for input in inputs {
+            match count_input(input, job, strategy) {
+                Ok((counts, flags)) => {
+                    add_to_total(&mut total, counts, cli.saturate);
+                    rows.push((counts, input.name_bytes(), flags));
+                }
+                Err(err) => {
+                    // A file can legitimately vanish mid-edit; diagnose it
+                    // and keep watching rather than give up.
+                    let (prefix, err) = split_partial(err);
+                    if let Some((counts, flags)) = prefix {
+                        add_to_total(&mut total, counts, cli.saturate);
+                        rows.push((counts, input.name_bytes(), flags));
+                    }
+                    let message = format!("wc-rs: {}: {}", diagnostic_name(cli, input), err);
+                    eprintln!("{}", err_style.error(&message));
+                }
+            }
+        }
+        let width = match &format {
+            NumberFormat::Raw => number_width(&sizes, job.sel, &rows),
+            _ => rendered_width(
+                &format,
+                job.sel,
+                &rows,
+                print_total.then_some(&total),
+                cli.precision,
+            ),
+        };
+        let stdout = io::stdout();
+        let mut out = BufWriter::new(stdout.lock());
+        let written = (|| -> io::Result<()> {
+            if cli.watch_format == WatchFormat::Clear {
+                // Home the cursor and wipe the previous report.
+                out.write_all(b"\x1b[H\x1b[2J")?;
+            }
+            if cli.total != TotalMode::Only {
+                for (counts, name, flags) in &rows {
+                    let name = styled_row_name(cli, &style, name, *flags);
+                    write_counts(
+                        &mut out,
+                        counts,
+                        job.sel,
+                        &format,
+                        width,
+                        cli.precision,
+                        name.as_deref(),
+                    )?;
+                }
+            }
+            if print_total {
+                let label = style.total(&total_row_label(cli));
+                write_counts(
+                    &mut out,
+                    &total,
+                    job.sel,
+                    &format,
+                    width,
+                    cli.precision,
+                    Some(&label),
+                )?;
+            }
+            out.flush()
+        })();
+        if let Err(err) = written {
+            return exit_for_write_error(err);
+        }
+        stamps = wait_for_change(&paths, &stamps);
+    }
+}
+
+/// Block until the watched files' metadata snapshot differs from
+/// `before`, and return the new snapshot. On Linux this parks on inotify
+/// events from the operands' parent directories — watching the directory
+/// rather than the file survives editors that save by renaming a
+/// temporary over the original — and every wakeup is checked against the
+/// snapshot so unrelated directory traffic does not trigger a redraw.
+#[cfg(target_os = "linux")]
+fn wait_for_change(paths: &[&Path], before: &[WatchStamp]) -> Vec<WatchStamp> {
+    use std::os::unix::ffi::OsStrExt;
+
+    // A change that landed while the previous report was being counted
+    // has no event coming; catch it by comparing snapshots up front.
+    let after = watch_snapshot(paths);
+    if after.as_slice() != before {
+        return after;
+    }
+    let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+    if fd < 0 {
+        return poll_for_change(paths, before);
+    }
+    let dirs: std::collections::BTreeSet<&Path> = paths
+        .iter()
+        .map(|path| match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        })
+        .collect();
+    let mask = libc::IN_CLOSE_WRITE
+        | libc::IN_MODIFY
+        | libc::IN_ATTRIB
+        | libc::IN_CREATE
+        | libc::IN_DELETE
+        | libc::IN_MOVED_TO
+        | libc::IN_MOVED_FROM;
+    let mut watching = false;
+    for dir in dirs {
+        let Ok(dir) = std::ffi::CString::new(dir.as_os_str().as_bytes()) else {
+            continue;
+        };
+        watching |= unsafe { libc::inotify_add_watch(fd, dir.as_ptr(), mask) } >= 0;
+    }
+    let after = loop {
+        if !watching {
+            break poll_for_change(paths, before);
+        }
+        let mut buf = [0u8; 4096];
+        let n = unsafe { libc::read(fd, buf.as_mut_ptr().cast(), buf.len()) };
+        if n <= 0 {
+            break poll_for_change(paths, before);
+        }
+        // Let a save that writes and then renames settle into one redraw.
+        std::thread::sleep(Duration::from_millis(20));
+        let after = watch_snapshot(paths);
+        if after.as_slice() != before {
+            break after;
+        }
+    };
+    unsafe { libc::close(fd) };
+    after
+}
+
+#[cfg(not(target_os = "linux"))]
+fn wait_for_change(paths: &[&Path], before: &[WatchStamp]) -> Vec<WatchStamp> {
+    let after = watch_snapshot(paths);
+    if after.as_slice() != before {
+        return after;
+    }
+    poll_for_change(paths, before)
+}
+
+/// The portable change detector: poll the files' metadata on a timer.
+fn poll_for_change(paths: &[&Path], before: &[WatchStamp]) -> Vec<WatchStamp> {
+    loop {
+        std::thread::sleep(WATCH_POLL);
+        let after = watch_snapshot(paths);
+        if after.as_slice() != before {
+            return after;
+        }
+    }
+}
+
+/// The `--fields` report: one row per input with the total field count,
+/// the fields-per-line width (a range when lines disagree), and a
+/// consistency verdict.
+fn run_fields(
+    cli: &Cli,
+    inputs: &[Input],
+    delim: u8,
+    mut failed: bool,
+    rusage: &RusageReport,
+) -> ExitCode {
+    let err_style = Style::for_stream(cli.color, io::stderr().is_terminal());
+    // Lock once and buffer: per-row write! calls against a bare StdoutLock
+    // each hit the kernel, which dominates runs over many files.
+    let mut out = match open_output(cli) {
+        Ok(out) => out,
+        Err(err) => return exit_for_output_error(cli, err),
+    };
+    let mut stdin_consumed = false;
+    for input in inputs {
+        let result = if *input == Input::Stdin && stdin_consumed {
+            Ok((FieldStats::default(), 0))
+        } else {
+            stdin_consumed |= *input == Input::Stdin;
+            count_fields_input(input, delim, cli.retries, cli.decompress)
+        };
+        match result {
+            Ok((stats, bytes)) => {
+                rusage.add_bytes(bytes);
+                let row = fields_row(&stats);
+                let written = write!(out, "{row} ")
+                    .and_then(|()| {
+                        out.write_all(&quote_name(&input.name_bytes(), cli.quoting_style))
+                    })
+                    .and_then(|()| writeln!(out));
+                if let Err(err) = written {
+                    return exit_for_write_error(err);
+                }
+            }
+            Err(err) => {
+                let message = format!("wc-rs: {}: {}", diagnostic_name(cli, input), err);
+                eprintln!("{}", err_style.error(&message));
+                failed = true;
+            }
+        }
+    }
+    if let Err(err) = out.commit() {
+        return exit_for_write_error(err);
+    }
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn count_fields_input(
+    input: &Input,
+    delim: u8,
+    retries: u32,
+    decompress: Decompress,
+) -> io::Result<(FieldStats, u64)> {
+    let mut counter = FieldCounter::new(delim);
+    let bytes = stream_input(input, retries, decompress, |buf| counter.update(buf))?;
+    Ok((counter.finish(), bytes))
+}
+
+/// The `--line-endings` report: LF, CRLF, and lone-CR tallies per input,
+/// with a verdict naming the single style in use or flagging a mix.
+fn run_line_endings(
+    cli: &Cli,
+    inputs: &[Input],
+    mut failed: bool,
+    rusage: &RusageReport,
+) -> ExitCode {
+    let err_style = Style::for_stream(cli.color, io::stderr().is_terminal());
+    let mut out = match open_output(cli) {
+        Ok(out) => out,
+        Err(err) => return exit_for_output_error(cli, err),
+    };
+    let mut stdin_consumed = false;
+    for input in inputs {
+        let result = if *input == Input::Stdin && stdin_consumed {
+            Ok((LineEndings::default(), 0))
+        } else {
+            stdin_consumed |= *input == Input::Stdin;
+            count_endings_input(input, cli.retries, cli.decompress)
+        };
+        match result {
+            Ok((endings, bytes)) => {
+                rusage.add_bytes(bytes);
+                let row = endings_row(&endings);
+                let written = write!(out, "{row} ")
+                    .and_then(|()| {
+                        out.write_all(&quote_name(&input.name_bytes(), cli.quoting_style))
+                    })
+                    .and_then(|()| writeln!(out));
+                if let Err(err) = written {
+                    return exit_for_write_error(err);
+                }
+            }
+            Err(err) => {
+                let message = format!("wc-rs: {}: {}", diagnostic_name(cli, input), err);
+                eprintln!("{}", err_style.error(&message));
+                failed = true;
+            }
+        }
+    }
+    if let Err(err) = out.commit() {
+        return exit_for_write_error(err);
+    }
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn count_endings_input(
+    input: &Input,
+    retries: u32,
+    decompress: Decompress,
+) -> io::Result<(LineEndings, u64)> {
+    let mut counter = EndingCounter::new(detect_simd_path());
+    let bytes = stream_input(input, retries, decompress, |buf| counter.update(buf))?;
+    Ok((counter.finish(), bytes))
+}
+
+/// The `--char-classes` report: letters, digits, punctuation, whitespace,
+/// control, and other characters per input.
+fn run_char_classes(
+    cli: &Cli,
+    inputs: &[Input],
+    mut failed: bool,
+    rusage: &RusageReport,
+) -> ExitCode {
+    let err_style = Style::for_stream(cli.color, io::stderr().is_terminal());
+    let mut out = match open_output(cli) {
+        Ok(out) => out,
+        Err(err) => return exit_for_output_error(cli, err),
+    };
+    let mut stdin_consumed = false;
+    for input in inputs {
+        let result = if *input == Input::Stdin && stdin_consumed {
+            Ok((CharClasses::default(), 0))
+        } else {
+            stdin_consumed |= *input == Input::Stdin;
+            count_classes_input(input, cli.retries, cli.decompress)
+        };
+        match result {
+            Ok((classes, bytes)) => {
+                rusage.add_bytes(bytes);
+                let row = classes_row(&classes);
+                let written = write!(out, "{row} ")
+                    .and_then(|()| {
+                        out.write_all(&quote_name(&input.name_bytes(), cli.quoting_style))
+                    })
+                    .and_then(|()| writeln!(out));
+                if let Err(err) = written {
+                    return exit_for_write_error(err);
+                }
+            }
+            Err(err) => {
+                let message = format!("wc-rs: {}: {}", diagnostic_name(cli, input), err);
+                eprintln!("{}", err_style.error(&message));
+                failed = true;
+            }
+        }
+    }
+    if let Err(err) = out.commit() {
+        return exit_for_write_error(err);
+    }
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn count_classes_input(
+    input: &Input,
+    retries: u32,
+    decompress: Decompress,
+) -> io::Result<(CharClasses, u64)> {
+    let mut counter = ClassCounter::new();
+    let bytes = stream_input(input, retries, decompress, |buf| counter.update(buf))?;
+    Ok((counter.finish(), bytes))
+}
+
+fn classes_row(classes: &CharClasses) -> String {
+    format!(
+        "{} {} {} {} {} {}",
+        classes.letters,
+        classes.digits,
+        classes.punctuation,
+        classes.whitespace,
+        classes.control,
+        classes.other
+    )
+}
+
+/// The `--entropy` report: each input's byte-level Shannon entropy in bits
+/// per byte.
+fn run_entropy(cli: &Cli, inputs: &[Input], mut failed: bool, rusage: &RusageReport) -> ExitCode {
+    let err_style = Style::for_stream(cli.color, io::stderr().is_terminal());
+    let mut out = match open_output(cli) {
+        Ok(out) => out,
+        Err(err) => return exit_for_output_error(cli, err),
+    };
+    let mut stdin_consumed = false;
+    for input in inputs {
+        let result = if *input == Input::Stdin && stdin_consumed {
+            Ok((0.0, 0))
+        } else {
+            stdin_consumed |= *input == Input::Stdin;
+            count_entropy_input(input, cli.retries, cli.decompress)
+        };
+        match result {
+            Ok((entropy, bytes)) => {
+                rusage.add_bytes(bytes);
+                let written = write!(out, "{entropy:.precision$} ", precision = cli.precision)
+                    .and_then(|()| {
+                        out.write_all(&quote_name(&input.name_bytes(), cli.quoting_style))
+                    })
+                    .and_then(|()| writeln!(out));
+                if let Err(err) = written {
+                    return exit_for_write_error(err);
+                }
+            }
+            Err(err) => {
+                let message = format!("wc-rs: {}: {}", diagnostic_name(cli, input), err);
+                eprintln!("{}", err_style.error(&message));
+                failed = true;
+            }
+        }
+    }
+    if let Err(err) = out.commit() {
+        return exit_for_write_error(err);
+    }
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn count_entropy_input(
+    input: &Input,
+    retries: u32,
+    decompress: Decompress,
+) -> io::Result<(f64, u64)> {
+    let mut hist = ByteHistogram::new();
+    let bytes = stream_input(input, retries, decompress, |buf| hist.update(buf))?;
+    Ok((hist.entropy(), bytes))
+}
+
+fn endings_row(endings: &LineEndings) -> String {
+    let verdict = if endings.is_mixed() {
+        "mixed"
+    } else if endings.lf > 0 {
+        "lf"
+    } else if endings.crlf > 0 {
+        "crlf"
+    } else if endings.cr > 0 {
+        "cr"
+    } else {
+        "none"
+    };
+    format!("{} {} {} {verdict}", endings.lf, endings.crlf, endings.cr)
+}
+
+/// Feed an input's bytes through `consume`, buffer by buffer, returning the
+/// number of bytes streamed.
+fn stream_input(
+    input: &Input,
+    retries: u32,
+    decompress: Decompress,
+    mut consume: impl FnMut(&[u8]),
+) -> io::Result<u64> {
+    let mut buf = vec![0u8; BUF_SIZE];
+    let mut reader: Box<dyn Read> = match input {
+        Input::Stdin => stdin_reader(retries, decompress)?,
+        #[cfg(feature = "http")]
+        Input::Url(url) => Box::new(RetryReader::new(url_reader(url)?, retries)),
+        Input::File(_) | Input::Fd(_) => Box::new(RetryReader::new(input.open_file()?, retries)),
+    };
+    let mut total = 0u64;
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            return Ok(total);
+        }
+        total += n as u64;
+        consume(&buf[..n]);
+    }
+}
+
+fn fields_row(stats: &FieldStats) -> String {
+    let width = match (stats.min_fields, stats.max_fields) {
+        (None, _) => "0".to_string(),
+        (Some(min), max) if min == max => min.to_string(),
+        (Some(min), max) => format!("{min}-{max}"),
+    };
+    let verdict = if stats.is_consistent() {
+        "consistent"
+    } else {
+        "ragged"
+    };
+    format!("{} {width} {verdict}", stats.total_fields)
+}
+
+/// One NDJSON object: selected counters plus either the file name or a
+/// `"total": true` marker.
+fn ndjson_row(
+    name: Option<&str>,
+    counts: &Counts,
+    sel: Selection,
+    flags: RowFlags,
+    metadata: Option<&RowMetadata>,
+) -> String {
+    let mut fields = Vec::new();
+    match name {
+        Some(name) => fields.push(format!("\"file\":\"{}\"", json_escape(name))),
+        None => {
+            fields.push("\"total\":true".to_string());
+            if TOTAL_SATURATED.load(Ordering::Relaxed) {
+                fields.push("\"saturated\":true".to_string());
+            }
+        }
+    }
+    for (selected, key, value) in [
+        (sel.lines, "lines", counts.lines),
+        (sel.words, "words", counts.words),
+        (sel.chars, "chars", counts.chars),
+        (sel.bytes, "bytes", counts.bytes),
+        (
+            sel.max_line_length,
+            "max_line_length",
+            counts.max_line_length,
+        ),
+        (
+            sel.max_words_per_line,
+            "max_words_per_line",
+            counts.max_words_per_line,
+        ),
+        (
+            sel.min_words_per_line,
+            "min_words_per_line",
+            counts.min_words_per_line.unwrap_or(0),
+        ),
+        (sel.unique_words, "unique_words", counts.unique_words),
+    ] {
+        if selected {
+            fields.push(format!("\"{key}\":{value}"));
+        }
+    }
+    if sel.avg_word_length {
+        fields.push(format!("\"avg_word_length\":{}", counts.avg_word_length()));
+    }
+    if let Some(meta) = metadata {
+        if let Some(size) = meta.size {
+            fields.push(format!("\"size\":{size}"));
+        }
+        if let Some(mtime) = meta.mtime {
+            fields.push(format!("\"mtime\":{mtime}"));
+        }
+        fields.push(format!("\"encoding\":\"{}\"", json_escape(&meta.encoding)));
+        fields.push(format!(
+            "\"backend\":\"{}\"",
+            format!("{:?}", meta.backend).to_lowercase()
+        ));
+    }
+    if flags.truncated {
+        fields.push("\"truncated\":true".to_string());
+    }
+    if flags.missing_newline {
+        fields.push("\"missing_final_newline\":true".to_string());
+    }
+    if flags.partial {
+        fields.push("\"partial\":true".to_string());
+    }
+    format!("{{{}}}", fields.join(","))
+}
+
+/// Per-file attributes `--with-metadata` appends to NDJSON rows, so
+/// downstream analytics can join counts with file facts without issuing
+/// stat calls of their own.
+struct RowMetadata {
+    /// Size from metadata; absent for stdin and non-regular files.
+    size: Option<u64>,
+    /// Modification time as seconds since the Unix epoch, when known.
+    mtime: Option<u64>,
+    /// The encoding the characters were decoded with: the counting mode
+    /// for plain runs, the encoding label when transcoding, or `auto`
+    /// when each input is sniffed.
+    encoding: String,
+    /// The counting backend in effect for this run.
+    backend: CountingBackend,
+}
+
+fn row_metadata(input: &Input, job: &CountJob) -> RowMetadata {
+    let meta = match input {
+        Input::File(path) => std::fs::metadata(openable_path(path)).ok(),
+        _ => None,
+    };
+    let size = meta.as_ref().and_then(|m| m.is_file().then_some(m.len()));
+    let mtime = meta
+        .as_ref()
+        .and_then(|m| m.modified().ok())
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs());
+    let encoding = match &job.encoding {
+        None => match job.mode {
+            CountMode::Utf8 => "utf8",
+            CountMode::Bytes => "bytes",
+        }
+        .to_string(),
+        Some(pipeline) => match pipeline.selector {
+            EncodingSelector::Fixed(enc) => enc.name().to_ascii_lowercase(),
+            EncodingSelector::Auto { .. } => "auto".to_string(),
+        },
+    };
+    RowMetadata {
+        size,
+        mtime,
+        encoding,
+        backend: detect_simd_path(),
+    }
+}
+
+/// Write the OpenMetrics exposition: one gauge family per selected counter,
+/// a sample per file, and the spec's `# EOF` terminator. Totals are left to
+/// the scraper, which can sum over the `file` label.
+/// One gauge family in the exposition: selected flag, metric name, help
+/// text, and the counter it samples.
+type MetricFamily = (bool, &'static str, &'static str, fn(&Counts) -> u64);
+
+fn write_openmetrics(
+    out: &mut impl Write,
+    rows: &[(Counts, Vec<u8>, RowFlags)],
+    sel: Selection,
+) -> io::Result<()> {
+    let families: [MetricFamily; 8] = [
+        (sel.lines, "wc_lines", "Newline count.", |c| c.lines),
+        (sel.words, "wc_words", "Word count.", |c| c.words),
+        (sel.chars, "wc_chars", "Character count.", |c| c.chars),
+        (sel.bytes, "wc_bytes", "Byte count.", |c| c.bytes),
+        (
+            sel.max_line_length,
+            "wc_max_line_length",
+            "Maximum display width of a line.",
+            |c| c.max_line_length,
+        ),
+        (
+            sel.max_words_per_line,
+            "wc_max_words_per_line",
+            "Most words on one line.",
+            |c| c.max_words_per_line,
+        ),
+        (
+            sel.min_words_per_line,
+            "wc_min_words_per_line",
+            "Fewest words on one line.",
+            |c| c.min_words_per_line.unwrap_or(0),
+        ),
+        (
+            sel.unique_words,
+            "wc_unique_words",
+            "Distinct word count.",
+            |c| c.unique_words,
+        ),
+    ];
+    for (selected, name, help, value) in families {
+        if !selected {
+            continue;
+        }
+        writeln!(out, "# HELP {name} {help}")?;
+        writeln!(out, "# TYPE {name} gauge")?;
+        for (counts, file, _) in rows {
+            writeln!(
+                out,
+                "{name}{{file=\"{}\"}} {}",
+                label_escape(&String::from_utf8_lossy(file)),
+                value(counts)
+            )?;
+        }
+    }
+    // The average is the one non-integer sample, so it sits outside the
+    // `fn(&Counts) -> u64` family table.
+    if sel.avg_word_length {
+        writeln!(
+            out,
+            "# HELP wc_avg_word_length Average word length in characters."
+        )?;
+        writeln!(out, "# TYPE wc_avg_word_length gauge")?;
+        for (counts, file, _) in rows {
+            writeln!(
+                out,
+                "wc_avg_word_length{{file=\"{}\"}} {}",
+                label_escape(&String::from_utf8_lossy(file)),
+                counts.avg_word_length()
+            )?;
+        }
+    }
+    if rows.iter().any(|(_, _, flags)| flags.truncated) {
+        writeln!(out, "# HELP wc_truncated Input was cut short by a cap.")?;
+        writeln!(out, "# TYPE wc_truncated gauge")?;
+        for (_, file, flags) in rows {
+            writeln!(
+                out,
+                "wc_truncated{{file=\"{}\"}} {}",
+                label_escape(&String::from_utf8_lossy(file)),
+                u64::from(flags.truncated)
+            )?;
+        }
+    }
+    if rows.iter().any(|(_, _, flags)| flags.partial) {
+        writeln!(
+            out,
+            "# HELP wc_partial Counts cover only a readable prefix."
+        )?;
+        writeln!(out, "# TYPE wc_partial gauge")?;
+        for (_, file, flags) in rows {
+            writeln!(
+                out,
+                "wc_partial{{file=\"{}\"}} {}",
+                label_escape(&String::from_utf8_lossy(file)),
+                u64::from(flags.partial)
+            )?;
+        }
+    }
+    if rows.iter().any(|(_, _, flags)| flags.missing_newline) {
+        writeln!(
+            out,
+            "# HELP wc_missing_newline Input does not end with a newline."
+        )?;
+        writeln!(out, "# TYPE wc_missing_newline gauge")?;
+        for (_, file, flags) in rows {
+            writeln!(
+                out,
+                "wc_missing_newline{{file=\"{}\"}} {}",
+                label_escape(&String::from_utf8_lossy(file)),
+                u64::from(flags.missing_newline)
+            )?;
+        }
+    }
+    writeln!(out, "# EOF")
+}
+
+/// OpenMetrics label-value escaping: backslash, quote, and newline.
+fn label_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Minimal JSON string escaping: quotes, backslashes, and control bytes.
+fn json_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Count files from a NUL-separated list as its entries arrive, printing
+/// each row immediately. Since the list's extent is unknown, GNU keeps the
+/// minimal column width here instead of sizing columns up front.
+fn run_files0_streaming(
+    list_path: &Path,
+    cli: &Cli,
+    job: CountJob,
+    rusage: &RusageReport,
+) -> ExitCode {
+    let CountJob { sel, .. } = job;
+    let reader: Box<dyn io::BufRead> = if list_path == Path::new("-") {
+        Box::new(io::BufReader::new(io::stdin()))
+    } else {
+        match File::open(list_path) {
+            Ok(file) => Box::new(io::BufReader::new(file)),
+            Err(err) => {
+                eprintln!("wc-rs: {}: {err}", list_path.display());
+                return ExitCode::FAILURE;
+            }
+        }
+    };
+    let mut names = files0::Files0Reader::new(reader);
+    let format = NumberFormat::from_cli(cli);
+    let style = Style::for_stream(cli.color, output_is_terminal(cli));
+    let err_style = Style::for_stream(cli.color, io::stderr().is_terminal());
+    let mut out = match open_output(cli) {
+        Ok(out) => out,
+        Err(err) => return exit_for_output_error(cli, err),
+    };
+    let mut failed = false;
+    let mut total = Counts::default();
+    let mut seen = 0usize;
+    // Exposition output groups samples by metric family, so it cannot be
+    // emitted until the whole list has been counted.
+    let mut metric_rows: Vec<(Counts, Vec<u8>, RowFlags)> = Vec::new();
+    let list_is_stdin = list_path == Path::new("-");
+    while let Some(item) = names.next_name() {
+        let name = match item {
+            Ok(name) => name,
+            Err(err @ files0::Files0ReadError::Parse(_)) => {
+                // GNU skips the bad entry and keeps going.
+                report_files0_error(list_path, &err, err_style);
+                failed = true;
+                continue;
+            }
+            Err(err) => {
+                report_files0_error(list_path, &err, err_style);
+                return ExitCode::FAILURE;
+            }
+        };
+        let input = if name == b"-" {
+            if list_is_stdin {
+                // Stdin is already being consumed by the list itself.
+                eprintln!(
+                    "{}",
+                    err_style.error(
+                        "wc-rs: when reading file names from stdin, no file name of '-' allowed"
+                    )
+                );
+                failed = true;
+                continue;
+            }
+            Input::Stdin
+        } else {
+            Input::File(path_from_bytes(&name))
+        };
+        seen += 1;
+        let strategy = choose_strategy(
+            cli.parallel_mode,
+            &[input.size()],
+            sel,
+            rayon::current_num_threads(),
+        );
+        match count_input(&input, job, strategy) {
+            Ok((counts, flags)) => {
+                warn_missing_newline(cli, &input, flags);
+                add_to_total(&mut total, counts, cli.saturate);
+                failed |= check_assert_limits(cli, &counts, &diagnostic_name(cli, &input), false);
+                if cli.output == OutputFormat::OpenMetrics {
+                    metric_rows.push((counts, input.name_bytes(), flags));
+                } else if cli.total != TotalMode::Only {
+                    let row = if cli.output == OutputFormat::Ndjson {
+                        writeln!(
+                            out,
+                            "{}",
+                            ndjson_row(
+                                Some(&input.display_name()),
+                                &counts,
+                                sel,
+                                flags,
+                                cli.with_metadata
+                                    .then(|| row_metadata(&input, &job))
+                                    .as_ref(),
+                            )
+                        )
+                    } else {
+                        let mut name =
+                            style.file_name(&quote_name(&input.name_bytes(), cli.quoting_style));
+                        if flags.truncated {
+                            name.extend_from_slice(b" (truncated)");
+                        }
+                        write_counts(
+                            &mut out,
+                            &counts,
+                            sel,
+                            &format,
+                            1,
+                            cli.precision,
+                            Some(&name),
+                        )
+                    };
+                    if let Err(err) = row.and_then(|()| out.flush()) {
+                        return exit_for_write_error(err);
+                    }
+                }
+            }
+            Err(err) => {
+                let (prefix, err) = split_partial(err);
+                if let Some((counts, flags)) = prefix {
+                    warn_missing_newline(cli, &input, flags);
+                    add_to_total(&mut total, counts, cli.saturate);
+                    if cli.output == OutputFormat::OpenMetrics {
+                        metric_rows.push((counts, input.name_bytes(), flags));
+                    } else if cli.total != TotalMode::Only {
+                        let row = if cli.output == OutputFormat::Ndjson {
+                            writeln!(
+                                out,
+                                "{}",
+                                ndjson_row(
+                                    Some(&input.display_name()),
+                                    &counts,
+                                    sel,
+                                    flags,
+                                    cli.with_metadata
+                                        .then(|| row_metadata(&input, &job))
+                                        .as_ref(),
+                                )
+                            )
+                        } else {
+                            let mut name = style
+                                .file_name(&quote_name(&input.name_bytes(), cli.quoting_style));
+                            name.extend_from_slice(b" (partial)");
+                            write_counts(
+                                &mut out,
+                                &counts,
+                                sel,
+                                &format,
+                                1,
+                                cli.precision,
+                                Some(&name),
+                            )
+                        };
+                        if let Err(err) = row.and_then(|()| out.flush()) {
+                            return exit_for_write_error(err);
+                        }
+                    }
+                }
+                let message = format!("wc-rs: {}: {}", diagnostic_name(cli, &input), err);
+                eprintln!("{}", err_style.error(&message));
+                failed = true;
+            }
+        }
+    }
+    rusage.add_bytes(total.bytes);
+    failed |= check_assert_limits(cli, &total, total_label(cli), true);
+    let print_total = match cli.total {
+        TotalMode::Auto => seen > 1,
+        TotalMode::Always | TotalMode::Only => true,
+        TotalMode::Never => false,
+    };
+    if cli.output == OutputFormat::OpenMetrics {
+        if let Err(err) = write_openmetrics(&mut out, &metric_rows, sel) {
+            return exit_for_write_error(err);
+        }
+    } else if print_total {
+        let row = if cli.output == OutputFormat::Ndjson {
+            writeln!(
+                out,
+                "{}",
+                ndjson_row(None, &total, sel, RowFlags::default(), None)
+            )
+        } else {
+            write_counts(
+                &mut out,
+                &total,
+                sel,
+                &format,
+                1,
+                cli.precision,
+                Some(&style.total(&total_row_label(cli))),
+            )
+        };
+        if let Err(err) = row {
+            return exit_for_write_error(err);
+        }
+    }
+    if let Err(err) = out.commit() {
+        return exit_for_write_error(err);
+    }
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Diagnose every `--assert-*` limit `counts` exceeds, naming `subject`
+/// (an input, or the totals label) on stderr. Returns whether any limit
+/// was exceeded, which fails the run; counts still print, so the gate's
+/// log shows the numbers that tripped it.
+fn check_assert_limits(cli: &Cli, counts: &Counts, subject: &str, total: bool) -> bool {
+    let limits = if total {
+        [
+            (
+                cli.assert_total_max_lines,
+                counts.lines,
+                "lines",
+                "--assert-total-max-lines",
+            ),
+            (
+                cli.assert_total_max_bytes,
+                counts.bytes,
+                "bytes",
+                "--assert-total-max-bytes",
+            ),
+        ]
+    } else {
+        [
+            (
+                cli.assert_max_lines,
+                counts.lines,
+                "lines",
+                "--assert-max-lines",
+            ),
+            (
+                cli.assert_max_bytes,
+                counts.bytes,
+                "bytes",
+                "--assert-max-bytes",
+            ),
+        ]
+    };
+    let mut exceeded = false;
+    for (limit, value, unit, flag) in limits {
+        match limit {
+            Some(limit) if value > limit => {
+                eprintln!("wc-rs: {subject}: {value} {unit} exceed {flag} {limit}");
+                exceeded = true;
+            }
+            _ => {}
+        }
+    }
+    exceeded
+}
+
+/// The totals row label: an explicit --total-label wins, otherwise the
+/// locale's translation of "total".
+fn total_label(cli: &Cli) -> &str {
+    if cli.total_label == "total" {
+        translate(Message::Total)
+    } else {
+        &cli.total_label
+    }
+}
+
+/// The label a printed totals row carries: the plain label, plus a
+/// marker when `--saturate` had to peg a counter, mirroring the
+/// per-row truncation markers.
+fn total_row_label(cli: &Cli) -> String {
+    let mut label = total_label(cli).to_string();
+    if TOTAL_SATURATED.load(Ordering::Relaxed) {
+        label.push_str(" (saturated)");
+    }
+    label
+}
+
+/// Whether to print the interactive-terminal hint: only for an implicit
+/// stdin read from a TTY, and never when `--quiet`, `--no-tty-hint`, or
+/// POSIX mode asks for bare behavior.
+fn should_hint_tty(cli: &Cli, posix: bool, stdin_is_tty: bool) -> bool {
+    stdin_is_tty
+        && cli.files.is_empty()
+        && cli.files0_from.is_none()
+        && !cli.quiet
+        && !cli.no_tty_hint
+        && !posix
+}
+
+/// A closed pipe downstream is normal termination; anything else is not.
+/// Where the report goes: standard output, or the `--output-file` target.
+///
+/// File output is staged in a temporary file in the destination's
+/// directory — so the final rename never crosses filesystems — and moved
+/// into place by [`Output::commit`], so a reader of the target sees
+/// either the old report or the whole new one. `--append` forgoes the
+/// staging and writes to the file directly.
+enum Output {
+    Stdout(BufWriter<io::StdoutLock<'static>>),
+    Staged {
+        writer: BufWriter<File>,
+        tmp: PathBuf,
+        dest: PathBuf,
+    },
+    Append(BufWriter<File>),
+}
+
+/// Open the report destination the command line asks for.
+fn open_output(cli: &Cli) -> io::Result<Output> {
+    let Some(dest) = &cli.output_file else {
+        return Ok(Output::Stdout(BufWriter::new(io::stdout().lock())));
+    };
+    if cli.append {
+        let file = File::options().append(true).create(true).open(dest)?;
+        return Ok(Output::Append(BufWriter::new(file)));
+    }
+    let tmp = staging_path(dest);
+    let writer = BufWriter::new(File::create(&tmp)?);
+    Ok(Output::Staged {
+        writer,
+        tmp,
+        dest: dest.clone(),
+    })
+}
+
+/// The hidden sibling a staged report is written to first. The pid keeps
+/// concurrent runs targeting the same file from clobbering each other's
+/// staging; last rename wins, as with any racing writers.
+fn staging_path(dest: &Path) -> PathBuf {
+    use std::ffi::OsString;
+    let mut name = OsString::from(".");
+    name.push(dest.file_name().unwrap_or_else(|| "wc-rs".as_ref()));
+    name.push(format!(".tmp.{}", std::process::id()));
+    dest.with_file_name(name)
+}
+
+impl Output {
+    /// Flush the report and, for staged file output, move it into place.
+    fn commit(self) -> io::Result<()> {
+        match self {
+            Output::Stdout(mut writer) => writer.flush(),
+            Output::Append(mut writer) => writer.flush(),
+            Output::Staged {
+                mut writer,
+                tmp,
+                dest,
+            } => {
+                writer.flush()?;
+                drop(writer);
+                std::fs::rename(&tmp, &dest)
+            }
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Stdout(writer) => writer.write(buf),
+            Output::Staged { writer, .. } => writer.write(buf),
+            Output::Append(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Stdout(writer) => writer.flush(),
+            Output::Staged { writer, .. } => writer.flush(),
+            Output::Append(writer) => writer.flush(),
+        }
+    }
+}
+
+/// Whether the report destination is an interactive terminal, which a
+/// `--output-file` target never is — `--color auto` must not write
+/// escape codes into the file just because stdout is a TTY.
+fn output_is_terminal(cli: &Cli) -> bool {
+    cli.output_file.is_none() && io::stdout().is_terminal()
+}
+
+/// Diagnose a failure to open the `--output-file` target.
+fn exit_for_output_error(cli: &Cli, err: io::Error) -> ExitCode {
+    let dest = cli.output_file.as_deref().unwrap_or_else(|| Path::new("-"));
+    eprintln!("wc-rs: {}: {err}", dest.display());
+    ExitCode::FAILURE
+}
+
+fn exit_for_write_error(err: io::Error) -> ExitCode {
+    if err.kind() == io::ErrorKind::BrokenPipe {
+        ExitCode::SUCCESS
+    } else {
+        eprintln!("wc-rs: {}: {err}", translate(Message::WriteError));
+        ExitCode::FAILURE
+    }
+}
+
+/// Expand the command line (operands or `--files0-from`) into inputs.
+/// Invalid list entries are diagnosed and skipped like GNU does; the
+/// returned flag records whether any were seen.
+fn resolve_inputs(cli: &Cli) -> Result<(Vec<Input>, bool), String> {
+    cli.validate()?;
+    if let Some(list_path) = &cli.files0_from {
+        // Only regular-file lists reach this path (see main); a `-` entry
+        // in such a list names stdin, exactly as an operand would.
+        let data = std::fs::read(list_path).map_err(|e| format!("{}: {e}", list_path.display()))?;
+        let mut reader = files0::Files0Reader::new(&data[..]);
+        let mut inputs = Vec::new();
+        let mut failed = false;
+        while let Some(item) = reader.next_name() {
+            match item {
+                Ok(name) if name == b"-" => inputs.push(Input::Stdin),
+                Ok(name) => inputs.push(Input::File(path_from_bytes(&name))),
+                Err(err) => {
+                    let style = Style::for_stream(cli.color, io::stderr().is_terminal());
+                    report_files0_error(list_path, &err, style);
+                    failed = true;
+                }
+            }
+        }
+        return Ok((inputs, failed));
+    }
+    if cli.files.is_empty() {
+        return Ok((vec![Input::Stdin], false));
+    }
+    let inputs = cli
+        .files
+        .iter()
+        .map(|path| {
+            if path == Path::new("-") {
+                return Input::Stdin;
+            }
+            if let Some(fd) = parse_fd_operand(path) {
+                return Input::Fd(fd);
+            }
+            #[cfg(feature = "http")]
+            if let Some(url) = parse_url_operand(path) {
+                return Input::Url(url);
+            }
+            Input::File(path.clone())
+        })
+        .collect();
+    Ok((inputs, false))
+}
+
+/// The descriptor named by an `fd://N` operand, or `None` when the operand
+/// is an ordinary path.
+fn parse_fd_operand(path: &Path) -> Option<i32> {
+    let digits = path.to_str()?.strip_prefix("fd://")?;
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+/// The operand as a URL, when it carries a scheme the `http` feature
+/// serves.
+#[cfg(feature = "http")]
+fn parse_url_operand(path: &Path) -> Option<String> {
+    let s = path.to_str()?;
+    (s.starts_with("http://") || s.starts_with("https://")).then(|| s.to_string())
+}
+
+/// Print a list-reading diagnostic in GNU's `LIST:ENTRY:` form (entry
+/// numbers are 1-based there).
+fn report_files0_error(list_path: &Path, err: &files0::Files0ReadError, style: Style) {
+    let message = match err {
+        files0::Files0ReadError::Parse(files0::Files0Error::EmptyName { index }) => {
+            format!(
+                "wc-rs: {}:{}: invalid zero-length file name",
+                list_path.display(),
+                index + 1
+            )
+        }
+        files0::Files0ReadError::Io(err) => format!("wc-rs: {}: {err}", list_path.display()),
+    };
+    eprintln!("{}", style.error(&message));
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// How many bytes may be counted between checkpoint saves. Large enough
+/// that the rewrite is noise next to the counting, small enough that an
+/// interrupted terabyte-scale run loses at most a few seconds of work.
+const CHECKPOINT_INTERVAL: u64 = 64 * 1024 * 1024;
+
+/// On-disk progress of an interrupted count: how far into the file the
+/// counter got, and the state it needs to continue mid-word and
+/// mid-character.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Checkpoint {
+    offset: u64,
+    state: StreamState,
+}
+
+fn render_checkpoint(ckpt: &Checkpoint) -> String {
+    let c = &ckpt.state.counts;
+    let mut text = format!(
+        "wc-rs-checkpoint 1\n\
+         offset {}\nlines {}\nwords {}\nchars {}\nbytes {}\n\
+         max_line_length {}\nmax_words_per_line {}\nword_chars {}\ncols {}\nin_word {}\n\
+         line_words {}\npending {}\ncarry {}\nlast_was_cr {}\n",
+        ckpt.offset,
+        c.lines,
+        c.words,
+        c.chars,
+        c.bytes,
+        c.max_line_length,
+        c.max_words_per_line,
+        c.word_chars,
+        ckpt.state.cols,
+        u8::from(ckpt.state.in_word),
+        ckpt.state.line_words,
+        hex_encode(&ckpt.state.pending),
+        hex_encode(ckpt.state.grapheme_carry.as_bytes()),
+        u8::from(ckpt.state.last_was_cr),
+    );
+    // `None` means no line has been seen yet; the key is simply absent.
+    if let Some(min) = c.min_words_per_line {
+        text.push_str(&format!("min_words_per_line {min}\n"));
+    }
+    text
+}
+
+fn parse_checkpoint(text: &str) -> Option<Checkpoint> {
+    let mut lines = text.lines();
+    if lines.next() != Some("wc-rs-checkpoint 1") {
+        return None;
+    }
+    let mut ckpt = Checkpoint {
+        offset: 0,
+        state: StreamState::default(),
+    };
+    for line in lines {
+        let (key, value) = line.split_once(' ')?;
+        let state = &mut ckpt.state;
+        match key {
+            "offset" => ckpt.offset = value.parse().ok()?,
+            "lines" => state.counts.lines = value.parse().ok()?,
+            "words" => state.counts.words = value.parse().ok()?,
+            "chars" => state.counts.chars = value.parse().ok()?,
+            "bytes" => state.counts.bytes = value.parse().ok()?,
+            "max_line_length" => state.counts.max_line_length = value.parse().ok()?,
+            "max_words_per_line" => state.counts.max_words_per_line = value.parse().ok()?,
+            "word_chars" => state.counts.word_chars = value.parse().ok()?,
+            "min_words_per_line" => state.counts.min_words_per_line = Some(value.parse().ok()?),
+            "cols" => state.cols = value.parse().ok()?,
+            "line_words" => state.line_words = value.parse().ok()?,
+            "in_word" => state.in_word = value == "1",
+            "pending" => state.pending = hex_decode(value)?,
+            "carry" => state.grapheme_carry = String::from_utf8(hex_decode(value)?).ok()?,
+            "last_was_cr" => state.last_was_cr = value == "1",
+            _ => return None,
+        }
+    }
+    Some(ckpt)
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn hex_decode(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    text.as_bytes()
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+/// Write the checkpoint through a rename so a crash mid-save leaves the
+/// previous checkpoint intact rather than a torn file.
+fn save_checkpoint(path: &Path, ckpt: &Checkpoint) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    std::fs::write(&tmp, render_checkpoint(ckpt))?;
+    std::fs::rename(&tmp, path)
+}
+
+/// A previously saved checkpoint, or `None` for a fresh start.
+fn load_checkpoint(path: &Path) -> io::Result<Option<Checkpoint>> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    parse_checkpoint(&text)
+        .map(Some)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed checkpoint file"))
+}
+
+/// Count one input, honoring `--checkpoint` when it is in effect.
+fn count_one(
+    input: &Input,
+    cli: &Cli,
+    job: CountJob,
+    strategy: Strategy,
+) -> io::Result<(Counts, RowFlags)> {
+    match &cli.checkpoint {
+        Some(path) => count_checkpointed(input, job, path),
+        None => count_input(input, job, strategy),
+    }
+}
+
+/// Stream one regular file with periodic progress saves, resuming from an
+/// existing checkpoint. The checkpoint is removed once the count completes.
+fn count_checkpointed(
+    input: &Input,
+    job: CountJob,
+    ckpt_path: &Path,
+) -> io::Result<(Counts, RowFlags)> {
+    if job.encoding.is_some() {
+        // The locale can imply transcoding even when no flag does.
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "--checkpoint cannot resume transcoded input",
+        ));
+    }
+    let Input::File(path) = input else {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "--checkpoint requires a seekable file",
+        ));
+    };
+    let mut file = File::open(openable_path(path))?;
+    let mut counter =
+        StreamCounter::new(job.sel, job.mode, detect_simd_path()).with_tab_width(job.tab_width);
+    let mut offset = 0u64;
+    if let Some(ckpt) = load_checkpoint(ckpt_path)? {
+        if ckpt.offset > file.metadata()?.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "checkpoint offset is beyond the end of the file",
+            ));
+        }
+        file.seek(SeekFrom::Start(ckpt.offset))?;
+        offset = ckpt.offset;
+        counter = counter.with_state(ckpt.state);
+    }
+    let mut buf = vec![0u8; BUF_SIZE];
+    let mut since_save = 0u64;
+    let mut last_byte = None;
+    let mut reader = RetryReader::new(&mut file, job.retries);
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        counter.update(&buf[..n]);
+        last_byte = Some(buf[n - 1]);
+        offset += n as u64;
+        since_save += n as u64;
+        if since_save >= CHECKPOINT_INTERVAL {
+            save_checkpoint(
+                ckpt_path,
+                &Checkpoint {
+                    offset,
+                    state: counter.state(),
+                },
+            )?;
+            since_save = 0;
+        }
+    }
+    let counts = counter.finish();
+    let flags = RowFlags {
+        truncated: false,
+        missing_newline: last_byte.is_some_and(|b| b != b'\n'),
+        partial: false,
+    };
+    // A finished count leaves nothing to resume.
+    match std::fs::remove_file(ckpt_path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok((counts, flags)),
+    }
+}
+
+/// The first line of a counts manifest, versioned like the checkpoint
+/// format so a future revision can change the layout detectably.
+const MANIFEST_MAGIC: &str = "wc-rs-manifest 1";
+
+/// One counter a manifest can record: its header name and the count it
+/// maps to.
+struct ManifestCounter {
+    name: &'static str,
+    selected: fn(&Selection) -> bool,
+    select: fn(&mut Selection),
+    value: fn(&Counts) -> u64,
+}
+
+/// Every counter a manifest may list, in the order the binary writes
+/// them. The set matches the NDJSON keys; derived floats stay out so a
+/// manifest compares exactly.
+const MANIFEST_COUNTERS: &[ManifestCounter] = &[
+    ManifestCounter {
+        name: "lines",
+        selected: |s| s.lines,
+        select: |s| s.lines = true,
+        value: |c| c.lines,
+    },
+    ManifestCounter {
+        name: "words",
+        selected: |s| s.words,
+        select: |s| s.words = true,
+        value: |c| c.words,
+    },
+    ManifestCounter {
+        name: "chars",
+        selected: |s| s.chars,
+        select: |s| s.chars = true,
+        value: |c| c.chars,
+    },
+    ManifestCounter {
+        name: "bytes",
+        selected: |s| s.bytes,
+        select: |s| s.bytes = true,
+        value: |c| c.bytes,
+    },
+    ManifestCounter {
+        name: "max_line_length",
+        selected: |s| s.max_line_length,
+        select: |s| s.max_line_length = true,
+        value: |c| c.max_line_length,
+    },
+    ManifestCounter {
+        name: "max_words_per_line",
+        selected: |s| s.max_words_per_line,
+        select: |s| s.max_words_per_line = true,
+        value: |c| c.max_words_per_line,
+    },
+    ManifestCounter {
+        name: "min_words_per_line",
+        selected: |s| s.min_words_per_line,
+        select: |s| s.min_words_per_line = true,
+        value: |c| c.min_words_per_line.unwrap_or(0),
+    },
+    ManifestCounter {
+        name: "unique_words",
+        selected: |s| s.unique_words,
+        select: |s| s.unique_words = true,
+        value: |c| c.unique_words,
+    },
+];
+
+/// A parsed counts manifest: which counters its rows record, the decoding
+/// mode they were counted under, and each file's recorded values.
+struct Manifest {
+    counters: Vec<&'static ManifestCounter>,
+    mode: CountMode,
+    rows: Vec<(String, Vec<u64>)>,
+}
+
+fn parse_manifest(text: &str) -> Result<Manifest, String> {
+    let mut lines = text.lines();
+    if lines.next() != Some(MANIFEST_MAGIC) {
+        return Err("not a wc-rs manifest (missing header)".to_string());
+    }
+    let mut counters: Vec<&'static ManifestCounter> = Vec::new();
+    let mut mode = CountMode::Utf8;
+    let mut rows = Vec::new();
+    for line in lines {
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(names) = line.strip_prefix("counters ") {
+            for name in names.split(' ') {
+                let counter = MANIFEST_COUNTERS
+                    .iter()
+                    .find(|counter| counter.name == name)
+                    .ok_or_else(|| format!("unknown counter '{name}'"))?;
+                counters.push(counter);
+            }
+        } else if let Some(value) = line.strip_prefix("mode ") {
+            mode = match value {
+                "utf8" => CountMode::Utf8,
+                "bytes" => CountMode::Bytes,
+                _ => return Err(format!("unknown mode '{value}'")),
+            };
+        } else {
+            // A counts row: one value per declared counter, then the file
+            // name, which may itself contain spaces.
+            if counters.is_empty() {
+                return Err("counts listed before a counters line".to_string());
+            }
+            let mut rest = line;
+            let mut values = Vec::with_capacity(counters.len());
+            for _ in 0..counters.len() {
+                let (field, tail) = rest
+                    .split_once(' ')
+                    .ok_or_else(|| format!("malformed counts row '{line}'"))?;
+                values.push(
+                    field
+                        .parse()
+                        .map_err(|_| format!("invalid count '{field}'"))?,
+                );
+                rest = tail;
+            }
+            rows.push((rest.to_string(), values));
+        }
+    }
+    if counters.is_empty() {
+        return Err("manifest names no counters".to_string());
+    }
+    Ok(Manifest {
+        counters,
+        mode,
+        rows,
+    })
+}
+
+/// The `--write-manifest` mode: count the operands and record the
+/// selected counters in a manifest `--check` can verify later. Rows are
+/// sorted by name and duplicates collapse, so the same operands produce
+/// byte-identical output however the shell ordered them — fit for
+/// committing next to the dataset. Any unreadable file aborts the run
+/// before anything is written; a silently short manifest would defeat
+/// the later check.
+fn run_write_manifest(cli: &Cli, dest: &Path, sel: Selection, mode: CountMode) -> ExitCode {
+    let counters: Vec<&ManifestCounter> = MANIFEST_COUNTERS
+        .iter()
+        .filter(|counter| (counter.selected)(&sel))
+        .collect();
+    let opts = CountOptions::new(sel, mode);
+    let mut paths: Vec<&PathBuf> = cli.files.iter().collect();
+    paths.sort();
+    paths.dedup();
+    let mut text = format!("{MANIFEST_MAGIC}\n");
+    let names: Vec<&str> = counters.iter().map(|counter| counter.name).collect();
+    text.push_str(&format!("counters {}\n", names.join(" ")));
+    let mode_name = match mode {
+        CountMode::Utf8 => "utf8",
+        CountMode::Bytes => "bytes",
+    };
+    text.push_str(&format!("mode {mode_name}\n"));
+    for path in paths {
+        let name = path.display().to_string();
+        if name.contains('\n') {
+            eprintln!("wc-rs: {name:?}: file names with newlines cannot be recorded");
+            return ExitCode::FAILURE;
+        }
+        let counts = match count_path(path, &opts) {
+            Ok(counts) => counts,
+            Err(err) => {
+                eprintln!("wc-rs: {name}: {err}");
+                return ExitCode::FAILURE;
+            }
+        };
+        for counter in &counters {
+            text.push_str(&format!("{} ", (counter.value)(&counts)));
+        }
+        text.push_str(&name);
+        text.push('\n');
+    }
+    if let Err(err) = std::fs::write(dest, text) {
+        eprintln!("wc-rs: {}: {err}", dest.display());
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+/// The `--check` mode: recount every file the manifest lists, under the
+/// manifest's own counters and mode, and report drift sha256sum-style —
+/// one status line per file, exit failure when anything differs.
+fn run_check(manifest_path: &Path) -> ExitCode {
+    let text = match std::fs::read_to_string(manifest_path) {
+        Ok(text) => text,
+        Err(err) => {
+            eprintln!("wc-rs: {}: {err}", manifest_path.display());
+            return ExitCode::FAILURE;
+        }
+    };
+    let manifest = match parse_manifest(&text) {
+        Ok(manifest) => manifest,
+        Err(err) => {
+            eprintln!("wc-rs: {}: {err}", manifest_path.display());
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut sel = Selection {
+        lines: false,
+        words: false,
+        bytes: false,
+        ..Selection::DEFAULT
+    };
+    for counter in &manifest.counters {
+        (counter.select)(&mut sel);
+    }
+    let opts = CountOptions::new(sel, manifest.mode);
+    let mut failed = 0usize;
+    for (name, recorded) in &manifest.rows {
+        match count_path(Path::new(name), &opts) {
+            Ok(counts) => {
+                let drifted: Vec<String> = manifest
+                    .counters
+                    .iter()
+                    .zip(recorded)
+                    .filter(|(counter, &value)| (counter.value)(&counts) != value)
+                    .map(|(counter, &value)| {
+                        format!(
+                            "{} {}, recorded {value}",
+                            counter.name,
+                            (counter.value)(&counts)
+                        )
+                    })
+                    .collect();
+                if drifted.is_empty() {
+                    println!("{name}: OK");
+                } else {
+                    println!("{name}: FAILED ({})", drifted.join("; "));
+                    failed += 1;
+                }
+            }
+            Err(err) => {
+                println!("{name}: FAILED ({err})");
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        eprintln!(
+            "wc-rs: WARNING: {failed} of {} listed files did not match",
+            manifest.rows.len()
+        );
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// The daemon protocol version, sent as the greeting line on every
+/// connection so clients can detect an incompatible server, like the
+/// manifest and checkpoint headers do for their formats.
+#[cfg(unix)]
+const SERVE_MAGIC: &str = "wc-rs-serve 1";
+
+/// The `serve` subcommand: bind a Unix socket and answer count requests
+/// until killed. The protocol is line-oriented — after the greeting, a
+/// client sends `count-path <path>` or `count-bytes <n>` followed by n
+/// raw bytes, and gets back `ok` with name/value pairs for the selected
+/// counters, or `err <reason>`. Each connection runs on its own thread;
+/// the counting itself reuses the cached backend detection, so repeated
+/// requests skip both process startup and CPU feature probing.
+#[cfg(unix)]
+fn run_serve(cli: &Cli, socket: &Path) -> ExitCode {
+    use std::os::unix::net::UnixListener;
+
+    // A socket file left by a previous daemon would make bind fail.
+    if let Err(err) = std::fs::remove_file(socket) {
+        if err.kind() != io::ErrorKind::NotFound {
+            eprintln!("wc-rs: {}: {}", socket.display(), err);
+            return ExitCode::FAILURE;
+        }
+    }
+    let listener = match UnixListener::bind(socket) {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("wc-rs: {}: {}", socket.display(), err);
+            return ExitCode::FAILURE;
+        }
+    };
+    let sel = cli.selection();
+    let mode = detect_count_mode(cli);
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                // A client that hangs up mid-request is its own problem,
+                // not the daemon's; drop the connection's error.
+                std::thread::spawn(move || {
+                    let _ = serve_connection(stream, sel, mode);
+                });
+            }
+            Err(err) => eprintln!("wc-rs: accept: {err}"),
+        }
+    }
+    unreachable!("the listener iterator never ends")
+}
+
+#[cfg(not(unix))]
+fn run_serve(_cli: &Cli, _socket: &Path) -> ExitCode {
+    eprintln!("wc-rs: serve requires Unix domain sockets");
+    ExitCode::FAILURE
+}
+
+/// Answer one client's requests until it hangs up.
+#[cfg(unix)]
+fn serve_connection(
+    stream: std::os::unix::net::UnixStream,
+    sel: Selection,
+    mode: CountMode,
+) -> io::Result<()> {
+    use std::io::BufRead;
+
+    let mut reader = io::BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    writeln!(writer, "{SERVE_MAGIC}")?;
+    writer.flush()?;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        let request = line.trim_end_matches('\n');
+        let reply = match request.split_once(' ') {
+            Some(("count-path", path)) if !path.is_empty() => {
+                match count_path(Path::new(path), &CountOptions::new(sel, mode)) {
+                    Ok(counts) => serve_reply(&counts, sel),
+                    Err(err) => format!("err {err}"),
+                }
+            }
+            Some(("count-bytes", len)) => match len.parse::<usize>() {
+                Ok(len) => {
+                    let mut data = vec![0u8; len];
+                    reader.read_exact(&mut data)?;
+                    match try_count_reader(data.as_slice(), sel, mode, CountLimits::default()) {
+                        Ok(counts) => serve_reply(&counts, sel),
+                        Err(err) => format!("err {err}"),
+                    }
+                }
+                Err(_) => "err count-bytes takes a byte length".to_string(),
+            },
+            _ => "err unknown request".to_string(),
+        };
+        writeln!(writer, "{reply}")?;
+        writer.flush()?;
+    }
+}
+
+/// One `ok` reply line: the selected counters as name/value pairs, named
+/// as in a manifest so clients parse one vocabulary.
+#[cfg(unix)]
+fn serve_reply(counts: &Counts, sel: Selection) -> String {
+    use std::fmt::Write as _;
+
+    let mut reply = String::from("ok");
+    for counter in MANIFEST_COUNTERS {
+        if (counter.selected)(&sel) {
+            let _ = write!(reply, " {} {}", counter.name, (counter.value)(counts));
+        }
+    }
+    reply
+}
+
+/// The `--rpc` loop: one line-delimited JSON-RPC 2.0 request per stdin
+/// line, one reply per stdout line, flushed as each count finishes so a
+/// parent process can pipeline requests. `count-path` counts a named
+/// file; `count-data` counts the UTF-8 bytes of an inline string. Replies
+/// report the selected counters under their manifest names.
+fn run_rpc(sel: Selection, mode: CountMode) -> ExitCode {
+    use std::io::BufRead;
+
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    for line in stdin.lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(err) => {
+                eprintln!("wc-rs: stdin: {err}");
+                return ExitCode::FAILURE;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = rpc_reply(&line, sel, mode);
+        if let Err(err) = writeln!(out, "{reply}").and_then(|()| out.flush()) {
+            return exit_for_write_error(err);
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+/// The reply to one request line, as serialized JSON. Malformed requests
+/// get the spec's error codes rather than killing the session: a parent
+/// with a bad escape in one path should lose that request, not the pipe.
+fn rpc_reply(line: &str, sel: Selection, mode: CountMode) -> String {
+    let Some(request) = parse_json(line) else {
+        return rpc_error("null", -32700, "parse error");
+    };
+    let id = match request.get("id") {
+        Some(Json::Number(n)) if n.fract() == 0.0 => format!("{}", *n as i64),
+        Some(Json::Number(n)) => format!("{n}"),
+        Some(Json::String(s)) => format!("\"{}\"", json_escape(s)),
+        _ => "null".to_string(),
+    };
+    let Some(Json::String(method)) = request.get("method") else {
+        return rpc_error(&id, -32600, "invalid request: no method");
+    };
+    let params = request.get("params");
+    let counted = match method.as_str() {
+        "count-path" => {
+            let Some(Json::String(path)) = params.and_then(|p| p.get("path")) else {
+                return rpc_error(&id, -32602, "count-path takes a \"path\" string");
+            };
+            count_path(Path::new(path), &CountOptions::new(sel, mode))
+                .map_err(|err| err.to_string())
+        }
+        "count-data" => {
+            let Some(Json::String(data)) = params.and_then(|p| p.get("data")) else {
+                return rpc_error(&id, -32602, "count-data takes a \"data\" string");
+            };
+            try_count_reader(data.as_bytes(), sel, mode, CountLimits::default())
+                .map_err(|err| err.to_string())
+        }
+        _ => return rpc_error(&id, -32601, "method not found"),
+    };
+    match counted {
+        Ok(counts) => {
+            let mut fields = Vec::new();
+            for counter in MANIFEST_COUNTERS {
+                if (counter.selected)(&sel) {
+                    fields.push(format!("\"{}\":{}", counter.name, (counter.value)(&counts)));
+                }
+            }
+            format!(
+                "{{\"jsonrpc\":\"2.0\",\"id\":{id},\"result\":{{{}}}}}",
+                fields.join(",")
+            )
+        }
+        Err(message) => rpc_error(&id, -32000, &message),
+    }
+}
+
+/// One JSON-RPC error reply; `id` is already serialized.
+fn rpc_error(id: &str, code: i32, message: &str) -> String {
+    format!(
+        "{{\"jsonrpc\":\"2.0\",\"id\":{id},\"error\":{{\"code\":{code},\"message\":\"{}\"}}}}",
+        json_escape(message)
+    )
+}
+
+/// A parsed JSON value — just enough for the RPC requests. The binary
+/// already writes JSON by hand (NDJSON rows, RPC replies); reading the
+/// small request objects by hand keeps the dependency list where it is.
+#[derive(Debug, Clone, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// An object member by key; the last duplicate wins, as serde does.
+    fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members
+                .iter()
+                .rev()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+}
+
+/// Parse one complete JSON value with nothing but whitespace around it.
+fn parse_json(text: &str) -> Option<Json> {
+    let mut parser = JsonParser {
+        bytes: text.as_bytes(),
+        pos: 0,
+        depth: 0,
+    };
+    parser.skip_ws();
+    let value = parser.value()?;
+    parser.skip_ws();
+    (parser.pos == parser.bytes.len()).then_some(value)
+}
+
+/// How deep arrays and objects may nest. The parser recurses one stack
+/// frame per level, so without a cap a request line of open brackets
+/// overflows the stack and takes the whole session down.
+const MAX_JSON_DEPTH: usize = 128;
+
+/// A recursive-descent cursor over the request bytes. Failures are plain
+/// `None`: the caller only ever turns them into one "parse error" reply.
+struct JsonParser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    depth: usize,
+}
+
+impl JsonParser<'_> {
+    fn skip_ws(&mut self) {
+        while matches!(self.bytes.get(self.pos), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, byte: u8) -> Option<()> {
+        if self.bytes.get(self.pos) == Some(&byte) {
+            self.pos += 1;
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    fn value(&mut self) -> Option<Json> {
+        match self.bytes.get(self.pos)? {
+            b'n' => self.literal("null", Json::Null),
+            b't' => self.literal("true", Json::Bool(true)),
+            b'f' => self.literal("false", Json::Bool(false)),
+            b'"' => self.string().map(Json::String),
+            b'[' => self.nested(Self::array),
+            b'{' => self.nested(Self::object),
+            _ => self.number(),
+        }
+    }
+
+    /// Run one container parse a level deeper, refusing past
+    /// [`MAX_JSON_DEPTH`] so absurd nesting is a parse error, not a
+    /// stack overflow.
+    fn nested(&mut self, parse: fn(&mut Self) -> Option<Json>) -> Option<Json> {
+        if self.depth == MAX_JSON_DEPTH {
+            return None;
+        }
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn literal(&mut self, text: &str, value: Json) -> Option<Json> {
+        self.bytes[self.pos..]
+            .starts_with(text.as_bytes())
+            .then(|| {
+                self.pos += text.len();
+                value
+            })
+    }
+
+    fn number(&mut self) -> Option<Json> {
+        let start = self.pos;
+        if self.bytes.get(self.pos) == Some(&b'-') {
+            self.pos += 1;
+        }
+        while matches!(
+            self.bytes.get(self.pos),
+            Some(b) if b.is_ascii_digit() || matches!(b, b'.' | b'e' | b'E' | b'+' | b'-')
+        ) {
+            self.pos += 1;
+        }
+        let text = std::str::from_utf8(&self.bytes[start..self.pos]).ok()?;
+        text.parse().ok().map(Json::Number)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        self.eat(b'"')?;
+        let mut out = String::new();
+        let mut run = self.pos;
+        loop {
+            match *self.bytes.get(self.pos)? {
+                b'"' => {
+                    out.push_str(std::str::from_utf8(&self.bytes[run..self.pos]).ok()?);
+                    self.pos += 1;
+                    return Some(out);
+                }
+                b'\\' => {
+                    out.push_str(std::str::from_utf8(&self.bytes[run..self.pos]).ok()?);
+                    self.pos += 1;
+                    let escape = *self.bytes.get(self.pos)?;
+                    self.pos += 1;
+                    match escape {
+                        b'"' => out.push('"'),
+                        b'\\' => out.push('\\'),
+                        b'/' => out.push('/'),
+                        b'b' => out.push('\u{8}'),
+                        b'f' => out.push('\u{c}'),
+                        b'n' => out.push('\n'),
+                        b'r' => out.push('\r'),
+                        b't' => out.push('\t'),
+                        b'u' => out.push(self.unicode_escape()?),
+                        _ => return None,
+                    }
+                    run = self.pos;
+                }
+                // Unescaped control characters are invalid JSON.
+                byte if byte < 0x20 => return None,
+                _ => self.pos += 1,
+            }
+        }
+    }
+
+    /// The code point after `\u`, pairing surrogates like JSON requires.
+    fn unicode_escape(&mut self) -> Option<char> {
+        let high = self.hex4()?;
+        if !(0xd800..=0xdbff).contains(&high) {
+            return char::from_u32(high);
+        }
+        (self.eat(b'\\').is_some() && self.eat(b'u').is_some())
+            .then(|| {
+                let low = self.hex4()?;
+                (0xdc00..=0xdfff)
+                    .contains(&low)
+                    .then(|| char::from_u32(0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)))?
+            })
+            .flatten()
+    }
+
+    fn hex4(&mut self) -> Option<u32> {
+        let digits = self.bytes.get(self.pos..self.pos + 4)?;
+        if !digits.iter().all(u8::is_ascii_hexdigit) {
+            return None;
+        }
+        self.pos += 4;
+        u32::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()
+    }
+
+    fn array(&mut self) -> Option<Json> {
+        self.eat(b'[')?;
+        let mut items = Vec::new();
+        self.skip_ws();
+        if self.eat(b']').is_some() {
+            return Some(Json::Array(items));
+        }
+        loop {
+            self.skip_ws();
+            items.push(self.value()?);
+            self.skip_ws();
+            if self.eat(b']').is_some() {
+                return Some(Json::Array(items));
+            }
+            self.eat(b',')?;
+        }
+    }
+
+    fn object(&mut self) -> Option<Json> {
+        self.eat(b'{')?;
+        let mut members = Vec::new();
+        self.skip_ws();
+        if self.eat(b'}').is_some() {
+            return Some(Json::Object(members));
+        }
+        loop {
+            self.skip_ws();
+            let key = self.string()?;
+            self.skip_ws();
+            self.eat(b':')?;
+            self.skip_ws();
+            members.push((key, self.value()?));
+            self.skip_ws();
+            if self.eat(b'}').is_some() {
+                return Some(Json::Object(members));
+            }
+            self.eat(b',')?;
+        }
+    }
+}
+
+/// Exercise every backend available on this CPU against the scalar
+/// reference, over buffer shapes that cover the SIMD main loops and their
+/// remainders. The comparison is the one `--verify` applies to real input.
+fn run_self_test() -> ExitCode {
+    let sel = Selection {
+        lines: true,
+        words: true,
+        chars: true,
+        bytes: true,
+        max_line_length: true,
+        max_words_per_line: true,
+        min_words_per_line: true,
+        unique_words: true,
+        avg_word_length: true,
+        fold_case: false,
+        count_cr_lines: false,
+    };
+    let mut data = Vec::new();
+    for i in 0..4096u32 {
+        data.push((i % 251) as u8);
+    }
+    data.extend_from_slice(
+        "line \u{f6}ne\n\u{4f60}\u{597d} w\u{f6}rld\nno final newline".as_bytes(),
+    );
+    let mut failed = false;
+    for backend in CountingBackend::available() {
+        let mut mismatched = false;
+        for mode in [CountMode::Utf8, CountMode::Bytes] {
+            for end in [0, 1, 15, 16, 17, 31, 32, 33, 100, data.len()] {
+                if let Err(mismatch) =
+                    verify_slice(&data[..end], sel, mode, backend, DEFAULT_TAB_WIDTH)
+                {
+                    eprintln!("wc-rs: self-test: {mismatch} ({mode:?} mode, {end} bytes)");
+                    mismatched = true;
+                }
+            }
+        }
+        println!("{backend:?}: {}", if mismatched { "FAILED" } else { "ok" });
+        failed |= mismatched;
+    }
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Count one input, picking the cheapest I/O path available.
+fn count_input(input: &Input, job: CountJob, strategy: Strategy) -> io::Result<(Counts, RowFlags)> {
+    let CountJob {
+        sel,
+        mode,
+        encoding,
+        range,
+        max_bytes,
+        max_lines,
+        verify,
+        tab_width,
+        partial,
+        retries,
+        decompress,
+        madvise,
+        debug,
+    } = job;
+    let caps = max_bytes.is_some() || max_lines.is_some();
+    if let Some(pipeline) = encoding {
+        let reader: Box<dyn Read> = match input {
+            Input::Stdin => Box::new(skip_into_range(stdin_reader(retries, decompress)?, range)?),
+            #[cfg(feature = "http")]
+            Input::Url(url) => Box::new(skip_into_range(
+                RetryReader::new(url_reader(url)?, retries),
+                range,
+            )?),
+            Input::File(_) | Input::Fd(_) => {
+                let file = input.open_file()?;
+                let meta = file.metadata()?;
+                // Virtual files (/proc) report zero size yet have content,
+                // so the stat shortcut only trusts a positive size.
+                if meta.is_file() && meta.len() > 0 && sel.bytes_only() && max_lines.is_none() {
+                    let bytes = range_overlap(meta.len(), range);
+                    let capped = max_bytes.map_or(bytes, |cap| bytes.min(cap));
+                    let start = range.map_or(0, |r| r.start).min(meta.len());
+                    return Ok((
+                        Counts {
+                            bytes: capped,
+                            ..Counts::default()
+                        },
+                        RowFlags {
+                            truncated: capped < bytes,
+                            missing_newline: file_ends_without_newline(&file, start + capped),
+                            partial: false,
+                        },
+                    ));
+                }
+                if meta.is_file() {
+                    Box::new(RetryReader::new(seek_into_range(file, range)?, retries))
+                } else {
+                    Box::new(skip_into_range(RetryReader::new(file, retries), range)?)
+                }
+            }
+        };
+        let mut reader = CappedReader::new(reader, max_bytes, max_lines);
+        let (counts, missing) = match pipeline.selector {
+            EncodingSelector::Fixed(encoding) => count_transcoded(
+                &mut reader,
+                sel,
+                encoding,
+                pipeline.normalize,
+                verify,
+                tab_width,
+                partial,
+            )?,
+            EncodingSelector::Auto { debug } => {
+                let mut head = Vec::with_capacity(BUF_SIZE);
+                (&mut reader).take(BUF_SIZE as u64).read_to_end(&mut head)?;
+                let encoding = sniff_encoding(&head, head.len() < BUF_SIZE);
+                if debug {
+                    eprintln!(
+                        "wc-rs: {}: detected encoding {}",
+                        input.display_name(),
+                        encoding.name()
+                    );
+                }
+                count_transcoded(
+                    io::Cursor::new(head).chain(&mut reader),
+                    sel,
+                    encoding,
+                    pipeline.normalize,
+                    verify,
+                    tab_width,
+                    partial,
+                )?
+            }
+        };
+        return Ok((
+            counts,
+            RowFlags {
+                truncated: reader.truncated,
+                missing_newline: missing,
+                partial: false,
+            },
+        ));
+    }
+    let backend = detect_simd_path();
+    match input {
+        Input::Stdin => {
+            // A bytes-only count of a plain pipe never needs the data in
+            // userspace; splice drains it straight into /dev/null.
+            #[cfg(target_os = "linux")]
+            if sel.bytes_only()
+                && range.is_none()
+                && !caps
+                && !verify
+                && !partial
+                && retries == 0
+                && decompress == Decompress::Never
+            {
+                if let Some(bytes) = splice_count_bytes(libc::STDIN_FILENO)? {
+                    return Ok((
+                        Counts {
+                            bytes,
+                            ..Counts::default()
+                        },
+                        RowFlags::default(),
+                    ));
+                }
+            }
+            let mut reader = CappedReader::new(
+                skip_into_range(stdin_reader(retries, decompress)?, range)?,
+                max_bytes,
+                max_lines,
+            );
+            let (counts, missing) =
+                count_reader(&mut reader, sel, mode, verify, tab_width, partial)?;
+            Ok((
+                counts,
+                RowFlags {
+                    truncated: reader.truncated,
+                    missing_newline: missing,
+                    partial: false,
+                },
+            ))
+        }
+        #[cfg(feature = "http")]
+        Input::Url(url) => {
+            let mut reader = CappedReader::new(
+                skip_into_range(RetryReader::new(url_reader(url)?, retries), range)?,
+                max_bytes,
+                max_lines,
+            );
+            let (counts, missing) =
+                count_reader(&mut reader, sel, mode, verify, tab_width, partial)?;
+            Ok((
+                counts,
+                RowFlags {
+                    truncated: reader.truncated,
+                    missing_newline: missing,
+                    partial: false,
+                },
+            ))
+        }
+        Input::File(_) | Input::Fd(_) => {
+            let file = input.open_file()?;
+            let meta = file.metadata()?;
+            if meta.is_file() {
+                // Virtual files (/proc) report zero size yet have content,
+                // so the stat shortcut only trusts a positive size.
+                if meta.len() > 0 && sel.bytes_only() && max_lines.is_none() {
+                    let bytes = range_overlap(meta.len(), range);
+                    let capped = max_bytes.map_or(bytes, |cap| bytes.min(cap));
+                    let start = range.map_or(0, |r| r.start).min(meta.len());
+                    return Ok((
+                        Counts {
+                            bytes: capped,
+                            ..Counts::default()
+                        },
+                        RowFlags {
+                            truncated: capped < bytes,
+                            missing_newline: file_ends_without_newline(&file, start + capped),
+                            partial: false,
+                        },
+                    ));
+                }
+                let map = (meta.len() > 0)
+                    .then(|| try_map_file(&file, &meta, input, debug))
+                    .flatten();
+                if let Some(map) = map {
+                    if madvise {
+                        advise_mapped_input(&map);
+                    }
+                    let ranged = range_slice(&map, range);
+                    let (data, truncated) = cap_slice(ranged, max_bytes, max_lines);
+                    let counts = if verify {
+                        verify_slice(data, sel, mode, backend, tab_width)
+                            .map_err(|m| verify_io_error(&m))?
+                    } else {
+                        match strategy {
+                            Strategy::Chunks => count_slice_chunked(
+                                data,
+                                sel,
+                                mode,
+                                backend,
+                                rayon::current_num_threads(),
+                            ),
+                            Strategy::Files => {
+                                count_slice_with_tab_width(data, sel, mode, backend, tab_width)
+                            }
+                        }
+                    };
+                    return Ok((
+                        counts,
+                        RowFlags {
+                            truncated,
+                            missing_newline: data.last().is_some_and(|&b| b != b'\n'),
+                            partial: false,
+                        },
+                    ));
+                }
+            }
+            // Named pipes opened by path get the same splice fast path as
+            // stdin.
+            #[cfg(target_os = "linux")]
+            if sel.bytes_only() && range.is_none() && !caps && !verify && !partial && retries == 0 {
+                use std::os::fd::AsRawFd;
+                if let Some(bytes) = splice_count_bytes(file.as_raw_fd())? {
+                    return Ok((
+                        Counts {
+                            bytes,
+                            ..Counts::default()
+                        },
+                        RowFlags::default(),
+                    ));
+                }
+            }
+            if caps {
+                let mut reader =
+                    CappedReader::new(RetryReader::new(file, retries), max_bytes, max_lines);
+                let (counts, missing) =
+                    count_reader(&mut reader, sel, mode, verify, tab_width, partial)?;
+                Ok((
+                    counts,
+                    RowFlags {
+                        truncated: reader.truncated,
+                        missing_newline: missing,
+                        partial: false,
+                    },
+                ))
+            } else {
+                let (counts, missing) = count_reader(
+                    RetryReader::new(file, retries),
+                    sel,
+                    mode,
+                    verify,
+                    tab_width,
+                    partial,
+                )?;
+                Ok((
+                    counts,
+                    RowFlags {
+                        truncated: false,
+                        missing_newline: missing,
+                        partial: false,
+                    },
+                ))
+            }
+        }
+    }
+}
+
+/// Map a regular file for in-place counting, or decide against it.
+/// Mapping failures (network filesystems, exotic platforms) and files
+/// whose size changes while being mapped (actively written logs, where a
+/// later truncation could fault the map) fall back to buffered reads;
+/// `--debug` reports which way each file went.
+fn try_map_file(
+    file: &File,
+    meta: &std::fs::Metadata,
+    input: &Input,
+    debug: bool,
+) -> Option<memmap2::Mmap> {
+    // SAFETY: the map is read-only and dropped before return; concurrent
+    // truncation is the usual mmap caveat.
+    let map = match unsafe { memmap2::Mmap::map(file) } {
+        Ok(map) => map,
+        Err(err) => {
+            if debug {
+                eprintln!(
+                    "wc-rs: {}: mmap failed ({err}); counting via buffered reads",
+                    input.display_name()
+                );
+            }
+            return None;
+        }
+    };
+    if file.metadata().is_ok_and(|now| now.len() != meta.len()) {
+        if debug {
+            eprintln!(
+                "wc-rs: {}: size changed under the map; counting via buffered reads",
+                input.display_name()
+            );
+        }
+        return None;
+    }
+    if debug {
+        eprintln!("wc-rs: {}: counting via mmap", input.display_name());
+    }
+    Some(map)
+}
+
+/// Count a pipe's bytes with `splice` into `/dev/null`, so a bytes-only
+/// pipeline never copies the data into userspace — the same trick GNU wc
+/// uses. Returns `Ok(None)` when the descriptor is not a pipe or the
+/// kernel refuses to splice it, so the caller can fall back to plain
+/// reads.
+#[cfg(target_os = "linux")]
+fn splice_count_bytes(fd: std::os::fd::RawFd) -> io::Result<Option<u64>> {
+    use std::os::fd::AsRawFd;
+
+    // splice needs a pipe on at least one side, and /dev/null is not one.
+    let mut stat = std::mem::MaybeUninit::<libc::stat>::zeroed();
+    // SAFETY: fstat only writes the struct passed to it.
+    if unsafe { libc::fstat(fd, stat.as_mut_ptr()) } != 0 {
+        return Ok(None);
+    }
+    // SAFETY: a successful fstat fills in the whole struct.
+    let stat = unsafe { stat.assume_init() };
+    if stat.st_mode & libc::S_IFMT != libc::S_IFIFO {
+        return Ok(None);
+    }
+    let null = File::options().write(true).open("/dev/null")?;
+    let mut total = 0u64;
+    loop {
+        // SAFETY: both descriptors are open for the whole call; the null
+        // offset pointers make the kernel use each file's own position.
+        let n = unsafe {
+            libc::splice(
+                fd,
+                std::ptr::null_mut(),
+                null.as_raw_fd(),
+                std::ptr::null_mut(),
+                1 << 20,
+                0,
+            )
+        };
+        match n {
+            0 => return Ok(Some(total)),
+            n if n > 0 => total += n as u64,
+            _ => {
+                let err = io::Error::last_os_error();
+                match err.raw_os_error() {
+                    Some(libc::EINTR) => continue,
+                    // The kernel cannot splice this pair; fall back to
+                    // reads while no byte has been consumed yet.
+                    Some(libc::EINVAL) if total == 0 => return Ok(None),
+                    _ => return Err(err),
+                }
+            }
+        }
+    }
+}
+
+/// Count a sequential reader with the streaming scanner.
+/// The number of bytes of an input `len` bytes long that fall in the range.
+fn range_overlap(len: u64, range: Option<ByteRange>) -> u64 {
+    match range {
+        None => len,
+        Some(range) => {
+            let start = range.start.min(len);
+            range
+                .end
+                .map_or(len, |end| end.min(len))
+                .saturating_sub(start)
+        }
+    }
+}
+
+/// The part of an in-memory input that falls in the range.
+fn range_slice(data: &[u8], range: Option<ByteRange>) -> &[u8] {
+    match range {
+        None => data,
+        Some(range) => {
+            let start = (range.start as usize).min(data.len());
+            let end = range
+                .end
+                .map_or(data.len(), |end| (end as usize).min(data.len()))
+                .max(start);
+            &data[start..end]
+        }
+    }
+}
+
+/// Restrict a seekable input to the range.
+fn seek_into_range(mut file: File, range: Option<ByteRange>) -> io::Result<io::Take<File>> {
+    use std::io::Seek;
+    let Some(range) = range else {
+        return Ok(file.take(u64::MAX));
+    };
+    file.seek(io::SeekFrom::Start(range.start))?;
+    Ok(file.take(range.end.map_or(u64::MAX, |end| end - range.start)))
+}
+
+/// Restrict an unseekable input to the range by reading and discarding the
+/// bytes before it.
+fn skip_into_range<R: Read>(mut reader: R, range: Option<ByteRange>) -> io::Result<io::Take<R>> {
+    let Some(range) = range else {
+        return Ok(reader.take(u64::MAX));
+    };
+    let mut to_skip = range.start;
+    let mut buf = vec![
+        0u8;
+        BUF_SIZE
+            .min(range.start.min(usize::MAX as u64) as usize)
+            .max(1)
+    ];
+    while to_skip > 0 {
+        let want = buf.len().min(to_skip.min(usize::MAX as u64) as usize);
+        let n = reader.read(&mut buf[..want])?;
+        if n == 0 {
+            break;
+        }
+        to_skip -= n as u64;
+    }
+    Ok(reader.take(range.end.map_or(u64::MAX, |end| end - range.start)))
+}
+
+/// The prefix of a slice allowed by the byte/line caps, and whether the
+/// caps cut anything off.
+fn cap_slice(data: &[u8], max_bytes: Option<u64>, max_lines: Option<u64>) -> (&[u8], bool) {
+    let mut cut = data.len();
+    if let Some(max_bytes) = max_bytes {
+        cut = cut.min(max_bytes.min(usize::MAX as u64) as usize);
+    }
+    if let Some(max_lines) = max_lines {
+        let line_cut = if max_lines == 0 {
+            0
+        } else {
+            memchr::memchr_iter(b'\n', data)
+                .nth(max_lines as usize - 1)
+                .map_or(data.len(), |pos| pos + 1)
+        };
+        cut = cut.min(line_cut);
+    }
+    (&data[..cut], cut < data.len())
+}
+
+/// Retries interrupted and transient reads, so a long count survives a
+/// signal-heavy environment or a flaky network mount. Interrupted reads
+/// (EINTR) are always retried, as nothing was lost; timeouts and
+/// would-block errors are retried up to the `--retries` budget per read
+/// before the error is surfaced.
+struct RetryReader<R> {
+    inner: R,
+    retries: u32,
+}
+
+impl<R> RetryReader<R> {
+    fn new(inner: R, retries: u32) -> Self {
+        RetryReader { inner, retries }
+    }
+}
+
+fn transient(kind: io::ErrorKind) -> bool {
+    matches!(kind, io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock)
+}
+
+impl<R: Read> Read for RetryReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut attempts = 0u32;
+        loop {
+            match self.inner.read(buf) {
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) if transient(err.kind()) && attempts < self.retries => {
+                    attempts += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Stops a stream at the `--max-bytes`/`--max-lines` caps, remembering
+/// whether any input was left behind. When a cap lands exactly on the end
+/// of a buffer the next call probes one byte to tell "cap reached" from
+/// "input exhausted".
+struct CappedReader<R> {
+    inner: R,
+    bytes_left: u64,
+    lines_left: u64,
+    truncated: bool,
+    done: bool,
+}
+
+impl<R: Read> CappedReader<R> {
+    fn new(inner: R, max_bytes: Option<u64>, max_lines: Option<u64>) -> Self {
+        CappedReader {
+            inner,
+            bytes_left: max_bytes.unwrap_or(u64::MAX),
+            lines_left: max_lines.unwrap_or(u64::MAX),
+            truncated: false,
+            done: false,
+        }
+    }
+}
+
+impl<R: Read> Read for CappedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.done {
+            return Ok(0);
+        }
+        if self.bytes_left == 0 || self.lines_left == 0 {
+            let mut probe = [0u8; 1];
+            self.truncated = self.inner.read(&mut probe)? > 0;
+            self.done = true;
+            return Ok(0);
+        }
+        let want = buf
+            .len()
+            .min(self.bytes_left.min(usize::MAX as u64) as usize);
+        let n = self.inner.read(&mut buf[..want])?;
+        self.bytes_left -= n as u64;
+        let mut consumed = n;
+        for pos in memchr::memchr_iter(b'\n', &buf[..n]) {
+            self.lines_left -= 1;
+            if self.lines_left == 0 {
+                consumed = pos + 1;
+                break;
+            }
+        }
+        if consumed < n {
+            self.truncated = true;
+            self.done = true;
+        }
+        Ok(consumed)
+    }
+}
+
+/// Look up the `--encoding` label, if one was given. `auto` defers the
+/// choice to per-input sniffing.
+fn resolve_encoding(cli: &Cli) -> Result<Option<EncodingSelector>, String> {
+    match cli.encoding.as_deref() {
+        None => Ok(None),
+        Some("auto") => Ok(Some(EncodingSelector::Auto { debug: cli.debug })),
+        Some(label) => match encoding_rs::Encoding::for_label_no_replacement(label.as_bytes()) {
+            Some(encoding) => Ok(Some(EncodingSelector::Fixed(encoding))),
+            None => Err(format!("unknown encoding '{label}'")),
+        },
+    }
+}
+
+/// Pick an encoding from an input's first block. BOMs are authoritative;
+/// otherwise valid UTF-8 wins, NUL bytes suggest UTF-16 (their position
+/// picks the byte order), then common East Asian encodings are tried in
+/// order of strictness, with windows-1252 as the never-failing fallback.
+/// `complete` says the block is the whole input, so a trailing truncated
+/// sequence counts as an error rather than a continuation.
+fn sniff_encoding(block: &[u8], complete: bool) -> &'static encoding_rs::Encoding {
+    if block.starts_with(b"\xef\xbb\xbf") {
+        return encoding_rs::UTF_8;
+    }
+    if block.starts_with(b"\xff\xfe") {
+        return encoding_rs::UTF_16LE;
+    }
+    if block.starts_with(b"\xfe\xff") {
+        return encoding_rs::UTF_16BE;
+    }
+    // NUL bytes never appear in byte-oriented text, so check for BOM-less
+    // UTF-16 before UTF-8 validity (which NULs would pass).
+    let zeros = block.iter().filter(|&&b| b == 0).count();
+    if zeros > block.len() / 4 {
+        let odd_zeros = block.iter().skip(1).step_by(2).filter(|&&b| b == 0).count();
+        return if odd_zeros * 2 > zeros {
+            encoding_rs::UTF_16LE
+        } else {
+            encoding_rs::UTF_16BE
+        };
+    }
+    match std::str::from_utf8(block) {
+        Ok(_) => return encoding_rs::UTF_8,
+        // Only a truncated sequence at the very end: still UTF-8.
+        Err(err) if !complete && err.error_len().is_none() => return encoding_rs::UTF_8,
+        Err(_) => {}
+    }
+    for encoding in [
+        encoding_rs::SHIFT_JIS,
+        encoding_rs::EUC_JP,
+        encoding_rs::GB18030,
+    ] {
+        if decodes_cleanly(encoding, block, complete) {
+            return encoding;
+        }
+    }
+    encoding_rs::WINDOWS_1252
+}
+
+/// Whether `block` decodes without a single malformed sequence.
+fn decodes_cleanly(encoding: &'static encoding_rs::Encoding, block: &[u8], complete: bool) -> bool {
+    let mut decoder = encoding.new_decoder_without_bom_handling();
+    let mut out = [0u8; 4096];
+    let mut input = block;
+    loop {
+        let (result, read, _) =
+            decoder.decode_to_utf8_without_replacement(input, &mut out, complete);
+        input = &input[read..];
+        match result {
+            encoding_rs::DecoderResult::Malformed(..) => return false,
+            encoding_rs::DecoderResult::InputEmpty => return true,
+            encoding_rs::DecoderResult::OutputFull => {}
+        }
+    }
+}
+
+/// Count a reader's contents after transcoding from a legacy encoding.
+/// Lines, words, chars, and line widths are measured on the decoded text;
+/// the byte count still reports the raw input, as wc does under such
+/// locales. Malformed sequences are skipped, mirroring how the UTF-8 path
+/// treats undecodable bytes.
+fn count_transcoded(
+    mut reader: impl Read,
+    sel: Selection,
+    encoding: &'static encoding_rs::Encoding,
+    normalize: Normalization,
+    verify: bool,
+    tab_width: u64,
+    partial: bool,
+) -> io::Result<(Counts, bool)> {
+    let mut counter = VerifiedCounter::new(sel, CountMode::Utf8, verify, tab_width);
+    let mut decoder = encoding.new_decoder();
+    let mut raw = vec![0u8; BUF_SIZE];
+    let mut decoded = vec![0u8; BUF_SIZE];
+    let mut pending = String::new();
+    let mut raw_bytes: u64 = 0;
+    loop {
+        let n = match reader.read(&mut raw) {
+            Ok(n) => n,
+            Err(err) if partial => {
+                let missing = counter.ends_without_newline();
+                let mut counts = counter.finish()?;
+                counts.bytes = raw_bytes;
+                return Err(io::Error::new(
+                    err.kind(),
+                    PartialCounts {
+                        counts,
+                        flags: RowFlags {
+                            truncated: false,
+                            missing_newline: missing,
+                            partial: true,
+                        },
+                        error: err,
+                    },
+                ));
+            }
+            Err(err) => return Err(err),
+        };
+        raw_bytes += n as u64;
+        let mut input = &raw[..n];
+        loop {
+            let (result, read, written) =
+                decoder.decode_to_utf8_without_replacement(input, &mut decoded, n == 0);
+            if normalize == Normalization::None {
+                counter.update(&decoded[..written]);
+            } else {
+                // The decoder only emits complete characters, so this slice
+                // is valid UTF-8 by construction.
+                pending.push_str(std::str::from_utf8(&decoded[..written]).unwrap());
+                drain_normalized(&mut pending, &mut counter, normalize, false);
+            }
+            input = &input[read..];
+            match result {
+                encoding_rs::DecoderResult::InputEmpty => break,
+                // Refill the output buffer, or step past the bad sequence.
+                encoding_rs::DecoderResult::OutputFull
+                | encoding_rs::DecoderResult::Malformed(..) => {}
+            }
+        }
+        if n == 0 {
+            if normalize != Normalization::None {
+                drain_normalized(&mut pending, &mut counter, normalize, true);
+            }
+            let missing = counter.ends_without_newline();
+            let mut counts = counter.finish()?;
+            counts.bytes = raw_bytes;
+            return Ok((counts, missing));
+        }
+    }
+}
+
+/// Normalize and count the settled prefix of `pending`. Everything from the
+/// last starter character (combining class 0) onward stays buffered unless
+/// `last` is set, since bytes still to come may compose with it; this keeps
+/// normalization correct across read-buffer boundaries.
+fn drain_normalized(
+    pending: &mut String,
+    counter: &mut VerifiedCounter,
+    normalize: Normalization,
+    last: bool,
+) {
+    use unicode_normalization::char::canonical_combining_class;
+    use unicode_normalization::UnicodeNormalization;
+
+    let split = if last {
+        pending.len()
+    } else {
+        match pending
+            .char_indices()
+            .rev()
+            .find(|(_, c)| canonical_combining_class(*c) == 0)
+        {
+            Some((index, _)) => index,
+            None => return,
+        }
+    };
+    if split == 0 {
+        return;
+    }
+    let ready = &pending[..split];
+    let text: String = match normalize {
+        Normalization::Nfc => ready.chars().nfc().collect(),
+        Normalization::Nfd => ready.chars().nfd().collect(),
+        Normalization::None => unreachable!("drain_normalized is only called when normalizing"),
+    };
+    counter.update(text.as_bytes());
+    pending.drain(..split);
+}
+
+fn count_reader(
+    mut reader: impl Read,
+    sel: Selection,
+    mode: CountMode,
+    verify: bool,
+    tab_width: u64,
+    partial: bool,
+) -> io::Result<(Counts, bool)> {
+    let mut counter = VerifiedCounter::new(sel, mode, verify, tab_width);
+    let mut buf = vec![0u8; BUF_SIZE];
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(n) => n,
+            Err(err) if partial => {
+                let missing = counter.ends_without_newline();
+                let counts = counter.finish()?;
+                return Err(io::Error::new(
+                    err.kind(),
+                    PartialCounts {
+                        counts,
+                        flags: RowFlags {
+                            truncated: false,
+                            missing_newline: missing,
+                            partial: true,
+                        },
+                        error: err,
+                    },
+                ));
+            }
+            Err(err) => return Err(err),
+        };
+        if n == 0 {
+            let missing = counter.ends_without_newline();
+            return counter.finish().map(|counts| (counts, missing));
+        }
+        counter.update(&buf[..n]);
+    }
+}
+
+/// Counts accumulated before a mid-file read error, carried inside the
+/// `io::Error` so the counting paths keep their signatures and the row
+/// emitters can choose (via `--partial`) to print the readable prefix.
+#[derive(Debug)]
+struct PartialCounts {
+    counts: Counts,
+    flags: RowFlags,
+    error: io::Error,
+}
+
+impl std::fmt::Display for PartialCounts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl std::error::Error for PartialCounts {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Unpack a counting failure: with `--partial` a mid-file error yields the
+/// prefix counts for the row plus the error to report; otherwise just the
+/// error.
+fn split_partial(err: io::Error) -> (Option<(Counts, RowFlags)>, io::Error) {
+    match err.downcast::<PartialCounts>() {
+        Ok(partial) => (Some((partial.counts, partial.flags)), partial.error),
+        Err(err) => (None, err),
+    }
+}
+
+/// Set when `--saturate` pegged a total, so the output paths can mark
+/// the totals row.
+static TOTAL_SATURATED: AtomicBool = AtomicBool::new(false);
+
+/// Fold one input's counters into a running total, diagnosing overflow.
+///
+/// Wrapping a 64-bit counter takes an absurd amount of input, but 32-bit
+/// builds fed synthetic streams can get there; when a total no longer
+/// fits, peg it under `--saturate`, and otherwise say so once instead of
+/// quietly handing out a wrapped number.
+fn add_to_total(total: &mut Counts, counts: Counts, saturate: bool) {
+    match total.checked_add(counts) {
+        Some(sum) => *total = sum,
+        None if saturate => {
+            TOTAL_SATURATED.store(true, Ordering::Relaxed);
+            *total = total.saturating_add(counts);
+        }
+        None => {
+            static WARNED: AtomicBool = AtomicBool::new(false);
+            if !WARNED.swap(true, Ordering::Relaxed) {
+                eprintln!("wc-rs: total overflowed a 64-bit counter; totals have wrapped");
+            }
+            *total = total.wrapping_add(counts);
+        }
+    }
+}
+
+/// Print the `--warn-missing-newline` diagnostic for a finished row.
+fn warn_missing_newline(cli: &Cli, input: &Input, flags: RowFlags) {
+    if cli.warn_missing_newline && flags.missing_newline {
+        eprintln!(
+            "wc-rs: {}: {}",
+            diagnostic_name(cli, input),
+            translate(Message::NoNewlineAtEndOfFile)
+        );
+    }
+}
+
+/// End-of-run resource report, behind `--rusage`.
+///
+/// Printed to stderr on drop so every exit path — the plain table, ndjson
+/// streaming, the report modes, and the files0 loop — is covered without
+/// wiring a print call into each return.
+struct RusageReport {
+    enabled: bool,
+    start: Instant,
+    bytes: AtomicU64,
+}
+
+impl RusageReport {
+    fn new(enabled: bool) -> Self {
+        RusageReport {
+            enabled,
+            start: Instant::now(),
+            bytes: AtomicU64::new(0),
+        }
+    }
+
+    fn add_bytes(&self, n: u64) {
+        if self.enabled {
+            self.bytes.fetch_add(n, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Drop for RusageReport {
+    fn drop(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        let wall = self.start.elapsed().as_secs_f64();
+        let bytes = self.bytes.load(Ordering::Relaxed);
+        #[cfg(unix)]
+        if let Some((user, sys, peak_kib)) = cpu_and_peak_rss() {
+            eprintln!(
+                "wc-rs: wall {wall:.3}s, user {user:.3}s, sys {sys:.3}s, \
+                 peak rss {peak_kib} KiB, {bytes} bytes processed"
+            );
+            return;
+        }
+        eprintln!("wc-rs: wall {wall:.3}s, {bytes} bytes processed");
+    }
+}
+
+/// User and system CPU time in seconds plus peak RSS in KiB, from
+/// `getrusage`.
+#[cfg(unix)]
+fn cpu_and_peak_rss() -> Option<(f64, f64, u64)> {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::zeroed();
+    // SAFETY: getrusage only writes the struct passed to it.
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    // SAFETY: a successful getrusage fills in the whole struct.
+    let usage = unsafe { usage.assume_init() };
+    let seconds = |tv: libc::timeval| tv.tv_sec as f64 + tv.tv_usec as f64 / 1e6;
+    // ru_maxrss is KiB on Linux but bytes on macOS.
+    let peak_kib = if cfg!(target_os = "macos") {
+        usage.ru_maxrss as u64 / 1024
+    } else {
+        usage.ru_maxrss as u64
+    };
+    Some((seconds(usage.ru_utime), seconds(usage.ru_stime), peak_kib))
+}
+
+/// Whether the last counted byte — the one just before `end` — is not a
+/// newline, probed directly so the bytes-only fast path stays read-free.
+/// Unreadable files report `false`; the counting path will surface the
+/// error itself.
+fn file_ends_without_newline(file: &File, end: u64) -> bool {
+    if end == 0 {
+        return false;
+    }
+    let Ok(mut probe) = file.try_clone() else {
+        return false;
+    };
+    let mut byte = [0u8; 1];
+    probe.seek(SeekFrom::Start(end - 1)).is_ok()
+        && probe.read_exact(&mut byte).is_ok()
+        && byte[0] != b'\n'
+}
+
+/// A stream counter that optionally runs the scalar reference alongside the
+/// detected backend and compares the two at the end, for `--verify`.
+struct VerifiedCounter {
+    backend: CountingBackend,
+    counter: StreamCounter,
+    reference: Option<StreamCounter>,
+    last_byte: Option<u8>,
+}
+
+impl VerifiedCounter {
+    fn new(sel: Selection, mode: CountMode, verify: bool, tab_width: u64) -> Self {
+        let backend = detect_simd_path();
+        VerifiedCounter {
+            backend,
+            counter: StreamCounter::new(sel, mode, backend).with_tab_width(tab_width),
+            reference: (verify && backend != CountingBackend::Scalar).then(|| {
+                StreamCounter::new(sel, mode, CountingBackend::Scalar).with_tab_width(tab_width)
+            }),
+            last_byte: None,
+        }
+    }
+
+    /// True once content has been seen whose final byte is not a newline.
+    fn ends_without_newline(&self) -> bool {
+        self.last_byte.is_some_and(|b| b != b'\n')
+    }
+
+    fn update(&mut self, buf: &[u8]) {
+        self.counter.update(buf);
+        if let Some(last) = buf.last() {
+            self.last_byte = Some(*last);
+        }
+        if let Some(reference) = &mut self.reference {
+            reference.update(buf);
+        }
+    }
+
+    fn finish(self) -> io::Result<Counts> {
+        let got = self.counter.finish();
+        if let Some(reference) = self.reference {
+            let expected = reference.finish();
+            if got != expected {
+                return Err(verify_io_error(&BackendMismatch {
+                    backend: self.backend,
+                    got,
+                    expected,
+                }));
+            }
+        }
+        Ok(got)
+    }
+}
+
+fn verify_io_error(mismatch: &BackendMismatch) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, mismatch.to_string())
+}
+
+/// Field width for the numeric columns, following GNU `wc`: wide enough for
+/// the byte-size estimate of all inputs, 7 when sizes are unknown (pipes,
+/// stdin), and collapsing to 1 for a single count of a single input.
+fn number_width(
+    sizes: &[Option<u64>],
+    sel: Selection,
+    rows: &[(Counts, Vec<u8>, RowFlags)],
+) -> usize {
+    if sel.len() == 1 && rows.len() == 1 {
+        return 1;
+    }
+    if sizes.iter().any(Option::is_none) || sizes.is_empty() {
+        return 7;
+    }
+    let total: u64 = sizes.iter().flatten().sum();
+    digits(total)
+}
+
+/// Width of the widest rendered field across every row being printed.
+fn rendered_width(
+    format: &NumberFormat,
+    sel: Selection,
+    rows: &[(Counts, Vec<u8>, RowFlags)],
+    total: Option<&Counts>,
+    precision: usize,
+) -> usize {
+    let avg = if sel.avg_word_length {
+        rows.iter()
+            .map(|(counts, _, _)| counts)
+            .chain(total)
+            .map(|counts| format!("{:.precision$}", counts.avg_word_length()).len())
+            .max()
+            .unwrap_or(1)
+    } else {
+        0
+    };
+    rows.iter()
+        .map(|(counts, _, _)| counts)
+        .chain(total)
+        .flat_map(|counts| {
+            [
+                (sel.lines, counts.lines),
+                (sel.words, counts.words),
+                (sel.chars, counts.chars),
+                (sel.bytes, counts.bytes),
+                (sel.max_line_length, counts.max_line_length),
+                (sel.max_words_per_line, counts.max_words_per_line),
+                (
+                    sel.min_words_per_line,
+                    counts.min_words_per_line.unwrap_or(0),
+                ),
+                (sel.unique_words, counts.unique_words),
+            ]
+        })
+        .filter(|(selected, _)| *selected)
+        .map(|(_, value)| format.render(value).len())
+        .max()
+        .unwrap_or(1)
+        .max(avg)
+}
+
+/// Column width for `--unordered` rows: they print before later results
+/// exist, so the width comes from the operand sizes alone. Unknown sizes
+/// get the same 7-column default streams do.
+fn unordered_width(format: &NumberFormat, sizes: &[Option<u64>]) -> usize {
+    if sizes.is_empty() || sizes.iter().any(Option::is_none) {
+        return 7;
+    }
+    let total: u64 = sizes.iter().flatten().sum();
+    format.render(total).len()
+}
+
+fn digits(mut n: u64) -> usize {
+    let mut d = 1;
+    while n >= 10 {
+        n /= 10;
+        d += 1;
+    }
+    d
+}
+
+/// Write one output row in GNU column order.
+/// Render a file name for the text output. `literal` writes the bytes
+/// untouched, as GNU wc does; the other styles escape the name so a row can
+/// be pasted back into a shell (`shell-escape`) or read by a C-string parser
+/// (`c`) without losing bytes.
+fn quote_name(name: &[u8], style: QuotingStyle) -> Vec<u8> {
+    match style {
+        QuotingStyle::Literal => name.to_vec(),
+        QuotingStyle::ShellEscape => shell_escape(name),
+        QuotingStyle::C => c_escape(name),
+    }
+}
+
+/// A file name as diagnostics print it: under the default literal style
+/// the lossy display form, otherwise escaped like the table rows — so a
+/// newline smuggled into a name cannot forge extra diagnostic lines once
+/// a quoting style is in effect.
+fn diagnostic_name(cli: &Cli, input: &Input) -> String {
+    match cli.quoting_style {
+        QuotingStyle::Literal => input.display_name(),
+        style => String::from_utf8_lossy(&quote_name(&input.name_bytes(), style)).into_owned(),
+    }
+}
+
+/// [`diagnostic_name`] for rows that only kept the raw name bytes.
+fn diagnostic_name_bytes(cli: &Cli, name: &[u8]) -> String {
+    match cli.quoting_style {
+        QuotingStyle::Literal => String::from_utf8_lossy(name).into_owned(),
+        style => String::from_utf8_lossy(&quote_name(name, style)).into_owned(),
+    }
+}
+
+/// Bytes a POSIX shell treats literally outside quotes.
+fn shell_safe(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"%+,-./:=@_".contains(&byte)
+}
+
+/// Plain names pass through; names with shell metacharacters are
+/// single-quoted; names with control bytes or invalid UTF-8 use the
+/// `$'...'` form, whose `\xNN` escapes reproduce the exact bytes.
+fn shell_escape(name: &[u8]) -> Vec<u8> {
+    if !name.is_empty() && name.iter().all(|&b| shell_safe(b)) {
+        return name.to_vec();
+    }
+    let plain_quotable = std::str::from_utf8(name).is_ok_and(|s| !s.chars().any(char::is_control));
+    if plain_quotable {
+        let mut out = vec![b'\''];
+        for &byte in name {
+            if byte == b'\'' {
+                out.extend_from_slice(b"'\\''");
+            } else {
+                out.push(byte);
+            }
+        }
+        out.push(b'\'');
+        return out;
+    }
+    let mut out = b"$'".to_vec();
+    for chunk in name.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\n' => out.extend_from_slice(b"\\n"),
+                '\t' => out.extend_from_slice(b"\\t"),
+                '\r' => out.extend_from_slice(b"\\r"),
+                '\\' => out.extend_from_slice(b"\\\\"),
+                '\'' => out.extend_from_slice(b"\\'"),
+                c if c.is_control() => {
+                    let mut buf = [0u8; 4];
+                    for byte in c.encode_utf8(&mut buf).bytes() {
+                        out.extend_from_slice(format!("\\x{byte:02x}").as_bytes());
+                    }
+                }
+                c => {
+                    let mut buf = [0u8; 4];
+                    out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+            }
+        }
+        for &byte in chunk.invalid() {
+            out.extend_from_slice(format!("\\x{byte:02x}").as_bytes());
+        }
+    }
+    out.push(b'\'');
+    out
+}
+
+/// C-style double-quoted form with octal escapes for control bytes and
+/// invalid UTF-8, mirroring coreutils' `--quoting-style=c`.
+fn c_escape(name: &[u8]) -> Vec<u8> {
+    let mut out = vec![b'"'];
+    for chunk in name.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\n' => out.extend_from_slice(b"\\n"),
+                '\t' => out.extend_from_slice(b"\\t"),
+                '\r' => out.extend_from_slice(b"\\r"),
+                '\\' => out.extend_from_slice(b"\\\\"),
+                '"' => out.extend_from_slice(b"\\\""),
+                c if c.is_control() => {
+                    let mut buf = [0u8; 4];
+                    for byte in c.encode_utf8(&mut buf).bytes() {
+                        out.extend_from_slice(format!("\\{byte:03o}").as_bytes());
+                    }
+                }
+                c => {
+                    let mut buf = [0u8; 4];
+                    out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+            }
+        }
+        for &byte in chunk.invalid() {
+            out.extend_from_slice(format!("\\{byte:03o}").as_bytes());
+        }
+    }
+    out.push(b'"');
+    out
+}
+
+fn write_counts(
+    out: &mut impl Write,
+    counts: &Counts,
+    sel: Selection,
+    format: &NumberFormat,
+    width: usize,
+    precision: usize,
+    name: Option<&[u8]>,
+) -> io::Result<()> {
+    let fields = [
+        (sel.lines, counts.lines),
+        (sel.words, counts.words),
+        (sel.chars, counts.chars),
+        (sel.bytes, counts.bytes),
+        (sel.max_line_length, counts.max_line_length),
+        (sel.max_words_per_line, counts.max_words_per_line),
+        (
+            sel.min_words_per_line,
+            counts.min_words_per_line.unwrap_or(0),
+        ),
+        (sel.unique_words, counts.unique_words),
+    ];
+    let mut first = true;
+    for (selected, value) in fields {
+        if !selected {
+            continue;
+        }
+        let value = format.render(value);
+        if first {
+            write!(out, "{value:>width$}")?;
+            first = false;
+        } else {
+            write!(out, " {value:>width$}")?;
+        }
+    }
+    // The average is a derived ratio, not a tally; it renders with its own
+    // fixed precision instead of going through the number format.
+    if sel.avg_word_length {
+        let value = format!("{:.precision$}", counts.avg_word_length());
+        if first {
+            write!(out, "{value:>width$}")?;
+        } else {
+            write!(out, " {value:>width$}")?;
+        }
+    }
+    if let Some(name) = name {
+        out.write_all(b" ")?;
+        out.write_all(name)?;
+    }
+    writeln!(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn western() -> Grouping {
+        Grouping {
+            separator: ",".to_string(),
+            sizes: vec![3],
+        }
+    }
+
+    /// Fails `failures` times with the given kind before yielding one byte.
+    struct FlakyReader {
+        failures: u32,
+        kind: io::ErrorKind,
+    }
+
+    impl Read for FlakyReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err(io::Error::from(self.kind));
+            }
+            buf[0] = b'x';
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn interrupted_reads_are_always_retried() {
+        let flaky = FlakyReader {
+            failures: 5,
+            kind: io::ErrorKind::Interrupted,
+        };
+        let mut reader = RetryReader::new(flaky, 0);
+        let mut buf = [0u8; 4];
+        assert_eq!(reader.read(&mut buf).unwrap(), 1);
+    }
+
+    #[test]
+    fn transient_errors_respect_the_retry_budget() {
+        let flaky = FlakyReader {
+            failures: 2,
+            kind: io::ErrorKind::TimedOut,
+        };
+        let mut buf = [0u8; 4];
+        assert_eq!(RetryReader::new(flaky, 2).read(&mut buf).unwrap(), 1);
+
+        let flaky = FlakyReader {
+            failures: 2,
+            kind: io::ErrorKind::TimedOut,
+        };
+        let err = RetryReader::new(flaky, 1).read(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn checkpoint_round_trips_through_its_text_form() {
+        let ckpt = Checkpoint {
+            offset: 1 << 40,
+            state: StreamState {
+                counts: Counts {
+                    lines: 7,
+                    words: 19,
+                    chars: 23,
+                    bytes: 1 << 40,
+                    max_line_length: 80,
+                    max_words_per_line: 6,
+                    min_words_per_line: Some(1),
+                    unique_words: 0,
+                    word_chars: 77,
+                },
+                in_word: true,
+                cols: 12,
+                line_words: 2,
+                pending: vec![0xe3, 0x81],
+                grapheme_carry: "e\u{301}".to_string(),
+                last_was_cr: true,
+            },
+        };
+        assert_eq!(parse_checkpoint(&render_checkpoint(&ckpt)), Some(ckpt));
+    }
+
+    #[test]
+    fn checkpoint_parsing_rejects_garbage() {
+        assert_eq!(parse_checkpoint(""), None);
+        assert_eq!(parse_checkpoint("wc-rs-checkpoint 2\noffset 0\n"), None);
+        assert_eq!(parse_checkpoint("wc-rs-checkpoint 1\noffset x\n"), None);
+        assert_eq!(parse_checkpoint("wc-rs-checkpoint 1\npending abc\n"), None);
+    }
+
+    #[test]
+    fn sniffing_honors_boms_and_heuristics() {
+        assert_eq!(sniff_encoding(b"\xef\xbb\xbfhi", true), encoding_rs::UTF_8);
+        assert_eq!(
+            sniff_encoding(b"\xff\xfeh\x00", true),
+            encoding_rs::UTF_16LE
+        );
+        assert_eq!(
+            sniff_encoding(b"\xfe\xff\x00h", true),
+            encoding_rs::UTF_16BE
+        );
+        assert_eq!(sniff_encoding(b"plain ascii\n", true), encoding_rs::UTF_8);
+        assert_eq!(sniff_encoding("héllo".as_bytes(), true), encoding_rs::UTF_8);
+        // NUL density and position reveal BOM-less UTF-16.
+        assert_eq!(
+            sniff_encoding(b"h\x00i\x00\n\x00", true),
+            encoding_rs::UTF_16LE
+        );
+        assert_eq!(
+            sniff_encoding(b"\x00h\x00i\x00\n", true),
+            encoding_rs::UTF_16BE
+        );
+        // A lone 0xE9 before a space fails every multibyte candidate.
+        assert_eq!(
+            sniff_encoding(b"caf\xe9 au lait", true),
+            encoding_rs::WINDOWS_1252
+        );
+    }
+
+    #[test]
+    fn shell_escape_quotes_only_when_needed() {
+        assert_eq!(shell_escape(b"plain/name.txt"), b"plain/name.txt".to_vec());
+        assert_eq!(shell_escape(b"a b"), b"'a b'".to_vec());
+        assert_eq!(shell_escape(b"it's"), b"'it'\\''s'".to_vec());
+        assert_eq!(shell_escape(b"a\nb"), b"$'a\\nb'".to_vec());
+        assert_eq!(shell_escape(b"a\xffb"), b"$'a\\xffb'".to_vec());
+    }
+
+    #[test]
+    fn c_escape_uses_octal_for_unprintable_bytes() {
+        assert_eq!(c_escape(b"a b"), b"\"a b\"".to_vec());
+        assert_eq!(c_escape(b"a\"b\\c"), b"\"a\\\"b\\\\c\"".to_vec());
+        assert_eq!(c_escape(b"a\tb\xff"), b"\"a\\tb\\377\"".to_vec());
+    }
+
+    #[test]
+    fn verbatim_prefix_round_trips_drive_and_unc_paths() {
+        assert_eq!(add_verbatim_prefix(r"C:\very\long"), r"\\?\C:\very\long");
+        assert_eq!(
+            add_verbatim_prefix(r"\\server\share\f"),
+            r"\\?\UNC\server\share\f"
+        );
+        assert_eq!(add_verbatim_prefix(r"\\?\C:\x"), r"\\?\C:\x");
+        assert_eq!(strip_verbatim_prefix(r"\\?\C:\very\long"), r"C:\very\long");
+        assert_eq!(
+            strip_verbatim_prefix(r"\\?\UNC\server\share\f"),
+            r"\\server\share\f"
+        );
+        assert_eq!(strip_verbatim_prefix(r"C:\plain"), r"C:\plain");
+    }
+
+    #[test]
+    fn grouping_inserts_separators_from_the_right() {
+        assert_eq!(western().apply(0), "0");
+        assert_eq!(western().apply(999), "999");
+        assert_eq!(western().apply(1000), "1,000");
+        assert_eq!(western().apply(1234567), "1,234,567");
+    }
+
+    #[test]
+    fn grouping_supports_uneven_group_sizes() {
+        // South Asian convention: 3 then repeating 2 (12,34,567).
+        let indic = Grouping {
+            separator: ",".to_string(),
+            sizes: vec![3, 2],
+        };
+        assert_eq!(indic.apply(1234567), "12,34,567");
+    }
+
+    #[test]
+    fn grouping_without_separator_is_a_no_op() {
+        let none = Grouping {
+            separator: String::new(),
+            sizes: vec![3],
+        };
+        assert_eq!(none.apply(1234567), "1234567");
+    }
+
+    #[test]
+    fn tty_hint_only_for_implicit_interactive_stdin() {
+        let parse =
+            |args: &[&str]| Cli::parse_from(std::iter::once("wc-rs").chain(args.iter().copied()));
+        assert!(should_hint_tty(&parse(&[]), false, true));
+        // Not a terminal, explicit operands, or an operand list: no hint.
+        assert!(!should_hint_tty(&parse(&[]), false, false));
+        assert!(!should_hint_tty(&parse(&["file.txt"]), false, true));
+        assert!(!should_hint_tty(
+            &parse(&["--files0-from", "-"]),
+            false,
+            true
+        ));
+        // Each opt-out works, and POSIX mode stays bare.
+        assert!(!should_hint_tty(&parse(&["--quiet"]), false, true));
+        assert!(!should_hint_tty(&parse(&["--no-tty-hint"]), false, true));
+        assert!(!should_hint_tty(&parse(&[]), true, true));
+    }
+}
@@ -0,0 +1,362 @@
+//! Parallel counting strategies.
+//!
+//! Two forms of parallelism are available: counting many files concurrently
+//! (one file per rayon task) and splitting a single large file into chunks
+//! counted concurrently and merged with [`ChunkCounts::merge`]. The
+//! [`choose_strategy`] heuristic picks between them from the shape of the
+//! input set; `--parallel-mode` overrides it. The per-file path also bounds
+//! how many files are open at once with [`OpenFileLimit`], since a large
+//! operand list can exceed the descriptor limit long before it exhausts the
+//! thread pool.
+
+use std::sync::{Condvar, Mutex};
+
+#[cfg(feature = "cli")]
+use clap::ValueEnum;
+use rayon::prelude::*;
+
+use crate::count::{count_chunk, split_point, ChunkCounts, CountMode, Counts, Selection};
+use crate::simd::CountingBackend;
+
+/// Minimum file size worth splitting across threads. Below this the
+/// per-chunk and merge overhead outweighs the parallelism.
+pub const MIN_CHUNK_SPLIT_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Descriptors held back from the open-file budget for stdio, the
+/// checkpoint and list files, and anything the runtime opens itself.
+const OPEN_FILE_HEADROOM: u64 = 32;
+
+/// Open-file budget when the platform limit cannot be queried. Matches a
+/// conservative reading of the Windows C runtime's default stream cap.
+const OPEN_FILE_FALLBACK: usize = 256;
+
+/// Caps how many files the file-parallel path holds open at once.
+///
+/// Thread count and descriptor budget are independent limits: `-j 64` over
+/// thousands of operands must not exhaust `RLIMIT_NOFILE`. A worker takes a
+/// permit before opening its file and holds it until the file closes; once
+/// the budget is spent, later workers queue on the condvar instead of
+/// failing with EMFILE mid-run.
+pub struct OpenFileLimit {
+    free: Mutex<usize>,
+    released: Condvar,
+}
+
+impl OpenFileLimit {
+    /// A limiter with an explicit permit count, floored at one so progress
+    /// is always possible.
+    pub fn new(permits: usize) -> Self {
+        OpenFileLimit {
+            free: Mutex::new(permits.max(1)),
+            released: Condvar::new(),
+        }
+    }
+
+    /// A limiter sized from the soft `RLIMIT_NOFILE`, minus [`OPEN_FILE_HEADROOM`]
+    /// and halved because a counted file may briefly hold two descriptors
+    /// (the file and its end-of-file probe clone).
+    pub fn from_rlimit() -> Self {
+        OpenFileLimit::new(open_file_budget())
+    }
+
+    /// Take a permit, blocking until one is free. It is returned when the
+    /// guard drops.
+    pub fn acquire(&self) -> OpenFileGuard<'_> {
+        let mut free = self.free.lock().unwrap();
+        while *free == 0 {
+            free = self.released.wait(free).unwrap();
+        }
+        *free -= 1;
+        OpenFileGuard { limit: self }
+    }
+}
+
+/// One open-file permit; dropping it wakes a queued worker.
+pub struct OpenFileGuard<'a> {
+    limit: &'a OpenFileLimit,
+}
+
+impl Drop for OpenFileGuard<'_> {
+    fn drop(&mut self) {
+        *self.limit.free.lock().unwrap() += 1;
+        self.limit.released.notify_one();
+    }
+}
+
+#[cfg(unix)]
+fn open_file_budget() -> usize {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: getrlimit only writes the struct passed to it.
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 {
+        return OPEN_FILE_FALLBACK;
+    }
+    if limit.rlim_cur == libc::RLIM_INFINITY {
+        return usize::MAX;
+    }
+    let budget = limit.rlim_cur.saturating_sub(OPEN_FILE_HEADROOM) / 2;
+    usize::try_from(budget).unwrap_or(usize::MAX).max(1)
+}
+
+#[cfg(not(unix))]
+fn open_file_budget() -> usize {
+    OPEN_FILE_FALLBACK
+}
+
+/// How to parallelize the work, as requested on the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+pub enum ParallelMode {
+    /// Count whole files concurrently.
+    Files,
+    /// Split each file into chunks counted concurrently.
+    Chunks,
+    /// Pick per input set; see [`choose_strategy`].
+    #[default]
+    Auto,
+}
+
+/// The P/E core split on hybrid CPUs, as the kernel reports it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HybridTopology {
+    /// CPU numbers of the performance cores.
+    pub performance: Vec<usize>,
+    /// CPU numbers of the efficiency cores.
+    pub efficiency: Vec<usize>,
+}
+
+/// Detect a hybrid P/E topology from the kernel's `cpu_core` and
+/// `cpu_atom` masks. `None` on homogeneous CPUs, where the masks do not
+/// exist, and on platforms without that sysfs interface.
+#[cfg(target_os = "linux")]
+pub fn hybrid_topology() -> Option<HybridTopology> {
+    let performance = parse_cpu_list(&std::fs::read_to_string("/sys/devices/cpu_core/cpus").ok()?)?;
+    let efficiency = parse_cpu_list(&std::fs::read_to_string("/sys/devices/cpu_atom/cpus").ok()?)?;
+    (!performance.is_empty() && !efficiency.is_empty()).then_some(HybridTopology {
+        performance,
+        efficiency,
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn hybrid_topology() -> Option<HybridTopology> {
+    None
+}
+
+/// Expand the kernel's `0-7,16-23` cpu-list syntax into CPU numbers.
+#[cfg(any(target_os = "linux", test))]
+fn parse_cpu_list(text: &str) -> Option<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for part in text.trim().split(',') {
+        if part.is_empty() {
+            continue;
+        }
+        match part.split_once('-') {
+            Some((start, end)) => {
+                let (start, end): (usize, usize) = (start.parse().ok()?, end.parse().ok()?);
+                if start > end {
+                    return None;
+                }
+                cpus.extend(start..=end);
+            }
+            None => cpus.push(part.parse().ok()?),
+        }
+    }
+    Some(cpus)
+}
+
+/// Pin the calling thread to the given CPUs. Advice only: failures are
+/// ignored, since an over-restricted cgroup mask still counts correctly.
+#[cfg(target_os = "linux")]
+pub fn pin_current_thread_to(cpus: &[usize]) {
+    // SAFETY: CPU_SET and sched_setaffinity only write the set we own.
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        for &cpu in cpus {
+            if cpu < libc::CPU_SETSIZE as usize {
+                libc::CPU_SET(cpu, &mut set);
+            }
+        }
+        let _ = libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn pin_current_thread_to(_cpus: &[usize]) {}
+
+/// The strategy actually used for a run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// One file at a time, each file split across threads.
+    Chunks,
+    /// Files in parallel, each counted on one thread.
+    Files,
+}
+
+/// Pick a strategy for `auto` mode.
+///
+/// Chunk splitting wins when there are fewer files than threads and the
+/// files are big enough that intra-file parallelism pays for itself: two
+/// 10 GiB files should each be split across all threads rather than
+/// occupying two of them. It requires mergeable counters, so a selection
+/// including max line length always counts per file.
+pub fn choose_strategy(
+    mode: ParallelMode,
+    sizes: &[Option<u64>],
+    sel: Selection,
+    threads: usize,
+) -> Strategy {
+    match mode {
+        ParallelMode::Files => Strategy::Files,
+        ParallelMode::Chunks => Strategy::Chunks,
+        ParallelMode::Auto => {
+            if !sel.is_chunk_mergeable() || threads < 2 {
+                return Strategy::Files;
+            }
+            let splittable = sizes
+                .iter()
+                .filter(|s| matches!(s, Some(n) if *n >= MIN_CHUNK_SPLIT_BYTES))
+                .count();
+            if splittable > 0 && sizes.len() < threads {
+                Strategy::Chunks
+            } else {
+                Strategy::Files
+            }
+        }
+    }
+}
+
+/// Count an in-memory buffer by splitting it into one chunk per thread and
+/// merging the per-chunk counters in order.
+pub fn count_slice_chunked(
+    data: &[u8],
+    sel: Selection,
+    mode: CountMode,
+    backend: CountingBackend,
+    threads: usize,
+) -> Counts {
+    debug_assert!(sel.is_chunk_mergeable());
+    let threads = threads.max(1);
+    let target = data.len().div_ceil(threads);
+    let mut bounds = Vec::with_capacity(threads + 1);
+    bounds.push(0);
+    while *bounds.last().unwrap() < data.len() {
+        let next = split_point(data, bounds.last().unwrap() + target);
+        bounds.push(next);
+    }
+    bounds
+        .par_windows(2)
+        .map(|w| count_chunk(&data[w[0]..w[1]], sel, mode, backend))
+        .collect::<Vec<_>>()
+        .into_iter()
+        .fold(ChunkCounts::default(), ChunkCounts::merge)
+        .finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::count::count_slice;
+
+    const SEL: Selection = Selection::DEFAULT;
+
+    #[test]
+    fn explicit_modes_are_honored() {
+        let sizes = [Some(MIN_CHUNK_SPLIT_BYTES * 2)];
+        assert_eq!(
+            choose_strategy(ParallelMode::Files, &sizes, SEL, 8),
+            Strategy::Files
+        );
+        assert_eq!(
+            choose_strategy(ParallelMode::Chunks, &sizes, SEL, 8),
+            Strategy::Chunks
+        );
+    }
+
+    #[test]
+    fn auto_splits_few_large_files() {
+        let sizes = [Some(10 << 30), Some(10 << 30)];
+        assert_eq!(
+            choose_strategy(ParallelMode::Auto, &sizes, SEL, 8),
+            Strategy::Chunks
+        );
+    }
+
+    #[test]
+    fn auto_prefers_per_file_for_many_or_small_files() {
+        let many: Vec<_> = (0..32).map(|_| Some(10u64 << 30)).collect();
+        assert_eq!(
+            choose_strategy(ParallelMode::Auto, &many, SEL, 8),
+            Strategy::Files
+        );
+        let small = [Some(4096), Some(4096)];
+        assert_eq!(
+            choose_strategy(ParallelMode::Auto, &small, SEL, 8),
+            Strategy::Files
+        );
+    }
+
+    #[test]
+    fn auto_never_splits_unmergeable_selections() {
+        let sel = Selection {
+            max_line_length: true,
+            ..Selection::DEFAULT
+        };
+        let sizes = [Some(10 << 30)];
+        assert_eq!(
+            choose_strategy(ParallelMode::Auto, &sizes, sel, 8),
+            Strategy::Files
+        );
+    }
+
+    #[test]
+    fn open_file_permits_bound_concurrency() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let limit = OpenFileLimit::new(2);
+        let open = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..50 {
+                        let _permit = limit.acquire();
+                        let now = open.fetch_add(1, Ordering::SeqCst) + 1;
+                        peak.fetch_max(now, Ordering::SeqCst);
+                        open.fetch_sub(1, Ordering::SeqCst);
+                    }
+                });
+            }
+        });
+        assert!(peak.load(Ordering::SeqCst) <= 2);
+    }
+
+    #[test]
+    fn cpu_lists_expand_like_the_kernel_writes_them() {
+        assert_eq!(
+            parse_cpu_list("0-7,16-23\n"),
+            Some((0..8).chain(16..24).collect())
+        );
+        assert_eq!(parse_cpu_list("3"), Some(vec![3]));
+        assert_eq!(parse_cpu_list(""), Some(vec![]));
+        assert_eq!(parse_cpu_list("7-3"), None);
+        assert_eq!(parse_cpu_list("p-cores"), None);
+    }
+
+    #[test]
+    fn chunked_counts_match_sequential() {
+        let data = "the quick brown fox\njumps over the lazy dog\n"
+            .repeat(1000)
+            .into_bytes();
+        let backend = CountingBackend::detect();
+        let whole = count_slice(&data, SEL, CountMode::Utf8, backend);
+        for threads in [1, 2, 3, 7] {
+            assert_eq!(
+                count_slice_chunked(&data, SEL, CountMode::Utf8, backend, threads),
+                whole
+            );
+        }
+    }
+}
@@ -0,0 +1,850 @@
+//! Runtime-dispatched byte-classification kernels.
+//!
+//! [`CountingBackend`] picks the widest SIMD path the running CPU supports
+//! and provides the bulk counting primitives the scalar scan does not need:
+//! newline counting and UTF-8 character (non-continuation byte) counting.
+//! Every backend must agree with [`CountingBackend::Scalar`] bit for bit;
+//! the tests check that on this machine's available backends.
+
+use std::sync::{LazyLock, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "cli")]
+use clap::ValueEnum;
+
+/// The backend every counting call site uses, resolved at most once.
+static DETECTED: OnceLock<CountingBackend> = OnceLock::new();
+
+/// A counting implementation selected at runtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CountingBackend {
+    /// Portable fallback, available everywhere.
+    Scalar,
+    /// 128-bit SSE2 kernels (baseline on x86_64).
+    #[cfg(target_arch = "x86_64")]
+    Sse2,
+    /// 256-bit AVX2 kernels.
+    #[cfg(target_arch = "x86_64")]
+    Avx2,
+    /// 512-bit AVX-512BW kernels; the mask popcounts are batched through
+    /// VPOPCNTDQ when the CPU has that sub-feature.
+    #[cfg(target_arch = "x86_64")]
+    Avx512,
+    /// 128-bit NEON kernels (baseline on aarch64).
+    #[cfg(target_arch = "aarch64")]
+    Neon,
+    /// A downstream kernel added through [`register_kernel`], identified by
+    /// its registration slot.
+    External(u8),
+}
+
+impl CountingBackend {
+    /// Probe the CPU and return the preferred backend.
+    pub fn detect() -> CountingBackend {
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("avx512bw") {
+                return CountingBackend::Avx512;
+            }
+            if is_x86_feature_detected!("avx2") {
+                return CountingBackend::Avx2;
+            }
+            CountingBackend::Sse2
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            CountingBackend::Neon
+        }
+        #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+        {
+            CountingBackend::Scalar
+        }
+    }
+
+    /// Like [`CountingBackend::detect`], but probing the CPU only once per
+    /// process. Prefer this at counting call sites: repeated cpuid probing
+    /// is measurable when the library is embedded and invoked per buffer.
+    pub fn detect_cached() -> CountingBackend {
+        *DETECTED.get_or_init(CountingBackend::detect)
+    }
+
+    /// All backends usable on the running CPU: registered external kernels
+    /// first (they were added deliberately), then the native paths widest
+    /// first.
+    pub fn available() -> Vec<CountingBackend> {
+        let mut v = Vec::new();
+        for (slot, kernel) in EXTERNAL.read().unwrap().iter().enumerate() {
+            if kernel.is_available() {
+                v.push(CountingBackend::External(slot as u8));
+            }
+        }
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("avx512bw") {
+                v.push(CountingBackend::Avx512);
+            }
+            if is_x86_feature_detected!("avx2") {
+                v.push(CountingBackend::Avx2);
+            }
+            v.push(CountingBackend::Sse2);
+        }
+        #[cfg(target_arch = "aarch64")]
+        v.push(CountingBackend::Neon);
+        v.push(CountingBackend::Scalar);
+        v
+    }
+
+    /// The backend's kernel table. Dispatch resolves here, once; after
+    /// that every primitive is a plain indirect call, which is what hot
+    /// paths counting many small buffers should hold on to.
+    pub fn kernels(self) -> Kernels {
+        match self {
+            CountingBackend::Scalar => SCALAR_KERNELS,
+            #[cfg(target_arch = "x86_64")]
+            CountingBackend::Sse2 => SSE2_KERNELS,
+            #[cfg(target_arch = "x86_64")]
+            CountingBackend::Avx2 => AVX2_KERNELS,
+            #[cfg(target_arch = "x86_64")]
+            CountingBackend::Avx512 => {
+                // Sub-feature refinement layered on the avx512bw gate. The
+                // probe result is cached by std, so re-resolving a table
+                // costs an atomic load.
+                if is_x86_feature_detected!("avx512vpopcntdq") {
+                    AVX512_VPOPCNT_KERNELS
+                } else {
+                    AVX512_KERNELS
+                }
+            }
+            #[cfg(target_arch = "aarch64")]
+            CountingBackend::Neon => NEON_KERNELS,
+            CountingBackend::External(slot) => EXTERNAL_TABLES[slot as usize],
+        }
+    }
+
+    /// Count newline bytes.
+    pub fn count_lines(&self, data: &[u8]) -> u64 {
+        self.kernels().count_lines(data)
+    }
+
+    /// Count CRLF pairs: newline bytes whose predecessor is a carriage
+    /// return. The SIMD paths test `\n` equality against a one-byte-shifted
+    /// `\r` equality mask, so the pair is found without a second pass.
+    pub fn count_crlf(&self, data: &[u8]) -> u64 {
+        self.kernels().count_crlf(data)
+    }
+
+    /// Count UTF-8 characters: the number of successfully decoded scalar
+    /// values, as GNU `wc -m` reports. Invalid sequences contribute nothing.
+    pub fn count_utf8_chars(&self, data: &[u8]) -> u64 {
+        self.kernels().count_utf8_chars(data)
+    }
+}
+
+/// One backend's counting primitives as plain function pointers, resolved
+/// by [`CountingBackend::kernels`] (or once per process by
+/// [`resolved_kernels`]) instead of re-matching the enum on every call.
+#[derive(Debug, Clone, Copy)]
+pub struct Kernels {
+    lines: fn(&[u8]) -> u64,
+    crlf: fn(&[u8]) -> u64,
+    non_continuation: fn(&[u8]) -> u64,
+}
+
+impl Kernels {
+    /// Count newline bytes.
+    pub fn count_lines(&self, data: &[u8]) -> u64 {
+        (self.lines)(data)
+    }
+
+    /// Count CRLF pairs.
+    pub fn count_crlf(&self, data: &[u8]) -> u64 {
+        (self.crlf)(data)
+    }
+
+    /// Count UTF-8 characters. Valid regions are counted with the SIMD
+    /// non-continuation-byte kernel (within valid UTF-8 the two are
+    /// identical), so the common all-valid case stays on the fast path.
+    pub fn count_utf8_chars(&self, data: &[u8]) -> u64 {
+        data.utf8_chunks()
+            .map(|chunk| (self.non_continuation)(chunk.valid().as_bytes()))
+            .sum()
+    }
+}
+
+const SCALAR_KERNELS: Kernels = Kernels {
+    lines: scalar::count_lines,
+    crlf: scalar::count_crlf,
+    non_continuation: scalar::count_non_continuation,
+};
+
+// SAFETY (both x86 tables): the tables are only reachable through a
+// `CountingBackend` variant, which is only constructed when the matching
+// CPU feature has been detected.
+#[cfg(target_arch = "x86_64")]
+const SSE2_KERNELS: Kernels = Kernels {
+    lines: |data| unsafe { x86::count_lines_sse2(data) },
+    crlf: |data| unsafe { x86::count_crlf_sse2(data) },
+    non_continuation: |data| unsafe { x86::count_non_continuation_sse2(data) },
+};
+
+#[cfg(target_arch = "x86_64")]
+const AVX2_KERNELS: Kernels = Kernels {
+    lines: |data| unsafe { x86::count_lines_avx2(data) },
+    crlf: |data| unsafe { x86::count_crlf_avx2(data) },
+    non_continuation: |data| unsafe { x86::count_non_continuation_avx2(data) },
+};
+
+#[cfg(target_arch = "x86_64")]
+const AVX512_KERNELS: Kernels = Kernels {
+    lines: |data| unsafe { x86::count_lines_avx512(data) },
+    crlf: |data| unsafe { x86::count_crlf_avx512(data) },
+    non_continuation: |data| unsafe { x86::count_non_continuation_avx512(data) },
+};
+
+#[cfg(target_arch = "x86_64")]
+const AVX512_VPOPCNT_KERNELS: Kernels = Kernels {
+    lines: |data| unsafe { x86::count_lines_avx512_vpopcnt(data) },
+    crlf: |data| unsafe { x86::count_crlf_avx512(data) },
+    non_continuation: |data| unsafe { x86::count_non_continuation_avx512_vpopcnt(data) },
+};
+
+#[cfg(target_arch = "aarch64")]
+const NEON_KERNELS: Kernels = Kernels {
+    lines: neon::count_lines,
+    crlf: neon::count_crlf,
+    non_continuation: neon::count_non_continuation,
+};
+
+/// A pluggable counting backend: the same three bulk primitives the
+/// built-in tables provide, implementable outside this crate (a GPU or
+/// FPGA offload, an instrumented wrapper). Registered kernels join
+/// [`CountingBackend::available`], and through it `--backend auto-bench`
+/// and the `--self-test` consistency check; every implementation must
+/// agree with [`CountingBackend::Scalar`] bit for bit.
+pub trait CountKernel: Send + Sync {
+    /// Short name shown in diagnostics.
+    fn name(&self) -> &'static str;
+
+    /// Whether the kernel can run on this machine; unavailable kernels
+    /// stay registered but are left out of [`CountingBackend::available`].
+    fn is_available(&self) -> bool {
+        true
+    }
+
+    /// Count newline bytes.
+    fn count_lines(&self, data: &[u8]) -> u64;
+
+    /// Count CRLF pairs.
+    fn count_crlf(&self, data: &[u8]) -> u64;
+
+    /// Count bytes that are not UTF-8 continuation bytes.
+    fn count_non_continuation(&self, data: &[u8]) -> u64;
+}
+
+/// Registered external kernels, by slot. The limit exists because each
+/// slot needs a monomorphized trampoline to fit the [`Kernels`] function
+/// pointers.
+const MAX_EXTERNAL: usize = 8;
+static EXTERNAL: RwLock<Vec<&'static dyn CountKernel>> = RwLock::new(Vec::new());
+
+/// Register a downstream kernel and return the backend that selects it,
+/// or `None` once all [`MAX_EXTERNAL`] slots are taken. The kernel is
+/// process-global and never unregistered; pass the returned backend to
+/// counting calls directly, or to [`pin_backend`] to make it the default.
+pub fn register_kernel(kernel: &'static dyn CountKernel) -> Option<CountingBackend> {
+    let mut kernels = EXTERNAL.write().unwrap();
+    if kernels.len() >= MAX_EXTERNAL {
+        return None;
+    }
+    kernels.push(kernel);
+    Some(CountingBackend::External((kernels.len() - 1) as u8))
+}
+
+/// The name a registered backend was given, for diagnostics.
+pub fn external_kernel_name(slot: u8) -> Option<&'static str> {
+    EXTERNAL
+        .read()
+        .unwrap()
+        .get(slot as usize)
+        .map(|kernel| kernel.name())
+}
+
+fn external_kernel(slot: usize) -> &'static dyn CountKernel {
+    // Only reachable through a `CountingBackend::External` constructed by
+    // `register_kernel`, so the slot is always populated.
+    EXTERNAL.read().unwrap()[slot]
+}
+
+fn external_table<const SLOT: usize>() -> Kernels {
+    Kernels {
+        lines: |data| external_kernel(SLOT).count_lines(data),
+        crlf: |data| external_kernel(SLOT).count_crlf(data),
+        non_continuation: |data| external_kernel(SLOT).count_non_continuation(data),
+    }
+}
+
+static EXTERNAL_TABLES: LazyLock<[Kernels; MAX_EXTERNAL]> = LazyLock::new(|| {
+    [
+        external_table::<0>(),
+        external_table::<1>(),
+        external_table::<2>(),
+        external_table::<3>(),
+        external_table::<4>(),
+        external_table::<5>(),
+        external_table::<6>(),
+        external_table::<7>(),
+    ]
+});
+
+/// The detected backend's kernel table, resolved once per process — the
+/// ifunc pattern for embedders counting many small buffers.
+pub fn resolved_kernels() -> Kernels {
+    static RESOLVED: OnceLock<Kernels> = OnceLock::new();
+    *RESOLVED.get_or_init(|| CountingBackend::detect_cached().kernels())
+}
+
+/// Detect the SIMD path for this process. Cached after the first call.
+pub fn detect_simd_path() -> CountingBackend {
+    CountingBackend::detect_cached()
+}
+
+/// How the backend is chosen, from the binary's `--backend`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+pub enum BackendChoice {
+    /// Trust CPU feature detection and use the widest path it reports.
+    #[default]
+    Auto,
+    /// Time every available backend on a small buffer at startup and keep
+    /// the fastest; helps CPUs whose widest path downclocks under load.
+    AutoBench,
+}
+
+/// Pin the backend every later [`detect_simd_path`] call returns. A no-op
+/// once detection has run, so call it before any counting starts.
+pub fn pin_backend(backend: CountingBackend) {
+    let _ = DETECTED.set(backend);
+}
+
+/// Micro-benchmark the available backends on a small in-memory buffer and
+/// return the fastest. Each candidate's best of a few rounds is kept to
+/// damp scheduler noise, and ties go to the wider path.
+pub fn bench_fastest() -> CountingBackend {
+    const BUF_LEN: usize = 256 * 1024;
+    const ROUNDS: usize = 5;
+    let data: Vec<u8> = (0..BUF_LEN).map(|i| (i % 251) as u8).collect();
+    let mut best = (CountingBackend::Scalar, Duration::MAX);
+    // `available` lists backends widest first; a strict comparison keeps
+    // the earlier (wider) candidate on equal times.
+    for backend in CountingBackend::available() {
+        let mut fastest = Duration::MAX;
+        for _ in 0..ROUNDS {
+            let start = Instant::now();
+            let n = backend.count_lines(&data) + backend.count_utf8_chars(&data);
+            std::hint::black_box(n);
+            fastest = fastest.min(start.elapsed());
+        }
+        if fastest < best.1 {
+            best = (backend, fastest);
+        }
+    }
+    best.0
+}
+
+mod scalar {
+    pub fn count_lines(data: &[u8]) -> u64 {
+        memchr::memchr_iter(b'\n', data).count() as u64
+    }
+
+    pub fn count_crlf(data: &[u8]) -> u64 {
+        memchr::memchr_iter(b'\n', data)
+            .filter(|&i| i > 0 && data[i - 1] == b'\r')
+            .count() as u64
+    }
+
+    pub fn count_non_continuation(data: &[u8]) -> u64 {
+        data.iter().filter(|&&b| b & 0xc0 != 0x80).count() as u64
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use std::arch::x86_64::*;
+
+    /// Split off the bytes before the first `ALIGN`-byte boundary, so a
+    /// kernel can peel them with the scalar path and run aligned loads on
+    /// the rest — measurably cheaper on some microarchitectures and on
+    /// mmap-backed buffers. The CRLF kernels keep unaligned loads: their
+    /// deliberately overlapping predecessor load cannot be aligned.
+    fn split_at_alignment<const ALIGN: usize>(data: &[u8]) -> (&[u8], &[u8]) {
+        let offset = data.as_ptr().align_offset(ALIGN).min(data.len());
+        data.split_at(offset)
+    }
+
+    /// Hint the cache a few lines past `ptr`, far enough ahead to hide
+    /// memory latency when a large buffer is streaming in cold. Compiled
+    /// to nothing without the `prefetch` feature.
+    #[inline(always)]
+    fn prefetch_ahead(ptr: *const u8) {
+        #[cfg(feature = "prefetch")]
+        // SAFETY: prefetch is a hint that cannot fault, and the wrapped
+        // pointer is never dereferenced, so running past the end of the
+        // buffer is harmless.
+        unsafe {
+            _mm_prefetch(ptr.wrapping_add(512) as *const i8, _MM_HINT_T0);
+        }
+        #[cfg(not(feature = "prefetch"))]
+        let _ = ptr;
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn count_lines_sse2(data: &[u8]) -> u64 {
+        let (head, body) = split_at_alignment::<16>(data);
+        let mut total = super::scalar::count_lines(head);
+        let needle = _mm_set1_epi8(b'\n' as i8);
+        let mut chunks = body.chunks_exact(16);
+        for chunk in &mut chunks {
+            prefetch_ahead(chunk.as_ptr());
+            let v = _mm_load_si128(chunk.as_ptr() as *const __m128i);
+            let eq = _mm_cmpeq_epi8(v, needle);
+            total += (_mm_movemask_epi8(eq) as u32).count_ones() as u64;
+        }
+        total + super::scalar::count_lines(chunks.remainder())
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn count_crlf_sse2(data: &[u8]) -> u64 {
+        if data.len() < 17 {
+            return super::scalar::count_crlf(data);
+        }
+        let lf = _mm_set1_epi8(b'\n' as i8);
+        let cr = _mm_set1_epi8(b'\r' as i8);
+        let mut total = 0u64;
+        // Start at 1 so every lane has a predecessor to load.
+        let mut i = 1usize;
+        while i + 16 <= data.len() {
+            prefetch_ahead(data.as_ptr().wrapping_add(i));
+            let at = _mm_loadu_si128(data.as_ptr().add(i) as *const __m128i);
+            let before = _mm_loadu_si128(data.as_ptr().add(i - 1) as *const __m128i);
+            let pair = _mm_and_si128(_mm_cmpeq_epi8(at, lf), _mm_cmpeq_epi8(before, cr));
+            total += (_mm_movemask_epi8(pair) as u32).count_ones() as u64;
+            i += 16;
+        }
+        // The tail slice keeps its predecessor byte, so pairs ending in it
+        // are found and pairs already counted end before it.
+        total + super::scalar::count_crlf(&data[i - 1..])
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn count_non_continuation_sse2(data: &[u8]) -> u64 {
+        let (head, body) = split_at_alignment::<16>(data);
+        let mut continuations = 0u64;
+        let mask = _mm_set1_epi8(0xc0u8 as i8);
+        let cont = _mm_set1_epi8(0x80u8 as i8);
+        let mut chunks = body.chunks_exact(16);
+        for chunk in &mut chunks {
+            prefetch_ahead(chunk.as_ptr());
+            let v = _mm_load_si128(chunk.as_ptr() as *const __m128i);
+            let eq = _mm_cmpeq_epi8(_mm_and_si128(v, mask), cont);
+            continuations += (_mm_movemask_epi8(eq) as u32).count_ones() as u64;
+        }
+        let rem = chunks.remainder();
+        super::scalar::count_non_continuation(head) + (body.len() - rem.len()) as u64
+            - continuations
+            + super::scalar::count_non_continuation(rem)
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn count_lines_avx2(data: &[u8]) -> u64 {
+        let (head, body) = split_at_alignment::<32>(data);
+        let mut total = super::scalar::count_lines(head);
+        let needle = _mm256_set1_epi8(b'\n' as i8);
+        let mut chunks = body.chunks_exact(32);
+        for chunk in &mut chunks {
+            prefetch_ahead(chunk.as_ptr());
+            let v = _mm256_load_si256(chunk.as_ptr() as *const __m256i);
+            let eq = _mm256_cmpeq_epi8(v, needle);
+            total += (_mm256_movemask_epi8(eq) as u32).count_ones() as u64;
+        }
+        total + super::scalar::count_lines(chunks.remainder())
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn count_crlf_avx2(data: &[u8]) -> u64 {
+        if data.len() < 33 {
+            return super::scalar::count_crlf(data);
+        }
+        let lf = _mm256_set1_epi8(b'\n' as i8);
+        let cr = _mm256_set1_epi8(b'\r' as i8);
+        let mut total = 0u64;
+        let mut i = 1usize;
+        while i + 32 <= data.len() {
+            prefetch_ahead(data.as_ptr().wrapping_add(i));
+            let at = _mm256_loadu_si256(data.as_ptr().add(i) as *const __m256i);
+            let before = _mm256_loadu_si256(data.as_ptr().add(i - 1) as *const __m256i);
+            let pair = _mm256_and_si256(_mm256_cmpeq_epi8(at, lf), _mm256_cmpeq_epi8(before, cr));
+            total += (_mm256_movemask_epi8(pair) as u32).count_ones() as u64;
+            i += 32;
+        }
+        total + super::scalar::count_crlf(&data[i - 1..])
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn count_non_continuation_avx2(data: &[u8]) -> u64 {
+        let (head, body) = split_at_alignment::<32>(data);
+        let mut continuations = 0u64;
+        let mask = _mm256_set1_epi8(0xc0u8 as i8);
+        let cont = _mm256_set1_epi8(0x80u8 as i8);
+        let mut chunks = body.chunks_exact(32);
+        for chunk in &mut chunks {
+            prefetch_ahead(chunk.as_ptr());
+            let v = _mm256_load_si256(chunk.as_ptr() as *const __m256i);
+            let eq = _mm256_cmpeq_epi8(_mm256_and_si256(v, mask), cont);
+            continuations += (_mm256_movemask_epi8(eq) as u32).count_ones() as u64;
+        }
+        let rem = chunks.remainder();
+        super::scalar::count_non_continuation(head) + (body.len() - rem.len()) as u64
+            - continuations
+            + super::scalar::count_non_continuation(rem)
+    }
+
+    #[target_feature(enable = "avx512bw")]
+    pub unsafe fn count_lines_avx512(data: &[u8]) -> u64 {
+        let (head, body) = split_at_alignment::<64>(data);
+        let mut total = super::scalar::count_lines(head);
+        let needle = _mm512_set1_epi8(b'\n' as i8);
+        let mut chunks = body.chunks_exact(64);
+        for chunk in &mut chunks {
+            prefetch_ahead(chunk.as_ptr());
+            let v = _mm512_load_si512(chunk.as_ptr() as *const __m512i);
+            total += _mm512_cmpeq_epi8_mask(v, needle).count_ones() as u64;
+        }
+        total + super::scalar::count_lines(chunks.remainder())
+    }
+
+    /// Unlike the narrower paths, the predecessor byte comes from mask
+    /// arithmetic — shift the `\r` mask up one bit and carry its top bit
+    /// into the next chunk — so no overlapping load is needed.
+    #[target_feature(enable = "avx512bw")]
+    pub unsafe fn count_crlf_avx512(data: &[u8]) -> u64 {
+        let lf = _mm512_set1_epi8(b'\n' as i8);
+        let cr = _mm512_set1_epi8(b'\r' as i8);
+        let mut total = 0u64;
+        let mut cr_carry = 0u64;
+        let mut chunks = data.chunks_exact(64);
+        for chunk in &mut chunks {
+            prefetch_ahead(chunk.as_ptr());
+            let v = _mm512_loadu_si512(chunk.as_ptr() as *const __m512i);
+            let lf_mask = _mm512_cmpeq_epi8_mask(v, lf);
+            let cr_mask = _mm512_cmpeq_epi8_mask(v, cr);
+            total += (lf_mask & ((cr_mask << 1) | cr_carry)).count_ones() as u64;
+            cr_carry = cr_mask >> 63;
+        }
+        let rem = chunks.remainder();
+        total
+            + super::scalar::count_crlf(rem)
+            + u64::from(cr_carry == 1 && rem.first() == Some(&b'\n'))
+    }
+
+    #[target_feature(enable = "avx512bw")]
+    pub unsafe fn count_non_continuation_avx512(data: &[u8]) -> u64 {
+        let (head, body) = split_at_alignment::<64>(data);
+        let mut continuations = 0u64;
+        let mask = _mm512_set1_epi8(0xc0u8 as i8);
+        let cont = _mm512_set1_epi8(0x80u8 as i8);
+        let mut chunks = body.chunks_exact(64);
+        for chunk in &mut chunks {
+            prefetch_ahead(chunk.as_ptr());
+            let v = _mm512_load_si512(chunk.as_ptr() as *const __m512i);
+            continuations +=
+                _mm512_cmpeq_epi8_mask(_mm512_and_si512(v, mask), cont).count_ones() as u64;
+        }
+        let rem = chunks.remainder();
+        super::scalar::count_non_continuation(head) + (body.len() - rem.len()) as u64
+            - continuations
+            + super::scalar::count_non_continuation(rem)
+    }
+
+    /// Like [`count_lines_avx512`], but eight chunk masks are batched into
+    /// one vector and popcounted with a single VPOPCNTDQ, keeping the
+    /// scalar unit out of the hot loop on Ice Lake and newer.
+    #[target_feature(enable = "avx512bw,avx512vpopcntdq")]
+    pub unsafe fn count_lines_avx512_vpopcnt(data: &[u8]) -> u64 {
+        let (head, body) = split_at_alignment::<64>(data);
+        let needle = _mm512_set1_epi8(b'\n' as i8);
+        let mut acc = _mm512_setzero_si512();
+        let mut groups = body.chunks_exact(512);
+        for group in &mut groups {
+            let mut masks = [0u64; 8];
+            for (lane, chunk) in group.chunks_exact(64).enumerate() {
+                prefetch_ahead(chunk.as_ptr());
+                let v = _mm512_load_si512(chunk.as_ptr() as *const __m512i);
+                masks[lane] = _mm512_cmpeq_epi8_mask(v, needle);
+            }
+            let packed = _mm512_loadu_si512(masks.as_ptr() as *const __m512i);
+            acc = _mm512_add_epi64(acc, _mm512_popcnt_epi64(packed));
+        }
+        super::scalar::count_lines(head)
+            + _mm512_reduce_add_epi64(acc) as u64
+            + count_lines_avx512(groups.remainder())
+    }
+
+    /// The VPOPCNTDQ batching of [`count_non_continuation_avx512`].
+    #[target_feature(enable = "avx512bw,avx512vpopcntdq")]
+    pub unsafe fn count_non_continuation_avx512_vpopcnt(data: &[u8]) -> u64 {
+        let (head, body) = split_at_alignment::<64>(data);
+        let mask = _mm512_set1_epi8(0xc0u8 as i8);
+        let cont = _mm512_set1_epi8(0x80u8 as i8);
+        let mut acc = _mm512_setzero_si512();
+        let mut groups = body.chunks_exact(512);
+        for group in &mut groups {
+            let mut masks = [0u64; 8];
+            for (lane, chunk) in group.chunks_exact(64).enumerate() {
+                prefetch_ahead(chunk.as_ptr());
+                let v = _mm512_load_si512(chunk.as_ptr() as *const __m512i);
+                masks[lane] = _mm512_cmpeq_epi8_mask(_mm512_and_si512(v, mask), cont);
+            }
+            let packed = _mm512_loadu_si512(masks.as_ptr() as *const __m512i);
+            acc = _mm512_add_epi64(acc, _mm512_popcnt_epi64(packed));
+        }
+        let continuations = _mm512_reduce_add_epi64(acc) as u64;
+        let rem = groups.remainder();
+        super::scalar::count_non_continuation(head) + (body.len() - rem.len()) as u64
+            - continuations
+            + count_non_continuation_avx512(rem)
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod neon {
+    use std::arch::aarch64::*;
+
+    /// Hint the cache a few lines past `ptr`; the PRFM twin of the x86
+    /// helper. Compiled to nothing without the `prefetch` feature.
+    #[inline(always)]
+    fn prefetch_ahead(ptr: *const u8) {
+        #[cfg(feature = "prefetch")]
+        // SAFETY: PRFM is a hint that cannot fault, and the wrapped
+        // pointer is never dereferenced.
+        unsafe {
+            std::arch::asm!(
+                "prfm pldl1keep, [{0}]",
+                in(reg) ptr.wrapping_add(512),
+                options(nostack, preserves_flags, readonly),
+            );
+        }
+        #[cfg(not(feature = "prefetch"))]
+        let _ = ptr;
+    }
+
+    pub fn count_lines(data: &[u8]) -> u64 {
+        // SAFETY: NEON is mandatory on aarch64.
+        let full = unsafe { count_matching(data, |v| vceqq_u8(v, vdupq_n_u8(b'\n'))) };
+        full + super::scalar::count_lines(data.chunks_exact(16).remainder())
+    }
+
+    pub fn count_crlf(data: &[u8]) -> u64 {
+        if data.len() < 17 {
+            return super::scalar::count_crlf(data);
+        }
+        // SAFETY: NEON is mandatory on aarch64.
+        unsafe {
+            let lf = vdupq_n_u8(b'\n');
+            let cr = vdupq_n_u8(b'\r');
+            let one = vdupq_n_u8(1);
+            let mut total = 0u64;
+            // Start at 1 so every lane has a predecessor to load.
+            let mut i = 1usize;
+            while i + 16 <= data.len() {
+                prefetch_ahead(data.as_ptr().wrapping_add(i));
+                let at = vld1q_u8(data.as_ptr().add(i));
+                let before = vld1q_u8(data.as_ptr().add(i - 1));
+                let pair = vandq_u8(vceqq_u8(at, lf), vceqq_u8(before, cr));
+                total += u64::from(vaddlvq_u8(vandq_u8(pair, one)));
+                i += 16;
+            }
+            total + super::scalar::count_crlf(&data[i - 1..])
+        }
+    }
+
+    pub fn count_non_continuation(data: &[u8]) -> u64 {
+        // SAFETY: NEON is mandatory on aarch64.
+        let continuations = unsafe {
+            count_matching(data, |v| {
+                vceqq_u8(vandq_u8(v, vdupq_n_u8(0xc0)), vdupq_n_u8(0x80))
+            })
+        };
+        let rem = data.chunks_exact(16).remainder();
+        data.len() as u64 - continuations - rem.len() as u64
+            + super::scalar::count_non_continuation(rem)
+    }
+
+    /// Count bytes for which `classify` produces an all-ones lane, over the
+    /// complete 16-byte chunks of `data`.
+    #[target_feature(enable = "neon")]
+    unsafe fn count_matching(data: &[u8], classify: impl Fn(uint8x16_t) -> uint8x16_t) -> u64 {
+        let mut total = 0u64;
+        let mut chunks = data.chunks_exact(16).peekable();
+        while chunks.peek().is_some() {
+            // A u8 lane accumulates at most one per chunk, so flushing every
+            // 128 chunks stays well clear of overflow.
+            let mut acc = vdupq_n_u8(0);
+            for chunk in chunks.by_ref().take(128) {
+                prefetch_ahead(chunk.as_ptr());
+                let v = vld1q_u8(chunk.as_ptr());
+                // Each matching lane is 0xFF (-1); subtracting adds one.
+                acc = vsubq_u8(acc, classify(v));
+            }
+            total += vaddlvq_u8(acc) as u64;
+        }
+        total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Vec<u8> {
+        let mut v = Vec::new();
+        for i in 0..4096u32 {
+            v.push((i % 251) as u8);
+        }
+        v.extend_from_slice("line one\nline twö\nnö newline".as_bytes());
+        v.extend_from_slice(b"windows\r\nold mac\rmixed\r\r\n\n\r");
+        v
+    }
+
+    #[test]
+    fn backends_agree_with_scalar() {
+        let data = sample();
+        for backend in CountingBackend::available() {
+            for end in [
+                0,
+                1,
+                15,
+                16,
+                17,
+                31,
+                32,
+                63,
+                64,
+                65,
+                100,
+                511,
+                512,
+                513,
+                data.len(),
+            ] {
+                // Odd starts exercise the alignment-peeling prologue.
+                for start in [0, 1, 7] {
+                    let slice = &data[start.min(end)..end];
+                    assert_eq!(
+                        backend.count_lines(slice),
+                        CountingBackend::Scalar.count_lines(slice),
+                        "{backend:?} lines, start {start}, len {end}"
+                    );
+                    assert_eq!(
+                        backend.count_utf8_chars(slice),
+                        CountingBackend::Scalar.count_utf8_chars(slice),
+                        "{backend:?} chars, start {start}, len {end}"
+                    );
+                    assert_eq!(
+                        backend.count_crlf(slice),
+                        CountingBackend::Scalar.count_crlf(slice),
+                        "{backend:?} crlf, start {start}, len {end}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn resolved_kernels_match_the_detected_backend() {
+        let data = sample();
+        let kernels = resolved_kernels();
+        let backend = CountingBackend::detect_cached();
+        assert_eq!(kernels.count_lines(&data), backend.count_lines(&data));
+        assert_eq!(kernels.count_crlf(&data), backend.count_crlf(&data));
+        assert_eq!(
+            kernels.count_utf8_chars(&data),
+            backend.count_utf8_chars(&data)
+        );
+    }
+
+    /// A registered kernel that simply delegates to the scalar reference.
+    struct Delegating {
+        available: bool,
+    }
+
+    impl CountKernel for Delegating {
+        fn name(&self) -> &'static str {
+            "delegating"
+        }
+
+        fn is_available(&self) -> bool {
+            self.available
+        }
+
+        fn count_lines(&self, data: &[u8]) -> u64 {
+            super::scalar::count_lines(data)
+        }
+
+        fn count_crlf(&self, data: &[u8]) -> u64 {
+            super::scalar::count_crlf(data)
+        }
+
+        fn count_non_continuation(&self, data: &[u8]) -> u64 {
+            super::scalar::count_non_continuation(data)
+        }
+    }
+
+    #[test]
+    fn registered_kernels_join_the_backend_list() {
+        static KERNEL: Delegating = Delegating { available: true };
+        static HIDDEN: Delegating = Delegating { available: false };
+        let backend = register_kernel(&KERNEL).unwrap();
+        let hidden = register_kernel(&HIDDEN).unwrap();
+        assert_ne!(backend, hidden);
+        let available = CountingBackend::available();
+        assert!(available.contains(&backend));
+        assert!(!available.contains(&hidden));
+        let CountingBackend::External(slot) = backend else {
+            panic!("registration returned a native backend");
+        };
+        assert_eq!(external_kernel_name(slot), Some("delegating"));
+        // The slot's trampoline reaches the registered implementation.
+        let data = sample();
+        assert_eq!(
+            backend.count_lines(&data),
+            CountingBackend::Scalar.count_lines(&data)
+        );
+        assert_eq!(
+            backend.count_crlf(&data),
+            CountingBackend::Scalar.count_crlf(&data)
+        );
+        assert_eq!(
+            backend.count_utf8_chars(&data),
+            CountingBackend::Scalar.count_utf8_chars(&data)
+        );
+    }
+
+    #[test]
+    fn bench_fastest_picks_an_available_backend() {
+        assert!(CountingBackend::available().contains(&bench_fastest()));
+    }
+
+    #[test]
+    fn cached_detection_matches_detect() {
+        assert_eq!(CountingBackend::detect_cached(), CountingBackend::detect());
+        assert_eq!(
+            CountingBackend::detect_cached(),
+            CountingBackend::detect_cached()
+        );
+    }
+
+    #[test]
+    fn detect_returns_available_backend() {
+        assert!(CountingBackend::available().contains(&CountingBackend::detect()));
+    }
+}
@@ -0,0 +1,51 @@
+//! Unicode property tables generated at build time.
+//!
+//! The scalar scan classifies every character, so its property checks
+//! come from compact tables `build.rs` writes into `OUT_DIR` rather than
+//! from std: ASCII resolves through a branchless bitmask, the non-ASCII
+//! remainder through a binary search over a handful of ranges, and the
+//! Unicode version the data was transcribed from is pinned in one place
+//! ([`UNICODE_VERSION`]) instead of floating with the toolchain.
+
+include!(concat!(env!("OUT_DIR"), "/unicode_tables.rs"));
+
+/// The `White_Space` property: what separates words in the scan, matching
+/// GNU `wc`'s use of `iswspace`.
+#[inline]
+pub fn is_white_space(c: char) -> bool {
+    let c = c as u32;
+    if c < 0x80 {
+        return WHITE_SPACE_ASCII >> c & 1 != 0;
+    }
+    WHITE_SPACE_RANGES
+        .binary_search_by(|&(lo, hi)| {
+            if c < lo {
+                std::cmp::Ordering::Greater
+            } else if c > hi {
+                std::cmp::Ordering::Less
+            } else {
+                std::cmp::Ordering::Equal
+            }
+        })
+        .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn white_space_table_matches_the_std_property() {
+        // std's is_whitespace is the same UCD property, so the generated
+        // tables must agree on every scalar value.
+        for c in char::MIN..=char::MAX {
+            assert_eq!(is_white_space(c), c.is_whitespace(), "U+{:04X}", c as u32);
+        }
+    }
+
+    #[test]
+    fn unicode_version_is_pinned() {
+        let (major, _, _) = UNICODE_VERSION;
+        assert!(major >= 17);
+    }
+}
@@ -0,0 +1,1839 @@
+//! End-to-end CLI tests: argument handling, output formatting, `--total`,
+//! `--files0-from`, stdin, exit codes, and broken-pipe behavior.
+
+use std::fs;
+use std::io::Write;
+use std::process::{Command as StdCommand, Stdio};
+
+use assert_cmd::Command;
+use predicates::prelude::*;
+use tempfile::TempDir;
+
+/// A command with a pinned locale so counts do not depend on the host.
+fn wc_rs() -> Command {
+    let mut cmd = Command::cargo_bin("wc-rs").unwrap();
+    cmd.env("LC_ALL", "C.UTF-8");
+    cmd
+}
+
+fn write_file(dir: &TempDir, name: &str, contents: &[u8]) -> std::path::PathBuf {
+    let path = dir.path().join(name);
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn stdin_default_counts() {
+    wc_rs()
+        .write_stdin("one two\nthree\n")
+        .assert()
+        .success()
+        .stdout("      2       3      14\n");
+}
+
+#[test]
+fn dash_operand_reads_stdin() {
+    wc_rs()
+        .arg("-")
+        .write_stdin("a b\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("-"));
+}
+
+#[test]
+fn single_file_prints_name() {
+    let dir = TempDir::new().unwrap();
+    let path = write_file(&dir, "a.txt", b"hello world\n");
+    wc_rs()
+        .arg(&path)
+        .assert()
+        .success()
+        .stdout(format!(" 1  2 12 {}\n", path.display()));
+}
+
+#[test]
+fn single_count_single_file_prints_bare_number() {
+    let dir = TempDir::new().unwrap();
+    let path = write_file(&dir, "a.txt", b"x\ny\n");
+    wc_rs()
+        .args(["-l"])
+        .arg(&path)
+        .assert()
+        .success()
+        .stdout(format!("2 {}\n", path.display()));
+}
+
+#[test]
+fn multiple_files_print_total() {
+    let dir = TempDir::new().unwrap();
+    let a = write_file(&dir, "a.txt", b"a\n");
+    let b = write_file(&dir, "b.txt", b"b b\n");
+    wc_rs()
+        .args([&a, &b])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("total\n"));
+}
+
+#[test]
+fn total_never_suppresses_the_total_row() {
+    let dir = TempDir::new().unwrap();
+    let a = write_file(&dir, "a.txt", b"a\n");
+    let b = write_file(&dir, "b.txt", b"b\n");
+    wc_rs()
+        .arg("--total=never")
+        .args([&a, &b])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("total").not());
+}
+
+#[test]
+fn total_only_prints_just_the_total() {
+    let dir = TempDir::new().unwrap();
+    let a = write_file(&dir, "a.txt", b"one\n");
+    let b = write_file(&dir, "b.txt", b"two three\n");
+    wc_rs()
+        .arg("--total=only")
+        .args([&a, &b])
+        .assert()
+        .success()
+        .stdout(" 2  3 14 total\n");
+}
+
+#[test]
+fn total_always_prints_total_for_one_file() {
+    let dir = TempDir::new().unwrap();
+    let a = write_file(&dir, "a.txt", b"one\n");
+    wc_rs()
+        .arg("--total=always")
+        .arg(&a)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("total\n"));
+}
+
+#[test]
+fn counter_flags_select_output_columns() {
+    let dir = TempDir::new().unwrap();
+    let path = write_file(&dir, "a.txt", b"ab cd\nefgh\n");
+    wc_rs()
+        .args(["-L", "-m"])
+        .arg(&path)
+        .assert()
+        .success()
+        .stdout(format!("11  5 {}\n", path.display()));
+}
+
+#[test]
+fn files0_from_reads_nul_separated_list() {
+    let dir = TempDir::new().unwrap();
+    let a = write_file(&dir, "a.txt", b"a\n");
+    let b = write_file(&dir, "b.txt", b"b b\n");
+    let mut list = Vec::new();
+    write!(list, "{}\0{}\0", a.display(), b.display()).unwrap();
+    let list_path = write_file(&dir, "list", &list);
+    wc_rs()
+        .arg(format!("--files0-from={}", list_path.display()))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("a.txt"))
+        .stdout(predicate::str::contains("b.txt"))
+        .stdout(predicate::str::contains("total"));
+}
+
+#[test]
+fn files0_from_stdin() {
+    let dir = TempDir::new().unwrap();
+    let a = write_file(&dir, "a.txt", b"one two\n");
+    wc_rs()
+        .arg("--files0-from=-")
+        .write_stdin(format!("{}\0", a.display()))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("a.txt"));
+}
+
+#[test]
+fn files0_from_stdin_streams_with_minimal_width() {
+    // When the list arrives on stdin its extent is unknown, so rows use the
+    // minimal column width instead of the size-derived one, like GNU wc.
+    let dir = TempDir::new().unwrap();
+    let a = write_file(&dir, "a.txt", b"one two\n");
+    wc_rs()
+        .arg("--files0-from=-")
+        .write_stdin(format!("{}\0{}\0", a.display(), a.display()))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!("1 2 8 {}\n", a.display())))
+        .stdout(predicate::str::contains("2 4 16 total"));
+}
+
+#[test]
+fn files0_from_rejects_file_operands() {
+    wc_rs()
+        .args(["--files0-from=whatever", "extra"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--files0-from"));
+}
+
+#[test]
+fn files0_from_rejects_zero_length_names() {
+    let dir = TempDir::new().unwrap();
+    let list_path = write_file(&dir, "list", b"\0\0");
+    wc_rs()
+        .arg(format!("--files0-from={}", list_path.display()))
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            ":1: invalid zero-length file name",
+        ))
+        .stderr(predicate::str::contains(
+            ":2: invalid zero-length file name",
+        ));
+}
+
+#[test]
+fn files0_from_skips_zero_length_names_and_counts_the_rest() {
+    // GNU diagnoses the bad entry with its 1-based position but still
+    // counts every valid entry, exiting 1.
+    let dir = TempDir::new().unwrap();
+    let a = write_file(&dir, "a.txt", b"a\n");
+    let mut list = Vec::new();
+    write!(list, "{}\0\0{}\0", a.display(), a.display()).unwrap();
+    let list_path = write_file(&dir, "list", &list);
+    wc_rs()
+        .arg(format!("--files0-from={}", list_path.display()))
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            ":2: invalid zero-length file name",
+        ))
+        .stdout(predicate::str::contains("total"));
+}
+
+#[test]
+fn files0_from_stdin_rejects_dash_entries() {
+    let dir = TempDir::new().unwrap();
+    let a = write_file(&dir, "a.txt", b"a\n");
+    wc_rs()
+        .arg("--files0-from=-")
+        .write_stdin(format!("{}\0-\0", a.display()))
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "when reading file names from stdin, no file name of '-' allowed",
+        ))
+        .stdout(predicate::str::contains("a.txt"));
+}
+
+#[test]
+fn files0_from_file_allows_dash_entries() {
+    let dir = TempDir::new().unwrap();
+    let list_path = write_file(&dir, "list", b"-\0");
+    wc_rs()
+        .arg(format!("--files0-from={}", list_path.display()))
+        .write_stdin("one two\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(" - ").or(predicate::str::ends_with("-\n")));
+}
+
+// Golden outputs below were captured verbatim from GNU wc (coreutils 9.1):
+// column width comes from the decimal width of the summed byte sizes, so the
+// total row lines up with every file row.
+
+#[test]
+fn column_width_follows_summed_file_sizes() {
+    let dir = TempDir::new().unwrap();
+    let a = write_file(&dir, "a.txt", &[b'x'; 150]);
+    let b = write_file(&dir, "b.txt", b"hello\n");
+    wc_rs().args([&a, &b]).assert().success().stdout(format!(
+        "  0   1 150 {}\n  1   1   6 {}\n  1   2 156 total\n",
+        a.display(),
+        b.display()
+    ));
+}
+
+#[test]
+fn redirected_regular_stdin_is_sized_for_width() {
+    // GNU fstats stdin, so `wc file - < other-file` uses real sizes for the
+    // width instead of falling back to the 7-column pipe default.
+    let dir = TempDir::new().unwrap();
+    let a = write_file(&dir, "a.txt", b"one two\n");
+    let b = write_file(&dir, "b.txt", b"three\n");
+    let output = StdCommand::new(assert_cmd::cargo::cargo_bin("wc-rs"))
+        .arg(&a)
+        .arg("-")
+        .env("LC_ALL", "C.UTF-8")
+        .stdin(Stdio::from(fs::File::open(&b).unwrap()))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!(" 1  2  8 {}\n 1  1  6 -\n 2  3 14 total\n", a.display())
+    );
+}
+
+#[test]
+fn human_readable_scales_counts_and_totals() {
+    // 1536 'a b\n' records: 384 lines, 768 words, 1536 bytes under 1024-
+    // based scaling, rounded up like du -h.
+    let dir = TempDir::new().unwrap();
+    let a = write_file(&dir, "a.txt", &b"a b\n".repeat(384));
+    wc_rs()
+        .arg("--human-readable")
+        .args([&a, &a])
+        .assert()
+        .success()
+        .stdout(format!(
+            " 384  768 1.5K {a}\n 384  768 1.5K {a}\n 768 1.5K 3.0K total\n",
+            a = a.display()
+        ));
+}
+
+#[test]
+fn si_scales_by_powers_of_1000() {
+    let dir = TempDir::new().unwrap();
+    let a = write_file(&dir, "a.txt", &[b'x'; 1536]);
+    wc_rs()
+        .args(["-c", "--si"])
+        .arg(&a)
+        .assert()
+        .success()
+        .stdout(format!("1.6k {}\n", a.display()));
+}
+
+#[test]
+fn human_readable_conflicts_with_si() {
+    wc_rs()
+        .args(["--human-readable", "--si"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+fn color_always_styles_names_total_and_errors() {
+    let dir = TempDir::new().unwrap();
+    let a = write_file(&dir, "a.txt", b"a\n");
+    wc_rs()
+        .arg("--color=always")
+        .args([&a, &dir.path().join("missing")])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("\u{1b}[36m")) // cyan file name
+        .stdout(predicate::str::contains("\u{1b}[1mtotal\u{1b}[0m"))
+        .stderr(predicate::str::contains("\u{1b}[31m")); // red diagnostic
+}
+
+#[test]
+fn color_auto_stays_plain_when_piped() {
+    let dir = TempDir::new().unwrap();
+    let a = write_file(&dir, "a.txt", b"a\n");
+    wc_rs()
+        .arg(&a)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\u{1b}").not());
+}
+
+#[test]
+fn ndjson_emits_one_object_per_file_and_a_total() {
+    let dir = TempDir::new().unwrap();
+    let a = write_file(&dir, "a.txt", b"one two\n");
+    let b = write_file(&dir, "b.txt", b"three\n");
+    wc_rs()
+        .arg("--output=ndjson")
+        .args([&a, &b])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "{{\"file\":\"{}\",\"lines\":1,\"words\":2,\"bytes\":8}}\n",
+            a.display()
+        )))
+        .stdout(predicate::str::contains(
+            "{\"total\":true,\"lines\":2,\"words\":3,\"bytes\":14}\n",
+        ));
+}
+
+#[test]
+fn ndjson_selected_counters_only() {
+    wc_rs()
+        .args(["--output=ndjson", "-l"])
+        .write_stdin("x\ny\n")
+        .assert()
+        .success()
+        .stdout("{\"file\":\"-\",\"lines\":2}\n");
+}
+
+#[test]
+fn openmetrics_exposes_gauges_per_selected_counter() {
+    let dir = TempDir::new().unwrap();
+    let a = write_file(&dir, "a.txt", b"one two\n");
+    wc_rs()
+        .args(["--output=openmetrics", "-l", "-w"])
+        .arg(&a)
+        .assert()
+        .success()
+        .stdout(format!(
+            "# HELP wc_lines Newline count.\n\
+             # TYPE wc_lines gauge\n\
+             wc_lines{{file=\"{a}\"}} 1\n\
+             # HELP wc_words Word count.\n\
+             # TYPE wc_words gauge\n\
+             wc_words{{file=\"{a}\"}} 2\n\
+             # EOF\n",
+            a = a.display()
+        ));
+}
+
+#[test]
+fn repeated_dash_operands_consume_stdin_once() {
+    // The first `-` reads stdin to EOF; later ones see an empty stream,
+    // matching GNU.
+    wc_rs()
+        .args(["-", "-"])
+        .write_stdin("a b\nc\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("2       3       6 -\n"))
+        .stdout(predicate::str::contains("0       0       0 -\n"))
+        .stdout(predicate::str::contains("2       3       6 total\n"));
+}
+
+#[test]
+fn missing_file_fails_but_others_are_counted() {
+    let dir = TempDir::new().unwrap();
+    let a = write_file(&dir, "a.txt", b"still counted\n");
+    wc_rs()
+        .arg(dir.path().join("no-such-file"))
+        .arg(&a)
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("a.txt"))
+        .stderr(predicate::str::contains("no-such-file"));
+}
+
+#[test]
+fn unknown_flag_is_a_usage_error() {
+    wc_rs().arg("--definitely-not-a-flag").assert().code(2);
+}
+
+#[test]
+fn parallel_modes_agree() {
+    let dir = TempDir::new().unwrap();
+    let path = write_file(&dir, "big.txt", "word ".repeat(50_000).as_bytes());
+    let chunks = wc_rs()
+        .args(["--parallel-mode=chunks"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    let files = wc_rs()
+        .args(["--parallel-mode=files"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert_eq!(chunks.stdout, files.stdout);
+}
+
+#[test]
+fn broken_pipe_exits_cleanly() {
+    // Enough rows to overflow the pipe buffer after the reader goes away.
+    let dir = TempDir::new().unwrap();
+    let a = write_file(&dir, "a.txt", b"x\n");
+    let mut list = Vec::new();
+    for _ in 0..8192 {
+        write!(list, "{}\0", a.display()).unwrap();
+    }
+    let list_path = write_file(&dir, "list", &list);
+    let mut child = StdCommand::new(assert_cmd::cargo::cargo_bin("wc-rs"))
+        .arg(format!("--files0-from={}", list_path.display()))
+        .env("LC_ALL", "C.UTF-8")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    drop(child.stdout.take());
+    let status = child.wait().unwrap();
+    assert!(status.success(), "expected clean exit, got {status:?}");
+}
+
+#[cfg(unix)]
+#[test]
+fn non_utf8_file_names_are_printed_byte_for_byte() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join(OsStr::from_bytes(b"caf\xe9.txt"));
+    fs::write(&path, b"one two\n").unwrap();
+    let output = wc_rs().arg("-w").arg(&path).output().unwrap();
+    assert!(output.status.success());
+    let tail = output
+        .stdout
+        .strip_suffix(b"\n")
+        .and_then(|s| s.strip_suffix(b"caf\xe9.txt"))
+        .unwrap_or_else(|| panic!("stdout {:?} does not end in the raw name", output.stdout));
+    assert!(tail.ends_with(b"/"), "unexpected prefix {tail:?}");
+}
+
+#[test]
+fn quoting_styles_escape_awkward_names() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("a b.txt");
+    fs::write(&path, b"x\n").unwrap();
+    wc_rs()
+        .args(["-l", "--quoting-style=shell-escape"])
+        .arg(&path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "'{}/a b.txt'",
+            dir.path().display()
+        )));
+    wc_rs()
+        .args(["-l", "--quoting-style=c"])
+        .arg(&path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "\"{}/a b.txt\"",
+            dir.path().display()
+        )));
+}
+
+#[test]
+fn encoding_transcodes_before_counting() {
+    let dir = TempDir::new().unwrap();
+    // "日本語 テスト\n" in Shift_JIS: 8 characters, 2 words, 14 raw bytes.
+    let path = write_file(
+        &dir,
+        "sjis.txt",
+        b"\x93\xfa\x96\x7b\x8c\xea \x83\x65\x83\x58\x83\x67\x0a",
+    );
+    let output = wc_rs()
+        .args(["-lwmc", "--encoding=shift_jis"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let fields: Vec<&str> = stdout.split_whitespace().take(4).collect();
+    assert_eq!(fields, ["1", "2", "8", "14"], "output {stdout:?}");
+}
+
+#[test]
+fn unknown_encoding_is_rejected() {
+    wc_rs()
+        .args(["--encoding=klingon"])
+        .write_stdin("x\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("unknown encoding 'klingon'"));
+}
+
+#[test]
+fn east_asian_locales_transcode_automatically() {
+    // "日本語 です\n" in EUC-JP: 7 characters, 2 words, 12 raw bytes.
+    let output = Command::cargo_bin("wc-rs")
+        .unwrap()
+        .env("LC_ALL", "ja_JP.eucJP")
+        .args(["-lwmc"])
+        .write_stdin(&b"\xc6\xfc\xcb\xdc\xb8\xec \xa4\xc7\xa4\xb9\x0a"[..])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let fields: Vec<&str> = stdout.split_whitespace().collect();
+    assert_eq!(fields, ["1", "2", "7", "12"], "output {stdout:?}");
+}
+
+#[test]
+fn encoding_auto_sniffs_utf16_and_reports_under_debug() {
+    // "hi\n" in UTF-16LE with a BOM: the BOM is consumed by decoding, so
+    // 3 characters remain; bytes stay the raw 8.
+    wc_rs()
+        .args(["-mc", "--encoding=auto", "--debug"])
+        .write_stdin(&b"\xff\xfeh\x00i\x00\n\x00"[..])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("3"))
+        .stdout(predicate::str::contains("8"))
+        .stderr(predicate::str::contains("detected encoding UTF-16LE"));
+}
+
+#[test]
+fn normalize_nfc_merges_combining_sequences() {
+    // "e" + U+0301 + newline: 3 code points raw, 2 after composition;
+    // bytes always report the raw input.
+    wc_rs()
+        .args(["-mc", "--normalize=nfc"])
+        .write_stdin("e\u{301}\n")
+        .assert()
+        .success()
+        .stdout("      2       4\n");
+    wc_rs()
+        .args(["-m", "--normalize=nfd"])
+        .write_stdin("\u{e9}\n")
+        .assert()
+        .success()
+        .stdout("3\n");
+}
+
+#[test]
+fn range_counts_only_the_requested_slice() {
+    let dir = TempDir::new().unwrap();
+    let path = write_file(&dir, "a.txt", b"aaaa\nbbbb\n");
+    // Bytes 5..10 are the second line; regular files seek to it.
+    let output = wc_rs()
+        .args(["-lc", "--range=5:10"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let fields: Vec<&str> = stdout.split_whitespace().take(2).collect();
+    assert_eq!(fields, ["1", "5"], "output {stdout:?}");
+    // Streams reach the range with skip-reads and must agree.
+    wc_rs()
+        .args(["-c", "--range=5:"])
+        .write_stdin(&b"aaaa\nbbbb\n"[..])
+        .assert()
+        .success()
+        .stdout("5\n");
+}
+
+#[test]
+fn backwards_range_is_rejected() {
+    wc_rs()
+        .args(["--range=9:5"])
+        .write_stdin("x")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("end offset precedes start"));
+}
+
+#[test]
+fn max_lines_caps_input_and_flags_the_row() {
+    let dir = TempDir::new().unwrap();
+    let path = write_file(&dir, "a.txt", b"one\ntwo\nthree\n");
+    let output = wc_rs()
+        .args(["-lc", "--max-lines=2"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let fields: Vec<&str> = stdout.split_whitespace().take(2).collect();
+    assert_eq!(fields, ["2", "8"], "output {stdout:?}");
+    assert!(
+        stdout.trim_end().ends_with("(truncated)"),
+        "output {stdout:?}"
+    );
+}
+
+#[test]
+fn max_bytes_cap_matching_the_input_is_not_truncation() {
+    wc_rs()
+        .args(["-c", "--max-bytes=5"])
+        .write_stdin("12345")
+        .assert()
+        .success()
+        .stdout("5\n");
+}
+
+#[test]
+fn ndjson_reports_truncation() {
+    let dir = TempDir::new().unwrap();
+    let path = write_file(&dir, "a.txt", b"one\ntwo\nthree\n");
+    let output = wc_rs()
+        .args(["--output=ndjson", "--max-bytes=4"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("\"truncated\":true"), "output {stdout:?}");
+}
+
+#[test]
+fn checkpoint_resumes_from_a_saved_offset() {
+    let dir = TempDir::new().unwrap();
+    let path = write_file(&dir, "big.txt", b"aaa bbb\nccc ddd\n");
+    let ckpt = dir.path().join("state.ckpt");
+    // A checkpoint written after the first line: the resumed run must only
+    // add the second line's counts on top of the recorded partial counts.
+    std::fs::write(
+        &ckpt,
+        "wc-rs-checkpoint 1\noffset 8\nlines 1\nwords 2\nchars 8\nbytes 8\n\
+         max_line_length 7\ncols 0\nin_word 0\npending \ncarry \n",
+    )
+    .unwrap();
+    let output = wc_rs()
+        .arg("--checkpoint")
+        .arg(&ckpt)
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let fields: Vec<&str> = stdout.split_whitespace().take(3).collect();
+    assert_eq!(fields, ["2", "4", "16"], "output {stdout:?}");
+    // A completed count leaves nothing to resume.
+    assert!(!ckpt.exists());
+}
+
+#[test]
+fn checkpoint_requires_a_single_file_operand() {
+    wc_rs()
+        .args(["--checkpoint", "state.ckpt", "a", "b"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("exactly one file operand"));
+}
+
+#[test]
+fn checkpoint_rejects_stream_reshaping_options() {
+    wc_rs()
+        .args(["--checkpoint", "state.ckpt", "--range=0:4", "a"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--checkpoint cannot be combined"));
+}
+
+#[test]
+fn verify_counts_normally_on_a_healthy_cpu() {
+    let dir = TempDir::new().unwrap();
+    let path = write_file(&dir, "a.txt", b"hello world\n");
+    let output = wc_rs().arg("--verify").arg(&path).output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let fields: Vec<&str> = stdout.split_whitespace().take(3).collect();
+    assert_eq!(fields, ["1", "2", "12"], "output {stdout:?}");
+    // The streaming path double-counts too.
+    wc_rs()
+        .arg("--verify")
+        .write_stdin("hello world\n")
+        .assert()
+        .success();
+}
+
+#[test]
+fn self_test_reports_each_backend() {
+    wc_rs()
+        .arg("self-test")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Scalar: ok"));
+}
+
+#[test]
+fn environment_variables_provide_runtime_defaults() {
+    let dir = TempDir::new().unwrap();
+    let a = write_file(&dir, "a.txt", b"one\n");
+    let b = write_file(&dir, "b.txt", b"two three\n");
+    // WC_RS_TOTAL takes effect when no flag is given...
+    let output = wc_rs()
+        .env("WC_RS_TOTAL", "only")
+        .args([&a, &b])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.lines().count(), 1, "output {stdout:?}");
+    // ...and an explicit flag overrides it.
+    let output = wc_rs()
+        .env("WC_RS_TOTAL", "only")
+        .arg("--total=never")
+        .args([&a, &b])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.lines().count(), 2, "output {stdout:?}");
+
+    wc_rs()
+        .env("WC_RS_OUTPUT", "ndjson")
+        .write_stdin("x\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("{"));
+}
+
+#[test]
+fn posix_mode_prints_bare_space_separated_fields() {
+    let dir = TempDir::new().unwrap();
+    let path = write_file(&dir, "a.txt", b"one two\nthree\n");
+    let output = wc_rs().arg("--posix").arg(&path).output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("2 3 14 "), "output {stdout:?}");
+    // Stdin drops the usual seven-column padding too.
+    wc_rs()
+        .arg("--posix")
+        .write_stdin("a b\n")
+        .assert()
+        .success()
+        .stdout("1 2 4\n");
+}
+
+#[test]
+fn posix_mode_rejects_extensions() {
+    wc_rs()
+        .args(["--posix", "-L"])
+        .write_stdin("x")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("not part of POSIX wc"));
+    wc_rs()
+        .args(["--posix", "-cm"])
+        .write_stdin("x")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("mutually exclusive"));
+    // POSIXLY_CORRECT implies the same checks without the flag.
+    wc_rs()
+        .env("POSIXLY_CORRECT", "1")
+        .arg("--human-readable")
+        .write_stdin("x")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("not part of POSIX wc"));
+}
+
+#[cfg(unix)]
+#[test]
+fn wc_applet_ignores_extensions_that_were_not_asked_for() {
+    let dir = TempDir::new().unwrap();
+    let applet = dir.path().join("wc");
+    std::os::unix::fs::symlink(env!("CARGO_BIN_EXE_wc-rs"), &applet).unwrap();
+    let a = write_file(&dir, "a.txt", b"one\n");
+    let b = write_file(&dir, "b.txt", b"two\n");
+    // WC_RS_* defaults are for wc-rs invocations, not a wc install.
+    let output = Command::new(&applet)
+        .env("LC_ALL", "C.UTF-8")
+        .env("WC_RS_TOTAL", "only")
+        .args([&a, &b])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.lines().count(), 3, "output {stdout:?}");
+    // `wc self-test` names a file, not the wc-rs subcommand.
+    let output = Command::new(&applet)
+        .env("LC_ALL", "C.UTF-8")
+        .current_dir(dir.path())
+        .arg("self-test")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("self-test"), "stderr {stderr:?}");
+    // Explicit flags still unlock the extensions.
+    let output = Command::new(&applet)
+        .env("LC_ALL", "C.UTF-8")
+        .arg("--output=ndjson")
+        .arg(&a)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(output.stdout.starts_with(b"{"));
+}
+
+#[test]
+fn words_per_line_extremes_count_each_record() {
+    let dir = TempDir::new().unwrap();
+    let path = write_file(&dir, "records.txt", b"one two three\nfour\nfive six\n");
+    wc_rs()
+        .args(["--max-words-per-line", "--min-words-per-line"])
+        .arg(&path)
+        .assert()
+        .success()
+        .stdout(format!(" 3  1 {}\n", path.display()));
+}
+
+#[test]
+fn fields_reports_totals_and_consistency() {
+    let dir = TempDir::new().unwrap();
+    let tsv = write_file(&dir, "table.tsv", b"1\t2\n3\t4\n");
+    wc_rs()
+        .arg("--fields")
+        .arg(&tsv)
+        .assert()
+        .success()
+        .stdout(format!("4 2 consistent {}\n", tsv.display()));
+
+    let csv = write_file(&dir, "ragged.csv", b"a,b,c\nd,e\n");
+    wc_rs()
+        .arg("--fields=,")
+        .arg(&csv)
+        .assert()
+        .success()
+        .stdout(format!("5 2-3 ragged {}\n", csv.display()));
+}
+
+#[test]
+fn tab_size_changes_the_max_line_length() {
+    let dir = TempDir::new().unwrap();
+    let path = write_file(&dir, "tabs.txt", b"a\tb\n");
+    wc_rs()
+        .args(["-L"])
+        .arg(&path)
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("9"));
+    wc_rs()
+        .args(["-L", "--tab-size", "4"])
+        .arg(&path)
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("5"));
+}
+
+#[test]
+fn line_endings_reports_styles_and_flags_mixes() {
+    let dir = TempDir::new().unwrap();
+    let unix = write_file(&dir, "unix.txt", b"a\nb\n");
+    wc_rs()
+        .arg("--line-endings")
+        .arg(&unix)
+        .assert()
+        .success()
+        .stdout(format!("2 0 0 lf {}\n", unix.display()));
+
+    let mixed = write_file(&dir, "mixed.txt", b"a\r\nb\nc\rd");
+    wc_rs()
+        .arg("--line-endings")
+        .arg(&mixed)
+        .assert()
+        .success()
+        .stdout(format!("1 1 1 mixed {}\n", mixed.display()));
+}
+
+#[test]
+fn missing_final_newline_warns_and_shows_in_ndjson() {
+    let dir = TempDir::new().unwrap();
+    let path = write_file(&dir, "chopped.txt", b"one\ntwo");
+    wc_rs()
+        .arg("--warn-missing-newline")
+        .arg(&path)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("no newline at end of file"));
+    wc_rs()
+        .args(["--output", "ndjson"])
+        .arg(&path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"missing_final_newline\":true"));
+
+    let clean = write_file(&dir, "clean.txt", b"one\ntwo\n");
+    wc_rs()
+        .arg("--warn-missing-newline")
+        .arg(&clean)
+        .assert()
+        .success()
+        .stderr("");
+}
+
+#[cfg(unix)]
+#[test]
+fn partial_reports_the_readable_prefix_on_read_errors() {
+    let dir = TempDir::new().unwrap();
+    // Opening a directory succeeds on Unix; reading it fails, which stands
+    // in for a mid-file I/O error.
+    wc_rs()
+        .arg("--partial")
+        .arg(dir.path())
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("(partial)"))
+        .stderr(predicate::str::is_empty().not());
+    // Without the flag the erroring input produces no row at all.
+    wc_rs().arg(dir.path()).assert().failure().stdout("");
+}
+
+#[test]
+fn rusage_reports_wall_time_and_bytes_processed() {
+    let dir = TempDir::new().unwrap();
+    let path = write_file(&dir, "data.txt", b"hello world\n");
+    wc_rs()
+        .arg("--rusage")
+        .arg(&path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(path.to_str().unwrap()))
+        .stderr(
+            predicate::str::contains("wall ").and(predicate::str::contains("12 bytes processed")),
+        );
+    // The report also covers the dedicated report modes.
+    wc_rs()
+        .args(["--rusage", "--fields=,"])
+        .arg(&path)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("12 bytes processed"));
+}
+
+#[test]
+fn auto_bench_backend_counts_like_the_default() {
+    let dir = TempDir::new().unwrap();
+    let path = write_file(&dir, "bench.txt", b"one two\nthree\n");
+    wc_rs()
+        .args(["--backend", "auto-bench", "--debug"])
+        .arg(&path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(" 2  3 14 "))
+        .stderr(predicate::str::contains("auto-bench selected"));
+}
+
+#[test]
+fn bytes_only_stdin_pipe_counts_without_reading() {
+    // Exercises the Linux splice fast path; elsewhere it falls back to
+    // plain reads with the same answer.
+    let data = vec![b'x'; 300_000];
+    wc_rs()
+        .arg("-c")
+        .write_stdin(data)
+        .assert()
+        .success()
+        .stdout("300000\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn fd_operand_counts_an_inherited_descriptor() {
+    // fd://0 names the same pipe stdin does, via a duplicated descriptor.
+    wc_rs()
+        .arg("fd://0")
+        .write_stdin("one two\nthree\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("2").and(predicate::str::contains("fd://0")));
+}
+
+#[cfg(feature = "http")]
+#[test]
+fn url_operand_counts_the_response_body() {
+    use std::io::{Read as _, Write as _};
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut buf = [0u8; 1024];
+        let _ = stream.read(&mut buf);
+        let body = "one two\nthree\n";
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+    });
+    let url = format!("http://{addr}/artifact.txt");
+    wc_rs()
+        .arg(&url)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("14").and(predicate::str::contains(&url)));
+    server.join().unwrap();
+}
+
+#[test]
+fn group_by_dir_prints_subtree_totals() {
+    let dir = TempDir::new().unwrap();
+    fs::create_dir_all(dir.path().join("a/b")).unwrap();
+    fs::write(dir.path().join("a/one.txt"), "x\n").unwrap();
+    fs::write(dir.path().join("a/b/two.txt"), "y\ny\n").unwrap();
+    wc_rs()
+        .current_dir(dir.path())
+        .args(["-l", "--group-by-dir", "a/one.txt", "a/b/two.txt"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("3 a\n").and(predicate::str::contains("2 a/b\n")));
+    // A depth limit keeps only the shallow directories.
+    wc_rs()
+        .current_dir(dir.path())
+        .args(["-l", "--group-by-dir=1", "a/one.txt", "a/b/two.txt"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("a/b\n").not());
+}
+
+#[test]
+fn total_label_renames_the_summary_row() {
+    let dir = TempDir::new().unwrap();
+    let a = write_file(&dir, "a.txt", b"one\n");
+    let b = write_file(&dir, "b.txt", b"two\n");
+    wc_rs()
+        .args(["-l", "--total-label", "corpus"])
+        .arg(&a)
+        .arg(&b)
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::contains("2 corpus\n").and(predicate::str::contains("total").not()),
+        );
+}
+
+#[test]
+fn diagnostics_follow_the_message_locale() {
+    let dir = TempDir::new().unwrap();
+    let a = write_file(&dir, "a.txt", b"eins\n");
+    let b = write_file(&dir, "b.txt", b"zwei");
+    wc_rs()
+        .env("LC_ALL", "de_DE.UTF-8")
+        .args(["-l", "--warn-missing-newline"])
+        .arg(&a)
+        .arg(&b)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("insgesamt"))
+        .stderr(predicate::str::contains("keine neue Zeile"));
+}
+
+#[test]
+fn debug_reports_the_mapping_strategy() {
+    let dir = TempDir::new().unwrap();
+    let path = write_file(&dir, "a.txt", b"hello\n");
+    wc_rs()
+        .arg("--debug")
+        .arg(&path)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("counting via mmap"));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn zero_size_virtual_files_are_streamed() {
+    // /proc files report size zero; the stat shortcut must not trust it.
+    wc_rs()
+        .args(["-c", "/proc/self/status"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("0 /proc").not());
+}
+
+#[test]
+fn unordered_streams_rows_in_any_order_with_a_correct_total() {
+    let dir = TempDir::new().unwrap();
+    let a = write_file(&dir, "a.txt", b"one two\n");
+    let b = write_file(&dir, "b.txt", b"three\n");
+    let output = wc_rs()
+        .args(["--unordered", "-w"])
+        .arg(&a)
+        .arg(&b)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut lines: Vec<&str> = stdout.lines().collect();
+    // The totals row is always last; the file rows may arrive either way.
+    assert!(lines.pop().unwrap().trim_start().starts_with("3 total"));
+    lines.sort_unstable();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].trim_start().starts_with('1') && lines[0].ends_with("b.txt"));
+    assert!(lines[1].trim_start().starts_with('2') && lines[1].ends_with("a.txt"));
+}
+
+#[test]
+fn unique_words_reports_distinct_tokens_per_file() {
+    let dir = TempDir::new().unwrap();
+    let file = write_file(&dir, "words.txt", b"the cat the dog The\n");
+    wc_rs()
+        .args(["--unique-words"])
+        .arg(&file)
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("4 "));
+    wc_rs()
+        .args(["--unique-words", "--fold-case"])
+        .arg(&file)
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("3 "));
+}
+
+#[test]
+fn avg_word_length_renders_with_the_requested_precision() {
+    let dir = TempDir::new().unwrap();
+    let file = write_file(&dir, "avg.txt", b"one two sixxxx\n");
+    wc_rs()
+        .args(["--avg-word-length"])
+        .arg(&file)
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("4.00 "));
+    wc_rs()
+        .args(["--avg-word-length", "--precision", "1"])
+        .arg(&file)
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("4.0 "));
+}
+
+#[test]
+fn char_classes_reports_one_bucket_per_character() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let file = dir.path().join("mixed.txt");
+    std::fs::write(&file, "ab1, x\n").unwrap();
+    let output = wc_rs().arg("--char-classes").arg(&file).output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    // letters digits punctuation whitespace control other
+    assert!(stdout.starts_with("3 1 1 2 0 0 "), "got {stdout:?}");
+}
+
+#[test]
+fn entropy_reports_bits_per_byte_with_the_requested_precision() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let file = dir.path().join("even.bin");
+    // Two equally likely byte values: exactly one bit per byte.
+    std::fs::write(&file, b"abababab").unwrap();
+    let output = wc_rs().arg("--entropy").arg(&file).output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("1.00 "), "got {stdout:?}");
+
+    let output = wc_rs()
+        .args(["--entropy", "--precision", "3"])
+        .arg(&file)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("1.000 "), "got {stdout:?}");
+}
+
+#[test]
+fn count_cr_lines_adds_classic_mac_terminators() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let file = dir.path().join("mac.txt");
+    // One lone CR, one CRLF, one LF.
+    std::fs::write(&file, "one\rtwo\r\nthree\n").unwrap();
+    let output = wc_rs().arg("-l").arg(&file).output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("2 "), "got {stdout:?}");
+
+    let output = wc_rs()
+        .args(["-l", "--count-cr-lines"])
+        .arg(&file)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("3 "), "got {stdout:?}");
+}
+
+#[test]
+fn window_emits_counts_per_block_of_lines() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let file = dir.path().join("doc.txt");
+    // Five lines: windows of two lines, plus a short final window.
+    std::fs::write(&file, "a b\nc\nd e f\ng\nh\n").unwrap();
+    let output = wc_rs()
+        .args(["-l", "-w", "--window", "2"])
+        .arg(&file)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 3, "got {stdout:?}");
+    let row = |i: usize| -> Vec<&str> { lines[i].split_whitespace().collect() };
+    assert_eq!(row(0)[..2], ["2", "3"], "got {stdout:?}");
+    assert!(lines[0].ends_with(":1"), "got {stdout:?}");
+    assert_eq!(row(1)[..2], ["2", "4"], "got {stdout:?}");
+    assert!(lines[1].ends_with(":2"), "got {stdout:?}");
+    assert_eq!(row(2)[..2], ["1", "1"], "got {stdout:?}");
+    assert!(lines[2].ends_with(":3"), "got {stdout:?}");
+}
+
+#[test]
+fn byte_windows_split_mid_line_and_total_sums_windows() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let file = dir.path().join("doc.txt");
+    std::fs::write(&file, "abcd efgh\n").unwrap();
+    let output = wc_rs()
+        .args(["-c", "--window", "4b", "--total", "always"])
+        .arg(&file)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 4, "got {stdout:?}");
+    let first = |i: usize| lines[i].split_whitespace().next().unwrap();
+    assert_eq!(first(0), "4", "got {stdout:?}");
+    assert_eq!(first(2), "2", "got {stdout:?}");
+    assert_eq!(first(3), "10", "got {stdout:?}");
+    assert!(lines[3].ends_with("total"), "got {stdout:?}");
+}
+
+/// "hello world\n" compressed with `gzip -n`.
+const HELLO_GZ: &[u8] = &[
+    0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57,
+    0x28, 0xcf, 0x2f, 0xca, 0x49, 0xe1, 0x02, 0x00, 0x2d, 0x3b, 0x08, 0xaf, 0x0c, 0x00, 0x00, 0x00,
+];
+
+#[test]
+fn decompress_auto_counts_gzipped_stdin() {
+    let output = wc_rs()
+        .args(["--decompress", "auto"])
+        .write_stdin(HELLO_GZ)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let fields: Vec<&str> = stdout.split_whitespace().collect();
+    // 1 line, 2 words, 12 decompressed bytes.
+    assert_eq!(fields[..3], ["1", "2", "12"], "got {stdout:?}");
+}
+
+#[test]
+fn decompress_auto_passes_plain_text_through() {
+    let output = wc_rs()
+        .args(["--decompress", "auto"])
+        .write_stdin("plain text\n")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let fields: Vec<&str> = stdout.split_whitespace().collect();
+    assert_eq!(fields[..3], ["1", "2", "11"], "got {stdout:?}");
+}
+
+#[test]
+fn decompress_gzip_rejects_corrupt_input() {
+    let output = wc_rs()
+        .args(["--decompress", "gzip"])
+        .write_stdin("this is not gzip data\n")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn output_file_replaces_the_report_atomically() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let report = dir.path().join("report.txt");
+    std::fs::write(&report, "stale\n").unwrap();
+    let output = wc_rs()
+        .args(["-o"])
+        .arg(&report)
+        .write_stdin("one two\n")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty(), "stdout still got the report");
+    let written = std::fs::read_to_string(&report).unwrap();
+    let fields: Vec<&str> = written.split_whitespace().collect();
+    assert_eq!(fields, ["1", "2", "8"], "got {written:?}");
+    // The staging file was renamed away, not left behind.
+    let leftovers: Vec<_> = std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|e| e.unwrap().file_name())
+        .collect();
+    assert_eq!(leftovers, ["report.txt"], "got {leftovers:?}");
+}
+
+#[test]
+fn output_file_append_accumulates_runs() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let report = dir.path().join("report.txt");
+    for _ in 0..2 {
+        wc_rs()
+            .args(["-l", "--output-file"])
+            .arg(&report)
+            .arg("--append")
+            .write_stdin("line\n")
+            .assert()
+            .success();
+    }
+    let written = std::fs::read_to_string(&report).unwrap();
+    assert_eq!(written.lines().count(), 2, "got {written:?}");
+}
+
+#[test]
+fn append_requires_output_file() {
+    wc_rs()
+        .arg("--append")
+        .write_stdin("x\n")
+        .assert()
+        .failure();
+}
+
+#[test]
+fn assert_max_lines_fails_oversized_input() {
+    let output = wc_rs()
+        .args(["--assert-max-lines", "2"])
+        .write_stdin("a\nb\nc\n")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    // Counts still print so the gate's log shows the numbers.
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        stdout.split_whitespace().next(),
+        Some("3"),
+        "got {stdout:?}"
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("--assert-max-lines 2"), "got {stderr:?}");
+}
+
+#[test]
+fn assert_total_limits_gate_the_sum_not_each_file() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let a = dir.path().join("a.txt");
+    let b = dir.path().join("b.txt");
+    std::fs::write(&a, "one\ntwo\n").unwrap();
+    std::fs::write(&b, "three\n").unwrap();
+    // Each file passes a per-file limit of 2, the sum of 3 does not.
+    wc_rs()
+        .args(["--assert-max-lines", "2"])
+        .args([&a, &b])
+        .assert()
+        .success();
+    wc_rs()
+        .args(["--assert-total-max-lines", "2"])
+        .args([&a, &b])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn assert_limits_need_the_counter_they_gate() {
+    wc_rs()
+        .args(["-w", "--assert-max-bytes", "10"])
+        .write_stdin("x\n")
+        .assert()
+        .failure();
+}
+
+#[test]
+fn check_verifies_a_manifest_and_reports_drift() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let data = dir.path().join("data.txt");
+    std::fs::write(&data, "one two\nthree\n").unwrap();
+    let manifest = dir.path().join("counts.manifest");
+    let row = format!(
+        "wc-rs-manifest 1\ncounters lines words bytes\nmode utf8\n2 3 14 {}\n",
+        data.display()
+    );
+    std::fs::write(&manifest, &row).unwrap();
+    let output = wc_rs().arg("--check").arg(&manifest).output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.trim_end().ends_with(": OK"), "got {stdout:?}");
+    // Drift fails the run and names the counter.
+    std::fs::write(&data, "one two\nthree\nfour\n").unwrap();
+    let output = wc_rs().arg("--check").arg(&manifest).output().unwrap();
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("FAILED"), "got {stdout:?}");
+    assert!(stdout.contains("lines 3, recorded 2"), "got {stdout:?}");
+}
+
+#[test]
+fn check_rejects_file_operands() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let manifest = dir.path().join("counts.manifest");
+    std::fs::write(&manifest, "wc-rs-manifest 1\ncounters lines\n").unwrap();
+    wc_rs()
+        .arg("--check")
+        .arg(&manifest)
+        .arg("extra.txt")
+        .assert()
+        .failure();
+}
+
+#[test]
+fn write_manifest_round_trips_through_check() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let b = dir.path().join("b.txt");
+    let a = dir.path().join("a.txt");
+    std::fs::write(&a, "one\n").unwrap();
+    std::fs::write(&b, "two words\n").unwrap();
+    let manifest = dir.path().join("counts.manifest");
+    // Operands in reverse order; the manifest still sorts by name.
+    wc_rs()
+        .args(["-l", "-w", "--write-manifest"])
+        .arg(&manifest)
+        .args([&b, &a])
+        .assert()
+        .success();
+    let text = std::fs::read_to_string(&manifest).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines[0], "wc-rs-manifest 1", "got {text:?}");
+    assert_eq!(lines[1], "counters lines words", "got {text:?}");
+    assert_eq!(lines[2], "mode utf8", "got {text:?}");
+    assert!(lines[3].ends_with("a.txt"), "got {text:?}");
+    assert!(lines[3].starts_with("1 1 "), "got {text:?}");
+    assert!(lines[4].ends_with("b.txt"), "got {text:?}");
+    wc_rs().arg("--check").arg(&manifest).assert().success();
+}
+
+#[test]
+fn write_manifest_aborts_before_writing_on_unreadable_input() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let manifest = dir.path().join("counts.manifest");
+    wc_rs()
+        .arg("--write-manifest")
+        .arg(&manifest)
+        .arg(dir.path().join("missing.txt"))
+        .assert()
+        .failure();
+    assert!(!manifest.exists(), "a failed run left a manifest behind");
+}
+
+#[test]
+fn watch_reprints_when_the_file_changes() {
+    use std::io::{BufRead, BufReader};
+
+    let dir = TempDir::new().unwrap();
+    let file = write_file(&dir, "notes.txt", b"one\n");
+    let mut child = StdCommand::new(assert_cmd::cargo::cargo_bin("wc-rs"))
+        .args(["--watch", "--watch-format", "append", "-l"])
+        .arg(&file)
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    let first = lines.next().unwrap().unwrap();
+    assert_eq!(first.split_whitespace().next(), Some("1"));
+    fs::OpenOptions::new()
+        .append(true)
+        .open(&file)
+        .unwrap()
+        .write_all(b"two\nthree\n")
+        .unwrap();
+    let second = lines.next().unwrap().unwrap();
+    assert_eq!(second.split_whitespace().next(), Some("3"));
+    child.kill().unwrap();
+    child.wait().unwrap();
+}
+
+#[test]
+fn watch_clear_format_redraws_from_the_top() {
+    use std::io::Read;
+
+    let dir = TempDir::new().unwrap();
+    let file = write_file(&dir, "notes.txt", b"one\n");
+    let mut child = StdCommand::new(assert_cmd::cargo::cargo_bin("wc-rs"))
+        .args(["--watch", "-l"])
+        .arg(&file)
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = child.stdout.take().unwrap();
+    let mut prefix = [0u8; 7];
+    stdout.read_exact(&mut prefix).unwrap();
+    assert_eq!(&prefix, b"\x1b[H\x1b[2J");
+    child.kill().unwrap();
+    child.wait().unwrap();
+}
+
+#[test]
+fn watch_requires_file_operands() {
+    wc_rs()
+        .arg("--watch")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("requires file operands"));
+    wc_rs()
+        .args(["--watch", "-"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("standard input"));
+}
+
+#[cfg(unix)]
+#[test]
+fn serve_answers_count_requests_over_the_socket() {
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::net::UnixStream;
+    use std::time::{Duration, Instant};
+
+    let dir = TempDir::new().unwrap();
+    let file = write_file(&dir, "notes.txt", b"one two\nthree\n");
+    let socket = dir.path().join("wc.sock");
+    let mut child = StdCommand::new(assert_cmd::cargo::cargo_bin("wc-rs"))
+        .args(["serve", "--socket"])
+        .arg(&socket)
+        .spawn()
+        .unwrap();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let stream = loop {
+        match UnixStream::connect(&socket) {
+            Ok(stream) => break stream,
+            Err(_) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(20)),
+            Err(err) => panic!("connecting to the daemon: {err}"),
+        }
+    };
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut writer = stream;
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(line, "wc-rs-serve 1\n");
+
+    writeln!(writer, "count-path {}", file.display()).unwrap();
+    line.clear();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(line, "ok lines 2 words 3 bytes 14\n");
+
+    let data = b"alpha beta\n";
+    writeln!(writer, "count-bytes {}", data.len()).unwrap();
+    writer.write_all(data).unwrap();
+    line.clear();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(line, "ok lines 1 words 2 bytes 11\n");
+
+    writeln!(writer, "count-path {}/missing", dir.path().display()).unwrap();
+    line.clear();
+    reader.read_line(&mut line).unwrap();
+    assert!(line.starts_with("err "), "got {line:?}");
+
+    writeln!(writer, "frobnicate").unwrap();
+    line.clear();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(line, "err unknown request\n");
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn serve_reports_the_counters_selected_on_the_command_line() {
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::net::UnixStream;
+    use std::time::{Duration, Instant};
+
+    let dir = TempDir::new().unwrap();
+    let file = write_file(&dir, "notes.txt", b"one two\n");
+    let socket = dir.path().join("wc.sock");
+    let mut child = StdCommand::new(assert_cmd::cargo::cargo_bin("wc-rs"))
+        .args(["-w", "serve", "--socket"])
+        .arg(&socket)
+        .spawn()
+        .unwrap();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let stream = loop {
+        match UnixStream::connect(&socket) {
+            Ok(stream) => break stream,
+            Err(_) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(20)),
+            Err(err) => panic!("connecting to the daemon: {err}"),
+        }
+    };
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut writer = stream;
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    writeln!(writer, "count-path {}", file.display()).unwrap();
+    line.clear();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(line, "ok words 2\n");
+    child.kill().unwrap();
+    child.wait().unwrap();
+}
+
+#[test]
+fn rpc_counts_paths_and_inline_data() {
+    let dir = TempDir::new().unwrap();
+    let file = write_file(&dir, "notes.txt", b"one two\nthree\n");
+    let requests = format!(
+        concat!(
+            "{{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"count-path\",",
+            "\"params\":{{\"path\":\"{}\"}}}}\n",
+            "{{\"jsonrpc\":\"2.0\",\"id\":\"two\",\"method\":\"count-data\",",
+            "\"params\":{{\"data\":\"alpha beta\\n\"}}}}\n",
+        ),
+        file.display()
+    );
+    wc_rs()
+        .arg("--rpc")
+        .write_stdin(requests)
+        .assert()
+        .success()
+        .stdout(concat!(
+            "{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":{\"lines\":2,\"words\":3,\"bytes\":14}}\n",
+            "{\"jsonrpc\":\"2.0\",\"id\":\"two\",\"result\":{\"lines\":1,\"words\":2,\"bytes\":11}}\n",
+        ));
+}
+
+#[test]
+fn rpc_bad_requests_get_error_replies_not_a_dead_pipe() {
+    let requests = concat!(
+        "this is not json\n",
+        "{\"jsonrpc\":\"2.0\",\"id\":2,\"method\":\"frobnicate\"}\n",
+        "{\"jsonrpc\":\"2.0\",\"id\":3,\"method\":\"count-path\",",
+        "\"params\":{\"path\":\"/nonexistent/wc-rs\"}}\n",
+        "{\"jsonrpc\":\"2.0\",\"id\":4,\"method\":\"count-data\",",
+        "\"params\":{\"data\":\"x\"}}\n",
+    );
+    let assert = wc_rs()
+        .arg("--rpc")
+        .write_stdin(requests)
+        .assert()
+        .success();
+    let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+    let replies: Vec<&str> = stdout.lines().collect();
+    assert_eq!(replies.len(), 4);
+    assert!(replies[0].contains("\"id\":null") && replies[0].contains("-32700"));
+    assert!(replies[1].contains("\"id\":2") && replies[1].contains("-32601"));
+    assert!(replies[2].contains("\"id\":3") && replies[2].contains("-32000"));
+    assert_eq!(
+        replies[3],
+        "{\"jsonrpc\":\"2.0\",\"id\":4,\"result\":{\"lines\":0,\"words\":1,\"bytes\":1}}"
+    );
+}
+
+#[test]
+fn rpc_rejects_file_operands() {
+    wc_rs()
+        .args(["--rpc", "some-file"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("stdin"));
+}
+
+#[test]
+fn quoting_style_escapes_names_in_diagnostics() {
+    let dir = TempDir::new().unwrap();
+    let missing = dir.path().join("no\nsuch file");
+    let assert = wc_rs()
+        .arg("--quoting-style=shell-escape")
+        .arg(&missing)
+        .assert()
+        .failure();
+    let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+    // The newline in the name must not forge a second diagnostic line.
+    assert_eq!(stderr.lines().count(), 1, "stderr: {stderr:?}");
+    assert!(stderr.contains("\\n"), "stderr: {stderr:?}");
+}
+
+#[test]
+fn quoting_style_escapes_names_in_assert_diagnostics() {
+    let dir = TempDir::new().unwrap();
+    let file = dir.path().join("over\nbudget.txt");
+    fs::write(&file, b"one\ntwo\n").unwrap();
+    let assert = wc_rs()
+        .args(["-l", "--assert-max-lines=1", "--quoting-style=c"])
+        .arg(&file)
+        .assert()
+        .failure();
+    let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+    assert_eq!(stderr.lines().count(), 1, "stderr: {stderr:?}");
+    assert!(stderr.contains("\\nbudget.txt"), "stderr: {stderr:?}");
+}
+
+#[test]
+fn with_metadata_joins_file_facts_onto_ndjson_rows() {
+    let dir = TempDir::new().unwrap();
+    let file = write_file(&dir, "notes.txt", b"one two\nthree\n");
+    let assert = wc_rs()
+        .args(["--output", "ndjson", "--with-metadata", "--total", "always"])
+        .arg(&file)
+        .assert()
+        .success();
+    let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+    let rows: Vec<&str> = stdout.lines().collect();
+    assert_eq!(rows.len(), 2);
+    assert!(rows[0].contains("\"size\":14"), "row: {}", rows[0]);
+    assert!(rows[0].contains("\"mtime\":"), "row: {}", rows[0]);
+    assert!(
+        rows[0].contains("\"encoding\":\"utf8\""),
+        "row: {}",
+        rows[0]
+    );
+    assert!(rows[0].contains("\"backend\":\""), "row: {}", rows[0]);
+    // The totals row aggregates files; it carries no single file's facts.
+    assert!(!rows[1].contains("\"size\""), "total: {}", rows[1]);
+}
+
+#[test]
+fn with_metadata_requires_ndjson_output() {
+    wc_rs()
+        .arg("--with-metadata")
+        .write_stdin("x\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("ndjson"));
+}
+
+#[test]
+fn j_auto_reports_its_decision_under_debug() {
+    let dir = TempDir::new().unwrap();
+    let a = write_file(&dir, "a.txt", b"one\n");
+    let b = write_file(&dir, "b.txt", b"two\n");
+    let assert = wc_rs()
+        .args(["-j", "auto", "--debug"])
+        .arg(&a)
+        .arg(&b)
+        .assert()
+        .success();
+    let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+    // Two tiny files land under the single-thread cutoff.
+    assert!(stderr.contains("-j auto chose 1 of"), "stderr: {stderr:?}");
+}
+
+#[test]
+fn saturate_leaves_ordinary_totals_unmarked() {
+    let dir = TempDir::new().unwrap();
+    let a = write_file(&dir, "a.txt", b"one two\n");
+    let b = write_file(&dir, "b.txt", b"three\n");
+    // Saturation only fires at the u64 ceiling, unreachable from files;
+    // the flag must still accumulate exactly and print a plain label.
+    wc_rs()
+        .args(["-lw", "--saturate", "--total", "always"])
+        .arg(&a)
+        .arg(&b)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("3 total"))
+        .stdout(predicate::str::contains("saturated").not());
+    let assert = wc_rs()
+        .args([
+            "-l",
+            "--saturate",
+            "--output",
+            "ndjson",
+            "--total",
+            "always",
+        ])
+        .arg(&a)
+        .arg(&b)
+        .assert()
+        .success();
+    let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+    let total = stdout.lines().last().unwrap();
+    assert!(total.contains("\"total\":true"), "total: {total}");
+    assert!(!total.contains("\"saturated\""), "total: {total}");
+}
+
+#[test]
+fn rpc_deep_nesting_gets_a_parse_error_not_a_crash() {
+    // A request line of nothing but open brackets used to recurse the
+    // parser off the stack and kill the session; it must cost only that
+    // request.
+    let mut requests = "[".repeat(2_000_000);
+    requests.push('\n');
+    requests.push_str("{\"jsonrpc\":\"2.0\",\"id\":2,\"method\":\"count-data\",");
+    requests.push_str("\"params\":{\"data\":\"one two\"}}\n");
+    let assert = wc_rs()
+        .arg("--rpc")
+        .write_stdin(requests)
+        .assert()
+        .success();
+    let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+    let replies: Vec<&str> = stdout.lines().collect();
+    assert_eq!(replies.len(), 2);
+    assert!(replies[0].contains("\"id\":null") && replies[0].contains("-32700"));
+    assert!(replies[1].contains("\"id\":2") && replies[1].contains("\"words\":2"));
+}
@@ -0,0 +1,165 @@
+//! Deterministic corpus generator: produces benchmark/test files of a
+//! requested size and composition so benches, integration tests, and the
+//! fuzz seed directory all draw from the same reproducible inputs.
+//!
+//! The same seed and parameters always yield byte-identical files, so a
+//! bench result or a test failure can be reproduced from its command line
+//! alone. Composition is controlled by four knobs: the ASCII-vs-multibyte
+//! ratio, the whitespace density, the invalid-byte rate, and the mean line
+//! length (lines are drawn from a geometric distribution around the mean).
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::Parser;
+
+#[derive(Debug, Parser)]
+#[command(name = "gen-corpus", about)]
+struct Args {
+    /// Seed for the generator; equal seeds yield identical files.
+    #[arg(long, default_value_t = 0x5eed)]
+    seed: u64,
+
+    /// Size of each generated file in bytes.
+    #[arg(long, default_value_t = 1 << 20)]
+    size: usize,
+
+    /// Number of files to generate (file N perturbs the seed by N).
+    #[arg(long, default_value_t = 1)]
+    count: usize,
+
+    /// Fraction of characters drawn from printable ASCII rather than
+    /// multibyte UTF-8 ranges.
+    #[arg(long, default_value_t = 0.9, value_parser = parse_ratio)]
+    ascii_ratio: f64,
+
+    /// Fraction of characters that are whitespace (space, tab, \r, \v, \f).
+    #[arg(long, default_value_t = 0.15, value_parser = parse_ratio)]
+    whitespace: f64,
+
+    /// Fraction of positions that emit a lone invalid UTF-8 byte.
+    #[arg(long, default_value_t = 0.0, value_parser = parse_ratio)]
+    invalid: f64,
+
+    /// Mean line length in characters.
+    #[arg(long, default_value_t = 60)]
+    mean_line_len: usize,
+
+    /// Output directory; files are named corpus-<seed>-<index>.dat.
+    /// With --count 1 and no directory, the file is written to stdout.
+    #[arg(long)]
+    out: Option<PathBuf>,
+}
+
+fn parse_ratio(s: &str) -> Result<f64, String> {
+    let v: f64 = s.parse().map_err(|e| format!("{e}"))?;
+    if (0.0..=1.0).contains(&v) {
+        Ok(v)
+    } else {
+        Err("ratio must be between 0.0 and 1.0".to_string())
+    }
+}
+
+/// xorshift64*: small, seedable, and good enough for corpus generation.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545f4914f6cdd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n.max(1) as u64) as usize
+    }
+
+    /// Uniform in [0, 1).
+    fn unit(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+const WHITESPACE: [u8; 5] = [b' ', b'\t', b'\r', 0x0b, 0x0c];
+
+/// Well-assigned multibyte ranges: Latin-1 letters, CJK, and an
+/// East-Asian-wide punctuation block, so widths of 1 and 2 both appear.
+const MULTIBYTE: [(u32, u32); 3] = [(0xC0, 0xFF), (0x4E00, 0x9FFF), (0x3001, 0x301F)];
+
+fn generate(rng: &mut Rng, args: &Args) -> Vec<u8> {
+    let mut out = Vec::with_capacity(args.size);
+    let mut line_left = line_length(rng, args.mean_line_len);
+    while out.len() < args.size {
+        if line_left == 0 {
+            out.push(b'\n');
+            line_left = line_length(rng, args.mean_line_len);
+            continue;
+        }
+        line_left -= 1;
+        let roll = rng.unit();
+        if roll < args.invalid {
+            // A lone continuation byte: never decodable, never a word char.
+            out.push(0x80 | rng.next() as u8 & 0x3f);
+        } else if roll < args.invalid + args.whitespace {
+            out.push(WHITESPACE[rng.below(WHITESPACE.len())]);
+        } else if rng.unit() < args.ascii_ratio {
+            out.push(b'!' + rng.below(94) as u8);
+        } else {
+            let (lo, hi) = MULTIBYTE[rng.below(MULTIBYTE.len())];
+            let c = char::from_u32(lo + rng.below((hi - lo + 1) as usize) as u32)
+                .expect("ranges contain no surrogates");
+            let mut buf = [0u8; 4];
+            out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+        }
+    }
+    out.truncate(args.size);
+    out
+}
+
+/// Geometric-ish line length around the mean: short lines are common,
+/// lines a few times the mean occasionally appear.
+fn line_length(rng: &mut Rng, mean: usize) -> usize {
+    if mean == 0 {
+        return 0;
+    }
+    let mut len = 0;
+    while rng.below(mean + 1) != 0 {
+        len += 1;
+    }
+    len
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    match &args.out {
+        None if args.count == 1 => {
+            let mut rng = Rng(args.seed | 1);
+            let data = generate(&mut rng, &args);
+            if std::io::stdout().write_all(&data).is_err() {
+                return ExitCode::FAILURE;
+            }
+        }
+        None => {
+            eprintln!("gen-corpus: --count > 1 requires --out DIR");
+            return ExitCode::FAILURE;
+        }
+        Some(dir) => {
+            if let Err(err) = std::fs::create_dir_all(dir) {
+                eprintln!("gen-corpus: {}: {err}", dir.display());
+                return ExitCode::FAILURE;
+            }
+            for index in 0..args.count {
+                let mut rng = Rng(args.seed.wrapping_add(index as u64) | 1);
+                let data = generate(&mut rng, &args);
+                let path = dir.join(format!("corpus-{:x}-{index}.dat", args.seed));
+                if let Err(err) = std::fs::write(&path, data) {
+                    eprintln!("gen-corpus: {}: {err}", path.display());
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,208 @@
+//! Differential tester: generate random corpora, run both `wc-rs` and the
+//! system `wc` on them under the C and UTF-8 locales, and flag any
+//! divergence in the counts as a finding.
+//!
+//! Raw byte corpora are compared fully under the C locale but only for the
+//! locale-independent counters (lines, bytes) under UTF-8: glibc consults
+//! its own printability tables for unassigned codepoints, which legitimately
+//! differ from the unicode-width tables wc-rs uses. Text corpora are drawn
+//! from well-assigned ranges and compared fully under both locales.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
+
+use clap::Parser;
+
+const FLAGS: [&str; 5] = ["-l", "-w", "-m", "-c", "-L"];
+
+#[derive(Debug, Parser)]
+#[command(name = "wc-diff", about)]
+struct Args {
+    /// Number of corpora to test.
+    #[arg(long, default_value_t = 1000)]
+    iterations: u64,
+
+    /// Seed for the corpus generator.
+    #[arg(long, default_value_t = 0x5eed)]
+    seed: u64,
+
+    /// Maximum corpus size in bytes.
+    #[arg(long, default_value_t = 1 << 16)]
+    max_len: usize,
+
+    /// Path to the wc-rs binary (defaults to a sibling of this binary).
+    #[arg(long)]
+    wc_rs: Option<PathBuf>,
+
+    /// Reference wc binary.
+    #[arg(long, default_value = "wc")]
+    wc: PathBuf,
+
+    /// Directory where diverging corpora are saved.
+    #[arg(long, default_value = "wc-diff-findings")]
+    findings: PathBuf,
+}
+
+/// xorshift64*: small, seedable, and good enough for corpus generation.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545f4914f6cdd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n.max(1) as u64) as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Corpus {
+    /// Arbitrary bytes, including invalid UTF-8.
+    Bytes,
+    /// Valid UTF-8 from well-assigned ranges.
+    Text,
+}
+
+fn generate(rng: &mut Rng, kind: Corpus, max_len: usize) -> Vec<u8> {
+    let len = rng.below(max_len + 1);
+    let mut out = Vec::with_capacity(len);
+    match kind {
+        Corpus::Bytes => {
+            while out.len() < len {
+                out.push(rng.next() as u8);
+            }
+        }
+        Corpus::Text => {
+            let mut buf = [0u8; 4];
+            while out.len() < len {
+                let c = match rng.below(10) {
+                    0 => '\n',
+                    1 => '\t',
+                    2 => ' ',
+                    3 => char::from(b'!' + rng.below(94) as u8),
+                    4 => char::from_u32(0x00c0 + rng.below(0x100) as u32).unwrap(),
+                    5 => char::from_u32(0x4e00 + rng.below(0x1000) as u32).unwrap(),
+                    6 => ['\r', '\x0b', '\x0c'][rng.below(3)],
+                    7 => '\u{2003}',
+                    _ => char::from(b'a' + rng.below(26) as u8),
+                };
+                out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            }
+        }
+    }
+    out
+}
+
+/// Run one `wc` implementation and parse its counts, one flag at a time so
+/// column formatting differences cannot matter.
+fn run(bin: &Path, file: &Path, locale: &str) -> Result<Vec<u64>, String> {
+    let mut counts = Vec::with_capacity(FLAGS.len());
+    for flag in FLAGS {
+        let output = Command::new(bin)
+            .arg(flag)
+            .arg(file)
+            .env("LC_ALL", locale)
+            .env_remove("LANG")
+            .output()
+            .map_err(|e| format!("failed to run {}: {e}", bin.display()))?;
+        if !output.status.success() {
+            return Err(format!(
+                "{} {flag} failed: {}",
+                bin.display(),
+                output.status
+            ));
+        }
+        let text = String::from_utf8_lossy(&output.stdout);
+        let value = text
+            .split_whitespace()
+            .next()
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| format!("unparsable output from {}: {text:?}", bin.display()))?;
+        counts.push(value);
+    }
+    Ok(counts)
+}
+
+fn default_wc_rs() -> PathBuf {
+    std::env::current_exe()
+        .ok()
+        .and_then(|p| Some(p.parent()?.join("wc-rs")))
+        .unwrap_or_else(|| PathBuf::from("wc-rs"))
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let wc_rs = args.wc_rs.clone().unwrap_or_else(default_wc_rs);
+    let mut rng = Rng(args.seed | 1);
+    let dir = tempfile::tempdir().expect("create temp dir");
+    let mut findings = 0u64;
+
+    for i in 0..args.iterations {
+        let kind = if i % 2 == 0 {
+            Corpus::Bytes
+        } else {
+            Corpus::Text
+        };
+        let data = generate(&mut rng, kind, args.max_len);
+        let path = dir.path().join("corpus");
+        std::fs::write(&path, &data).expect("write corpus");
+
+        for locale in ["C", "C.UTF-8"] {
+            let reference = match run(&args.wc, &path, locale) {
+                Ok(counts) => counts,
+                Err(e) => {
+                    eprintln!("wc-diff: skipping iteration {i}: {e}");
+                    continue;
+                }
+            };
+            let ours = match run(&wc_rs, &path, locale) {
+                Ok(counts) => counts,
+                Err(e) => {
+                    eprintln!("wc-diff: {e}");
+                    findings += 1;
+                    continue;
+                }
+            };
+            // Word, char and width counts on arbitrary bytes depend on
+            // glibc's printability tables under UTF-8; compare only the
+            // locale-independent counters there.
+            let compare: &[usize] = if kind == Corpus::Bytes && locale != "C" {
+                &[0, 3]
+            } else {
+                &[0, 1, 2, 3, 4]
+            };
+            let diverged: Vec<String> = compare
+                .iter()
+                .filter(|&&f| reference[f] != ours[f])
+                .map(|&f| format!("{}: wc={} wc-rs={}", FLAGS[f], reference[f], ours[f]))
+                .collect();
+            if !diverged.is_empty() {
+                findings += 1;
+                std::fs::create_dir_all(&args.findings).expect("create findings dir");
+                let saved = args.findings.join(format!("finding-{i}-{locale}.bin"));
+                std::fs::write(&saved, &data).expect("save finding");
+                let mut err = std::io::stderr().lock();
+                let _ = writeln!(
+                    err,
+                    "wc-diff: FINDING at iteration {i} (locale {locale}, {} bytes, saved to {}):\n  {}",
+                    data.len(),
+                    saved.display(),
+                    diverged.join("\n  ")
+                );
+            }
+        }
+    }
+
+    if findings > 0 {
+        eprintln!("wc-diff: {findings} finding(s)");
+        ExitCode::FAILURE
+    } else {
+        println!("wc-diff: no divergence in {} iterations", args.iterations);
+        ExitCode::SUCCESS
+    }
+}